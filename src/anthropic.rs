@@ -0,0 +1,169 @@
+// Winston Anthropic messages API
+// Endpoints with the anthropic flavor take the messages API shape instead of
+// chat completions. System messages move to a top-level system field, the rest
+// are sent as lists of text content blocks, and max_tokens is required so a
+// default is filled in when it is unset. Stop sequences that are only
+// whitespace, like the default newline, are rejected by the API and left out.
+// The reply is a list of content blocks whose text is joined into a single
+// choice.
+
+use serde::{Deserialize, Serialize};
+
+use crate::chat::{ChatRequest, ChatResponse, Choice, Message, Role, Usage};
+use crate::config::Stop;
+
+// max_tokens sent when none is configured
+pub const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+// the body of a messages API request
+#[derive(Debug, Serialize)]
+pub struct MessagesRequest<'a> {
+    pub model: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    pub messages: Vec<MessageParam<'a>>,
+    pub max_tokens: u32,
+    pub temperature: f32,
+    // only sent when it narrows sampling
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop_sequences: Vec<&'a str>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub stream: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MessageParam<'a> {
+    pub role: Role,
+    pub content: Vec<ContentBlock<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ContentBlock<'a> {
+    Text { text: &'a str },
+}
+
+impl<'a> From<&'a ChatRequest> for MessagesRequest<'a> {
+    fn from(request: &'a ChatRequest) -> Self {
+        let (system, messages): (Vec<&Message>, Vec<&Message>) =
+            request.messages.iter().partition(|message| message.role == Role::System);
+        let system: Vec<&str> = system.iter().map(|message| message.content.as_str()).collect();
+        Self {
+            model: &request.model,
+            system: (!system.is_empty()).then(|| system.join("\n\n")),
+            messages: messages
+                .into_iter()
+                .map(|message| MessageParam {
+                    role: message.role,
+                    content: vec![ContentBlock::Text { text: &message.content }],
+                })
+                .collect(),
+            max_tokens: request.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            temperature: request.temperature,
+            top_p: Some(request.top_p).filter(|&top_p| top_p < 1.0),
+            stop_sequences: request
+                .stop
+                .iter()
+                .flat_map(Stop::sequences)
+                .filter(|stop| !stop.trim().is_empty())
+                .collect(),
+            stream: request.stream,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MessagesResponse {
+    pub content: Vec<ResponseBlock>,
+    pub usage: Option<MessagesUsage>,
+}
+
+// a block of the reply, only text blocks carry content winston uses
+#[derive(Debug, Deserialize)]
+pub struct ResponseBlock {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MessagesUsage {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+}
+
+impl From<MessagesResponse> for ChatResponse {
+    fn from(response: MessagesResponse) -> Self {
+        let text: String = response
+            .content
+            .into_iter()
+            .filter(|block| block.kind == "text")
+            .filter_map(|block| block.text)
+            .collect();
+        Self {
+            choices: vec![Choice { message: Message::new(Role::Assistant, text) }],
+            usage: response.usage.map(|usage| Usage {
+                prompt_tokens: usage.input_tokens,
+                completion_tokens: usage.output_tokens,
+                total_tokens: usage.input_tokens + usage.output_tokens,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{Param, WinstonConfigBuilder};
+
+    // test that system messages move to the top level and the rest become
+    // text content blocks
+    #[test]
+    fn messages_request_test() {
+        let config = WinstonConfigBuilder::new()
+            .model("claude-sonnet-4-5".to_string())
+            .system(Param::Value("Be brief.".to_string()))
+            .build()
+            .unwrap();
+        let request = ChatRequest::new(&config, vec![
+            Message::new(Role::User, "hello"),
+            Message::new(Role::Assistant, "Hi."),
+            Message::new(Role::User, "how are you?"),
+        ]);
+        let body = serde_json::to_value(MessagesRequest::from(&request)).unwrap();
+        assert_eq!(body, serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "system": "Be brief.",
+            "messages": [
+                {"role": "user", "content": [{"type": "text", "text": "hello"}]},
+                {"role": "assistant", "content": [{"type": "text", "text": "Hi."}]},
+                {"role": "user", "content": [{"type": "text", "text": "how are you?"}]},
+            ],
+            "max_tokens": DEFAULT_MAX_TOKENS,
+            "temperature": 0.9f32,
+        }));
+    }
+
+    // test that the text blocks of a reply become the content of one choice
+    #[test]
+    fn messages_response_test() {
+        let body = r#"{
+            "id": "msg_01",
+            "type": "message",
+            "role": "assistant",
+            "model": "claude-sonnet-4-5",
+            "content": [
+                {"type": "text", "text": "Doing well, "},
+                {"type": "tool_use", "id": "toolu_01", "name": "noop", "input": {}},
+                {"type": "text", "text": "thanks."}
+            ],
+            "stop_reason": "end_turn",
+            "usage": {"input_tokens": 12, "output_tokens": 5}
+        }"#;
+        let response = ChatResponse::from(serde_json::from_str::<MessagesResponse>(body).unwrap());
+        assert_eq!(response.content(), Some("Doing well, thanks."));
+        assert_eq!(response.usage, Some(Usage { prompt_tokens: 12, completion_tokens: 5, total_tokens: 17 }));
+    }
+}
@@ -0,0 +1,348 @@
+// Winston batch subcommand
+// Batch sends one request per non-empty input line and writes one JSON object
+// per line with either the completion or the error, so a failed prompt doesn't
+// stop the rest of the batch. With --resume each successful request leaves a
+// marker in a state directory, keyed by a hash of the request body, and a
+// resumed batch skips the requests that already have one. Up to
+// max_concurrency prompts are sent at once, and the lines are still written in
+// the order of the prompts, so they line up with the input, or with
+// --unordered as soon as each one finishes. With --fail-fast no more prompts
+// are sent once one fails, the replies to those already in flight are dropped,
+// and the failed line is the last one written.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use serde::Serialize;
+
+use crate::chat::{ChatRequest, Message, Role};
+use crate::client::Client;
+use crate::config::WinstonConfig;
+use super::Result;
+
+// completion markers of a resumable batch
+#[derive(Debug)]
+pub struct StateDir {
+    path: PathBuf,
+}
+
+impl StateDir {
+    pub fn new(path: PathBuf) -> Result<Self> {
+        std::fs::create_dir_all(&path)
+            .map_err(|e| format!("Could not create batch state directory {}: {}", path.display(), e))?;
+        Ok(Self { path })
+    }
+
+    // the default state directory, XDG_CACHE_HOME/winston/batch
+    pub fn default_path() -> Result<PathBuf> {
+        let mut path = dirs::cache_dir().ok_or("Could not find cache directory")?;
+        path.push("winston");
+        path.push("batch");
+        Ok(path)
+    }
+
+    // remove every marker left by earlier runs
+    pub fn clear(&self) -> Result<()> {
+        for entry in std::fs::read_dir(&self.path)? {
+            std::fs::remove_file(entry?.path())?;
+        }
+        Ok(())
+    }
+
+    fn marker(&self, key: &str) -> PathBuf {
+        self.path.join(key)
+    }
+
+    pub fn is_done(&self, key: &str) -> bool {
+        self.marker(key).exists()
+    }
+
+    pub fn mark_done(&self, key: &str) -> Result<()> {
+        std::fs::write(self.marker(key), "")?;
+        Ok(())
+    }
+}
+
+// a stable key for a request, the FNV-1a hash of its serialized body
+pub fn request_key(request: &ChatRequest) -> Result<String> {
+    Ok(hash_key(&serde_json::to_vec(request)?))
+}
+
+// the FNV-1a hash of some bytes as hex
+pub fn hash_key(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+#[derive(Debug, Serialize)]
+struct BatchLine<'a> {
+    prompt: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    completion: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct BatchReport {
+    pub completed: u32,
+    pub failed: u32,
+    pub skipped: u32,
+}
+
+// what became of a single prompt, with the JSON line to write for it
+enum Outcome {
+    Skipped,
+    Completed(String),
+    Failed(String),
+}
+
+// send a prompt unless it completed in an earlier run
+fn send(client: &Client, config: &WinstonConfig, prompt: &str, state: Option<&StateDir>) -> Result<Outcome> {
+    let request = ChatRequest::new(config, vec![Message::new(Role::User, prompt)]);
+    let key = request_key(&request)?;
+    if state.is_some_and(|state| state.is_done(&key)) {
+        return Ok(Outcome::Skipped);
+    }
+
+    let outcome = match client.chat(&request) {
+        Ok(response) => {
+            if let Some(state) = state {
+                state.mark_done(&key)?;
+            }
+            Outcome::Completed(serde_json::to_string(&BatchLine {
+                prompt,
+                completion: Some(response.content().unwrap_or_default()),
+                error: None,
+            })?)
+        }
+        Err(e) => Outcome::Failed(serde_json::to_string(&BatchLine {
+            prompt,
+            completion: None,
+            error: Some(e.to_string()),
+        })?),
+    };
+    Ok(outcome)
+}
+
+// send each prompt from up to max_concurrency threads, writing a JSON line per
+// result to out in the order of the prompts, or in the order they finish when
+// unordered
+pub fn run(
+    client: &Client,
+    config: &WinstonConfig,
+    prompts: &[String],
+    state: Option<&StateDir>,
+    fail_fast: bool,
+    unordered: bool,
+    out: &mut impl Write,
+) -> Result<BatchReport> {
+    let prompts: Vec<&str> = prompts.iter().map(|p| p.trim()).filter(|p| !p.is_empty()).collect();
+    let next = AtomicUsize::new(0);
+    let cancelled = AtomicBool::new(false);
+    let mut report = BatchReport::default();
+
+    thread::scope(|scope| -> Result<()> {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..(config.max_concurrency as usize).min(prompts.len()) {
+            let (sender, prompts, next, cancelled) = (sender.clone(), &prompts, &next, &cancelled);
+            scope.spawn(move || loop {
+                if cancelled.load(Ordering::SeqCst) {
+                    break;
+                }
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(prompt) = prompts.get(index) else {
+                    break;
+                };
+                // errors can't be sent between threads, so only their message is
+                let outcome = send(client, config, prompt, state).map_err(|e| e.to_string());
+                if fail_fast && matches!(outcome, Ok(Outcome::Failed(_))) {
+                    cancelled.store(true, Ordering::SeqCst);
+                }
+                if sender.send((index, outcome)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        // hold back results that finish early until those before them are written
+        let mut finished = BTreeMap::new();
+        let mut written = 0;
+        'receive: for (index, outcome) in receiver {
+            finished.insert(index, outcome);
+            if unordered {
+                written = index;
+            }
+            while let Some(outcome) = finished.remove(&written) {
+                written += 1;
+                match outcome? {
+                    Outcome::Skipped => report.skipped += 1,
+                    Outcome::Completed(line) => {
+                        report.completed += 1;
+                        writeln!(out, "{}", line)?;
+                    }
+                    Outcome::Failed(line) => {
+                        report.failed += 1;
+                        writeln!(out, "{}", line)?;
+                        // dropping the receiver stops the workers
+                        if fail_fast {
+                            break 'receive;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::WinstonConfigBuilder;
+
+    // test that a resumed batch skips the prompts that already completed
+    #[test]
+    fn resume_skips_completed_test() {
+        let mut server = mockito::Server::new();
+        let first = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::Regex("first|second".to_string()))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"role":"assistant","content":"done"}}]}"#)
+            .expect(2)
+            .create();
+        let third = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::Regex("third".to_string()))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"role":"assistant","content":"done"}}]}"#)
+            .expect(1)
+            .create();
+
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .model("gpt-4o".to_string())
+            .build()
+            .unwrap();
+        let client = Client::from_config(&config).unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let state = StateDir::new(temp_dir.path().to_path_buf()).unwrap();
+
+        let prompts = ["first".to_string(), "second".to_string()];
+        let mut out = Vec::new();
+        let report = run(&client, &config, &prompts, Some(&state), false, false, &mut out).unwrap();
+        assert_eq!(report.completed, 2);
+
+        let prompts = ["first".to_string(), "second".to_string(), "third".to_string()];
+        let mut out = Vec::new();
+        let report = run(&client, &config, &prompts, Some(&state), false, false, &mut out).unwrap();
+        assert_eq!(report, BatchReport { completed: 1, failed: 0, skipped: 2 });
+        assert_eq!(String::from_utf8(out).unwrap(), "{\"prompt\":\"third\",\"completion\":\"done\"}\n");
+
+        first.assert();
+        third.assert();
+
+        state.clear().unwrap();
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+    }
+
+    // test that with several prompts in flight the lines are written in the
+    // order of the prompts, unless unordered
+    #[test]
+    fn ordered_output_test() {
+        let mut server = mockito::Server::new();
+        let reply = |content: &str| format!(r#"{{"choices":[{{"message":{{"role":"assistant","content":"{}"}}}}]}}"#, content);
+        // the first prompt is answered last
+        let slow_reply = reply("one");
+        let slow = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::Regex("first".to_string()))
+            .with_status(200)
+            .with_chunked_body(move |w| {
+                thread::sleep(std::time::Duration::from_millis(300));
+                w.write_all(slow_reply.as_bytes())
+            })
+            .expect(2)
+            .create();
+        let fast = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::Regex("second|third".to_string()))
+            .with_status(200)
+            .with_body(reply("done"))
+            .expect(4)
+            .create();
+
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .model("gpt-4o".to_string())
+            .max_concurrency(3)
+            .build()
+            .unwrap();
+        let client = Client::from_config(&config).unwrap();
+        let prompts = ["first", "second", "third"].map(String::from);
+        let written = |unordered: bool| {
+            let mut out = Vec::new();
+            run(&client, &config, &prompts, None, false, unordered, &mut out).unwrap();
+            let out = String::from_utf8(out).unwrap();
+            out.lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["prompt"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(written(false), ["first", "second", "third"]);
+        assert_eq!(written(true).last().unwrap(), "first");
+        slow.assert();
+        fast.assert();
+    }
+
+    // test that --fail-fast stops sending prompts after the first failure
+    #[test]
+    fn fail_fast_test() {
+        let mut server = mockito::Server::new();
+        let ok = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::Regex("first|third|fourth".to_string()))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"role":"assistant","content":"done"}}]}"#)
+            .expect(1)
+            .create();
+        let unauthorized = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::Regex("second".to_string()))
+            .with_status(401)
+            .with_body(r#"{"error":{"message":"Incorrect API key provided"}}"#)
+            .expect(1)
+            .create();
+
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .model("gpt-4o".to_string())
+            .max_concurrency(1)
+            .build()
+            .unwrap();
+        let client = Client::from_config(&config).unwrap();
+        let prompts = ["first", "second", "third", "fourth"].map(String::from);
+        let mut out = Vec::new();
+        let report = run(&client, &config, &prompts, None, true, false, &mut out).unwrap();
+
+        ok.assert();
+        unauthorized.assert();
+        assert_eq!(report, BatchReport { completed: 1, failed: 1, skipped: 0 });
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().count(), 2);
+        assert!(out.lines().last().unwrap().starts_with(r#"{"prompt":"second","error":"#));
+    }
+}
@@ -0,0 +1,178 @@
+// Winston bench subcommand
+// Bench fires a number of identical chat requests at the endpoint from a pool
+// of worker threads sharing one client, so connections are pooled across
+// requests. It reports the error rate, total throughput and latency
+// percentiles of the successful requests. Authentication failures will never
+// succeed on retry, so the run is stopped early once several arrive in a row.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::chat::ChatRequest;
+use crate::client::{ApiError, Client};
+use crate::ping::millis;
+
+// consecutive authentication failures after which the run is stopped
+pub const MAX_AUTH_FAILURES: u32 = 3;
+
+#[derive(Debug)]
+pub struct BenchReport {
+    pub sent: u32,
+    pub failed: u32,
+    // latencies of the successful requests, sorted ascending
+    pub latencies: Vec<Duration>,
+    pub elapsed: Duration,
+    pub aborted: bool,
+}
+
+// nearest-rank percentile of a sorted slice
+pub fn percentile(sorted: &[Duration], p: f64) -> Option<Duration> {
+    let last = sorted.len().checked_sub(1)?;
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.saturating_sub(1).min(last)])
+}
+
+// send requests copies of request using concurrency worker threads
+pub fn run(client: &Client, request: &ChatRequest, requests: u32, concurrency: u32) -> BenchReport {
+    let next = AtomicU32::new(0);
+    let auth_failures = AtomicU32::new(0);
+    let results = Mutex::new(Vec::new());
+
+    let start = Instant::now();
+    thread::scope(|scope| {
+        for _ in 0..concurrency.max(1) {
+            scope.spawn(|| {
+                while auth_failures.load(Ordering::SeqCst) < MAX_AUTH_FAILURES
+                    && next.fetch_add(1, Ordering::SeqCst) < requests
+                {
+                    let sent = Instant::now();
+                    let result = client.chat(request);
+                    let latency = sent.elapsed();
+                    match result {
+                        Ok(_) => {
+                            auth_failures.store(0, Ordering::SeqCst);
+                            results.lock().unwrap().push(Some(latency));
+                        }
+                        Err(err) => {
+                            if err.downcast_ref::<ApiError>().is_some_and(ApiError::is_auth) {
+                                auth_failures.fetch_add(1, Ordering::SeqCst);
+                            }
+                            results.lock().unwrap().push(None);
+                        }
+                    }
+                }
+            });
+        }
+    });
+    let elapsed = start.elapsed();
+
+    let results = results.into_inner().unwrap();
+    let mut latencies: Vec<Duration> = results.iter().flatten().copied().collect();
+    latencies.sort();
+    BenchReport {
+        sent: results.len() as u32,
+        failed: (results.len() - latencies.len()) as u32,
+        latencies,
+        elapsed,
+        aborted: auth_failures.load(Ordering::SeqCst) >= MAX_AUTH_FAILURES,
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let error_rate = if self.sent == 0 {
+            0.0
+        } else {
+            self.failed as f64 / self.sent as f64 * 100.0
+        };
+        writeln!(f, "requests: {} sent, {} failed ({:.1}% errors)", self.sent, self.failed, error_rate)?;
+        writeln!(f, "throughput: {:.2} req/s", self.sent as f64 / self.elapsed.as_secs_f64())?;
+        if let (Some(p50), Some(p95), Some(p99)) = (
+            percentile(&self.latencies, 50.0),
+            percentile(&self.latencies, 95.0),
+            percentile(&self.latencies, 99.0),
+        ) {
+            write!(f, "latency p50/p95/p99 = {:.1}/{:.1}/{:.1} ms", millis(p50), millis(p95), millis(p99))?;
+        }
+        if self.aborted {
+            write!(f, "\naborted after {} consecutive authentication failures", MAX_AUTH_FAILURES)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chat::{Message, Role};
+    use crate::config::WinstonConfigBuilder;
+
+    const CHAT_RESPONSE: &str = r#"{"choices":[{"message":{"role":"assistant","content":"pong"}}]}"#;
+
+    fn request() -> ChatRequest {
+        let config = WinstonConfigBuilder::new().model("gpt-4o".to_string()).build().unwrap();
+        ChatRequest::new(&config, vec![Message::new(Role::User, "ping")])
+    }
+
+    // test that percentiles use the nearest rank
+    #[test]
+    fn percentile_test() {
+        let sorted: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&sorted, 50.0), Some(Duration::from_millis(50)));
+        assert_eq!(percentile(&sorted, 99.0), Some(Duration::from_millis(99)));
+        assert_eq!(percentile(&sorted[..1], 95.0), Some(Duration::from_millis(1)));
+        assert_eq!(percentile(&[], 50.0), None);
+    }
+
+    // test that every request is sent across the worker threads
+    #[test]
+    fn bench_mock_server_test() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_body(CHAT_RESPONSE)
+            .expect(6)
+            .create();
+
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .build()
+            .unwrap();
+        let client = Client::from_config(&config).unwrap();
+        let report = run(&client, &request(), 6, 3);
+
+        mock.assert();
+        assert_eq!(report.sent, 6);
+        assert_eq!(report.failed, 0);
+        assert_eq!(report.latencies.len(), 6);
+        assert!(!report.aborted);
+    }
+
+    // test that the run stops after repeated authentication failures
+    #[test]
+    fn bench_auth_failure_test() {
+        let mut server = mockito::Server::new();
+        let _mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(401)
+            .with_body(r#"{"error":{"message":"Incorrect API key provided"}}"#)
+            .create();
+
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("bad-key".to_string())
+            .build()
+            .unwrap();
+        let client = Client::from_config(&config).unwrap();
+        let report = run(&client, &request(), 20, 1);
+
+        assert_eq!(report.sent, MAX_AUTH_FAILURES);
+        assert_eq!(report.failed, MAX_AUTH_FAILURES);
+        assert!(report.aborted);
+    }
+}
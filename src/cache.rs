@@ -0,0 +1,177 @@
+// Winston response cache
+// With --cache a reply is kept on disk, keyed by a hash of the request body
+// together with the endpoint, flavor and API it was sent to, and an identical
+// request to the same place later gets the kept reply instead of being sent
+// again. Only complete, non-streamed replies are cached, and not those from a
+// fallback model, which answer another request. Entries never expire
+// on their own, the cache subcommand reports how much space they take and
+// clears them, optionally only those older than a given age.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::batch::hash_key;
+use crate::chat::{ChatRequest, ChatResponse};
+use crate::config::WinstonConfig;
+use super::Result;
+
+// the cache directory, XDG_CACHE_HOME/winston/responses
+pub fn cache_dir() -> Result<PathBuf> {
+    let mut dir = dirs::cache_dir().ok_or("Could not find cache directory")?;
+    dir.push("winston");
+    dir.push("responses");
+    Ok(dir)
+}
+
+// the same body sent to another endpoint, flavor or API gets its own entry
+fn entry(dir: &Path, config: &WinstonConfig, request: &ChatRequest) -> Result<PathBuf> {
+    let mut key = format!("{}\n{:?}\n{:?}\n", config.api_endpoint, config.flavor, request.api).into_bytes();
+    key.extend(serde_json::to_vec(request)?);
+    Ok(dir.join(format!("{}.json", hash_key(&key))))
+}
+
+// the kept reply to a request, if there is one
+pub fn get(dir: &Path, config: &WinstonConfig, request: &ChatRequest) -> Result<Option<ChatResponse>> {
+    let fp = entry(dir, config, request)?;
+    if !fp.exists() {
+        return Ok(None);
+    }
+    let body = std::fs::read(&fp).map_err(|e| format!("Could not read cache entry {}: {}", fp.display(), e))?;
+    // an unreadable entry is a miss, it is replaced by the next reply
+    Ok(serde_json::from_slice(&body).ok())
+}
+
+// keep the reply to a request
+pub fn put(dir: &Path, config: &WinstonConfig, request: &ChatRequest, response: &ChatResponse) -> Result<()> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Could not create cache directory {}: {}", dir.display(), e))?;
+    std::fs::write(entry(dir, config, request)?, serde_json::to_vec(response)?)?;
+    Ok(())
+}
+
+// the entries in the cache with their sizes and ages
+fn entries(dir: &Path) -> Result<Vec<(PathBuf, u64, SystemTime)>> {
+    let mut entries = Vec::new();
+    let read_dir = match std::fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(entries),
+        Err(e) => return Err(format!("Could not read cache directory {}: {}", dir.display(), e).into()),
+    };
+    for item in read_dir {
+        let path = item?.path();
+        if path.extension().is_some_and(|extension| extension == "json") {
+            let metadata = std::fs::metadata(&path)?;
+            entries.push((path, metadata.len(), metadata.modified()?));
+        }
+    }
+    Ok(entries)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CacheInfo {
+    pub dir: PathBuf,
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+impl CacheInfo {
+    pub fn new(dir: &Path) -> Result<Self> {
+        let entries = entries(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            entries: entries.len(),
+            bytes: entries.iter().map(|(_, bytes, _)| bytes).sum(),
+        })
+    }
+}
+
+impl fmt::Display for CacheInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "directory: {}", self.dir.display())?;
+        writeln!(f, "entries: {}", self.entries)?;
+        write!(f, "size: {} bytes", self.bytes)
+    }
+}
+
+// remove the entries, only those last written more than older_than before now
+// when given, returning how many were removed
+pub fn clear(dir: &Path, older_than: Option<Duration>, now: SystemTime) -> Result<usize> {
+    let mut removed = 0;
+    for (path, _, modified) in entries(dir)? {
+        let old = older_than.is_none_or(|age| now.duration_since(modified).is_ok_and(|elapsed| elapsed > age));
+        if old {
+            std::fs::remove_file(&path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // test that the entries and their total size are reported
+    #[test]
+    fn cache_info_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let missing = temp_dir.path().join("missing");
+        assert_eq!(CacheInfo::new(&missing).unwrap().entries, 0);
+
+        std::fs::write(temp_dir.path().join("a.json"), "x".repeat(100)).unwrap();
+        std::fs::write(temp_dir.path().join("b.json"), "x".repeat(28)).unwrap();
+        std::fs::write(temp_dir.path().join("notes.txt"), "not an entry").unwrap();
+        let info = CacheInfo::new(temp_dir.path()).unwrap();
+        assert_eq!(info.entries, 2);
+        assert_eq!(info.bytes, 128);
+        assert!(info.to_string().ends_with("entries: 2\nsize: 128 bytes"));
+    }
+
+    // test that only entries older than the given age are pruned
+    #[test]
+    fn clear_older_than_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        let days = |days: u64| Duration::from_secs(days * 24 * 60 * 60);
+        for (name, age) in [("new", days(1)), ("week", days(8)), ("month", days(31))] {
+            let fp = temp_dir.path().join(format!("{}.json", name));
+            std::fs::write(&fp, "{}").unwrap();
+            std::fs::File::options().write(true).open(&fp).unwrap().set_modified(now - age).unwrap();
+        }
+
+        assert_eq!(clear(temp_dir.path(), Some(days(7)), now).unwrap(), 2);
+        assert!(temp_dir.path().join("new.json").exists());
+        assert_eq!(clear(temp_dir.path(), None, now).unwrap(), 1);
+        assert_eq!(CacheInfo::new(temp_dir.path()).unwrap().entries, 0);
+    }
+
+    // test that a reply is only reused for the endpoint, flavor and API it
+    // came from
+    #[test]
+    fn cache_key_scope_test() {
+        use crate::chat::{Message, Role};
+        use crate::config::{ApiFlavor, ApiKind, WinstonConfigBuilder};
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = WinstonConfigBuilder::new().api_endpoint("https://one.example".to_string()).build().unwrap();
+        let request = ChatRequest::new(&config, vec![Message::new(Role::User, "hello")]);
+        let response: ChatResponse = serde_json::from_str(
+            r#"{"choices":[{"index":0,"message":{"role":"assistant","content":"hi"},"finish_reason":"stop"}]}"#,
+        )
+        .unwrap();
+        put(temp_dir.path(), &config, &request, &response).unwrap();
+        assert!(get(temp_dir.path(), &config, &request).unwrap().is_some());
+
+        let other = WinstonConfigBuilder::new().api_endpoint("https://two.example".to_string()).build().unwrap();
+        assert!(get(temp_dir.path(), &other, &request).unwrap().is_none());
+        let other = WinstonConfigBuilder::new()
+            .api_endpoint("https://one.example".to_string())
+            .flavor(ApiFlavor::Anthropic)
+            .build()
+            .unwrap();
+        assert!(get(temp_dir.path(), &other, &request).unwrap().is_none());
+        let mut completion = request.clone();
+        completion.api = ApiKind::Completions;
+        assert!(get(temp_dir.path(), &config, &completion).unwrap().is_none());
+    }
+}
@@ -0,0 +1,995 @@
+// Winston chat completion types
+// These are the request and response bodies of the chat completions endpoint.
+// Optional parameters are left out of the serialized request when they are
+// unset so that the server applies its own behavior instead of a default.
+// Legacy models only accept the completions endpoint, which takes a single
+// prompt and returns text, so their requests and responses are converted to
+// and from that shape when sent.
+// Files uploaded beforehand can be referenced with --file-id. A message with
+// file references is sent with its content as a list of parts, the text
+// followed by a file part per id. Only the chat API of the openai and azure
+// flavors takes file parts, the anthropic flavor and the completions API don't.
+
+use std::fmt;
+use std::path::Path;
+
+use encoding_rs::Encoding;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::config::{ApiFlavor, ApiKind, Stop, WinstonConfig};
+use crate::error::WinstonError;
+use crate::lang;
+use crate::schema::ResponseFormat;
+use super::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+    // the result of a tool call, sent back to the model
+    Tool,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::Tool => "tool",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(from = "WireMessage", into = "WireMessage")]
+pub struct Message {
+    pub role: Role,
+    // null when the model refuses
+    pub content: String,
+    pub refusal: Option<String>,
+    // the tools the model asked to call in an assistant message
+    pub tool_calls: Vec<ToolCall>,
+    // the call a tool message answers
+    pub tool_call_id: Option<String>,
+    // ids of uploaded files given to the model along with the content
+    pub file_ids: Vec<String>,
+}
+
+// a message as sent. Content is a string, or a list of parts when files are
+// referenced.
+#[derive(Debug, Deserialize, Serialize)]
+struct WireMessage {
+    role: Role,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    content: WireContent,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    refusal: Option<String>,
+    #[serde(default, deserialize_with = "null_as_empty", skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<ToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+enum WireContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+impl Default for WireContent {
+    fn default() -> Self {
+        WireContent::Text(String::new())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentPart {
+    Text { text: String },
+    File { file: FileRef },
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct FileRef {
+    file_id: String,
+}
+
+impl From<WireMessage> for Message {
+    fn from(wire: WireMessage) -> Self {
+        let (content, file_ids) = match wire.content {
+            WireContent::Text(text) => (text, Vec::new()),
+            WireContent::Parts(parts) => {
+                let mut content = String::new();
+                let mut file_ids = Vec::new();
+                for part in parts {
+                    match part {
+                        ContentPart::Text { text } => content.push_str(&text),
+                        ContentPart::File { file } => file_ids.push(file.file_id),
+                    }
+                }
+                (content, file_ids)
+            }
+        };
+        Self {
+            role: wire.role,
+            content,
+            refusal: wire.refusal,
+            tool_calls: wire.tool_calls,
+            tool_call_id: wire.tool_call_id,
+            file_ids,
+        }
+    }
+}
+
+impl From<Message> for WireMessage {
+    fn from(message: Message) -> Self {
+        let content = match message.file_ids.is_empty() {
+            true => WireContent::Text(message.content),
+            false => {
+                let mut parts = vec![ContentPart::Text { text: message.content }];
+                parts.extend(message.file_ids.into_iter().map(|file_id| ContentPart::File { file: FileRef { file_id } }));
+                WireContent::Parts(parts)
+            }
+        };
+        Self {
+            role: message.role,
+            content,
+            refusal: message.refusal,
+            tool_calls: message.tool_calls,
+            tool_call_id: message.tool_call_id,
+        }
+    }
+}
+
+impl Message {
+    pub fn new(role: Role, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+            refusal: None,
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+            file_ids: Vec::new(),
+        }
+    }
+
+    // the result of a tool call
+    pub fn tool_result(call: &ToolCall, content: impl Into<String>) -> Self {
+        Self {
+            tool_call_id: Some(call.id.clone()),
+            ..Self::new(Role::Tool, content)
+        }
+    }
+}
+
+// a call the model asked for, to a function from the tools sent
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type", default = "function_type")]
+    pub kind: String,
+    pub function: FunctionCall,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct FunctionCall {
+    pub name: String,
+    // the arguments as a JSON object in a string, as the model wrote them
+    pub arguments: String,
+}
+
+fn function_type() -> String {
+    "function".to_string()
+}
+
+fn null_as_empty<'de, D: Deserializer<'de>, T: Deserialize<'de> + Default>(deserializer: D) -> std::result::Result<T, D::Error> {
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+// load a conversation from a JSON array of {role, content} messages, sent as
+// is instead of building one from the prompt
+pub fn load_messages(fp: &Path) -> Result<Vec<Message>> {
+    let messages = std::fs::read_to_string(fp)
+        .map_err(|e| format!("Could not read messages file {}: {}", fp.display(), e))?;
+    let messages = serde_json::from_str(&messages)
+        .map_err(|e| format!("Invalid messages file {}: {}", fp.display(), e))?;
+    Ok(messages)
+}
+
+// load tool definitions from a JSON array, passed to the model as they are
+pub fn load_tools(fp: &Path) -> Result<Vec<serde_json::Value>> {
+    let tools = std::fs::read_to_string(fp)
+        .map_err(|e| format!("Could not read tools file {}: {}", fp.display(), e))?;
+    let tools = serde_json::from_str(&tools)
+        .map_err(|e| format!("Invalid tools file {}: {}", fp.display(), e))?;
+    Ok(tools)
+}
+
+// append the contents of a suffix file to the last user message, separated by
+// a blank line, for closing instructions kept apart from the prompt
+pub fn append_suffix_file(messages: &mut [Message], fp: &Path, encoding: &'static Encoding) -> Result<()> {
+    let suffix = crate::input::read_file(fp, encoding)
+        .map_err(|e| format!("Could not read prompt suffix file: {}", e))?;
+    let message = messages
+        .iter_mut()
+        .rev()
+        .find(|message| message.role == Role::User)
+        .ok_or("There is no user message to append the prompt suffix file to")?;
+    let suffix = suffix.trim_end();
+    if message.content.trim().is_empty() {
+        message.content = suffix.to_string();
+    } else {
+        message.content = format!("{}\n\n{}", message.content.trim_end(), suffix);
+    }
+    Ok(())
+}
+
+// whether requests can reference uploaded files
+pub fn supports_files(flavor: ApiFlavor, api: ApiKind) -> bool {
+    matches!(flavor, ApiFlavor::OpenAi | ApiFlavor::Azure) && api == ApiKind::Chat
+}
+
+// reject a conversation with nothing to respond to. A prompt may be blank as
+// long as a system message or an assistant prefill gives the model content.
+pub fn check_prompt(messages: &[Message]) -> std::result::Result<(), WinstonError> {
+    match messages.iter().all(|message| message.content.trim().is_empty()) {
+        true => Err(WinstonError::EmptyPrompt),
+        false => Ok(()),
+    }
+}
+
+// the refusal in a reply, either the structured refusal field of supporting
+// models or the content when it contains pattern, ignoring case
+pub fn refusal(content: &str, refusal: Option<&str>, pattern: Option<&str>) -> Option<String> {
+    if let Some(refusal) = refusal {
+        return Some(refusal.to_string());
+    }
+    let pattern = pattern?.to_lowercase();
+    content.to_lowercase().contains(&pattern).then(|| content.to_string())
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ChatRequest {
+    #[serde(skip)]
+    pub api: ApiKind,
+    pub model: String,
+    pub messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    pub temperature: f32,
+    pub top_p: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Stop>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<String>,
+    // sampling parameters only local servers accept
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat_penalty: Option<f32>,
+    // number of choices to generate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<serde_json::Value>>,
+    // only meaningful alongside tools, false has the model call them one at a time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parallel_tool_calls: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StreamOptions {
+    pub include_usage: bool,
+}
+
+impl ChatRequest {
+    // build a request for the given messages from the resolved configuration,
+    // starting with the configured system message unless there already is one.
+    // The language directive is added to the end of the system message.
+    pub fn new(config: &WinstonConfig, mut messages: Vec<Message>) -> Self {
+        if let Some(system) = &config.system {
+            if messages.first().is_none_or(|message| message.role != Role::System) {
+                messages.insert(0, Message::new(Role::System, system.clone()));
+            }
+        }
+        if let Some(directive) = config.lang.as_deref().and_then(|code| lang::directive(code).ok()) {
+            match messages.first_mut() {
+                Some(first) if first.role == Role::System => first.content = lang::compose(Some(&first.content), &directive),
+                _ => messages.insert(0, Message::new(Role::System, directive)),
+            }
+        }
+        Self {
+            api: config.api.unwrap_or_else(|| api_for_model(&config.model)),
+            model: config.model.clone(),
+            messages,
+            max_tokens: config.max_tokens,
+            temperature: config.temperature,
+            top_p: config.top_p,
+            frequency_penalty: config.frequency_penalty,
+            presence_penalty: config.presence_penalty,
+            stop: config.stop.clone(),
+            reasoning_effort: config.reasoning_effort.clone(),
+            min_p: config.min_p,
+            top_k: config.top_k,
+            repeat_penalty: config.repeat_penalty,
+            n: None,
+            stream: false,
+            stream_options: None,
+            response_format: None,
+            tools: None,
+            parallel_tool_calls: None,
+        }
+    }
+
+    // reference uploaded files from the last user message
+    pub fn attach_files(&mut self, file_ids: &[String]) {
+        if let Some(message) = self.messages.iter_mut().rev().find(|message| message.role == Role::User) {
+            message.file_ids.extend(file_ids.iter().cloned());
+        }
+    }
+
+    // stream the response, asking for a final usage chunk when include_usage
+    // is set since streamed responses otherwise carry no usage
+    pub fn streaming(mut self, include_usage: bool) -> Self {
+        self.stream = true;
+        self.stream_options = include_usage.then_some(StreamOptions { include_usage });
+        self
+    }
+}
+
+// instructions for the edit subcommand
+pub const EDIT_PROMPT: &str = "Apply the instruction to the text that follows it. Reply with only the edited text, without comments or code fences.";
+
+// a request to edit text following an instruction
+pub fn edit_request(config: &WinstonConfig, instruction: &str, text: &str) -> ChatRequest {
+    ChatRequest::new(config, vec![
+        Message::new(Role::System, EDIT_PROMPT),
+        Message::new(Role::User, format!("{}\n\n{}", instruction, text)),
+    ])
+}
+
+// the messages sent, each labelled with its role, followed by the label of the
+// completion printed after them
+pub fn echo_prompt(messages: &[Message], assistant_label: Option<&str>) -> String {
+    let label = |role: Role| match (role, assistant_label) {
+        (Role::Assistant, Some(label)) => label.to_string(),
+        (role, _) => role.to_string(),
+    };
+    let mut echo = String::new();
+    for message in messages {
+        echo.push_str(&format!("{}: {}\n", label(message.role), message.content));
+    }
+    echo.push_str(&format!("{}: ", label(Role::Assistant)));
+    echo
+}
+
+// the completion as printed, without surrounding whitespace when trim is set
+pub fn output_text(content: &str, trim: bool) -> &str {
+    if trim {
+        content.trim()
+    } else {
+        content
+    }
+}
+
+// the first line of the completion as printed with --first-line. With trim
+// that is the first line with text on it, without its surrounding whitespace.
+pub fn first_line(content: &str, trim: bool) -> &str {
+    let line = output_text(content, trim).lines().next().unwrap_or_default();
+    output_text(line, trim)
+}
+
+// whether a model is known to accept reasoning_effort. The parameter is passed
+// through regardless so that new models work, but other models may reject it.
+pub fn supports_reasoning(model: &str) -> bool {
+    ["o1", "o3", "o4", "gpt-5"].iter().any(|prefix| model.starts_with(prefix))
+}
+
+// the request shape a model is expected to use when none was configured
+pub fn api_for_model(model: &str) -> ApiKind {
+    let legacy = ["davinci", "curie", "babbage", "ada", "text-", "code-"]
+        .iter()
+        .any(|prefix| model.starts_with(prefix));
+    if legacy || model.ends_with("-instruct") {
+        ApiKind::Completions
+    } else {
+        ApiKind::Chat
+    }
+}
+
+// the body of a legacy completions request
+#[derive(Debug, Serialize)]
+pub struct CompletionRequest<'a> {
+    pub model: &'a str,
+    pub prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    pub temperature: f32,
+    pub top_p: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<&'a Stop>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<&'a StreamOptions>,
+}
+
+impl<'a> From<&'a ChatRequest> for CompletionRequest<'a> {
+    // the messages are joined into a single prompt
+    fn from(request: &'a ChatRequest) -> Self {
+        let prompt: Vec<&str> = request.messages.iter().map(|m| m.content.as_str()).collect();
+        Self {
+            model: &request.model,
+            prompt: prompt.join("\n\n"),
+            max_tokens: request.max_tokens,
+            temperature: request.temperature,
+            top_p: request.top_p,
+            frequency_penalty: request.frequency_penalty,
+            presence_penalty: request.presence_penalty,
+            stop: request.stop.as_ref(),
+            min_p: request.min_p,
+            top_k: request.top_k,
+            repeat_penalty: request.repeat_penalty,
+            n: request.n,
+            stream: request.stream,
+            stream_options: request.stream_options.as_ref(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompletionResponse {
+    pub choices: Vec<CompletionChoice>,
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompletionChoice {
+    pub text: String,
+}
+
+impl From<CompletionResponse> for ChatResponse {
+    fn from(response: CompletionResponse) -> Self {
+        Self {
+            choices: response
+                .choices
+                .into_iter()
+                .map(|choice| Choice { message: Message::new(Role::Assistant, choice.text) })
+                .collect(),
+            usage: response.usage,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ChatResponse {
+    pub choices: Vec<Choice>,
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Choice {
+    pub message: Message,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Usage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
+}
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "usage: {} prompt + {} completion = {} tokens",
+            self.prompt_tokens, self.completion_tokens, self.total_tokens
+        )
+    }
+}
+
+impl std::ops::Add for Usage {
+    type Output = Usage;
+
+    fn add(self, other: Usage) -> Usage {
+        Usage {
+            prompt_tokens: self.prompt_tokens + other.prompt_tokens,
+            completion_tokens: self.completion_tokens + other.completion_tokens,
+            total_tokens: self.total_tokens + other.total_tokens,
+        }
+    }
+}
+
+// how to pick one completion when several choices were requested
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Select {
+    Longest,
+    Shortest,
+    // the first choice that isn't empty
+    First,
+}
+
+impl ChatResponse {
+    // the content of the first choice
+    pub fn content(&self) -> Option<&str> {
+        self.choices.first().map(|choice| choice.message.content.as_str())
+    }
+
+    // the content of every choice
+    pub fn contents(&self) -> impl DoubleEndedIterator<Item = &str> {
+        self.choices.iter().map(|choice| choice.message.content.as_str())
+    }
+
+    // the content of the choice picked by select, ignoring empty choices.
+    // Ties go to the earlier choice.
+    pub fn select(&self, select: Select) -> Option<&str> {
+        let mut contents = self.contents().filter(|content| !content.trim().is_empty());
+        let len = |content: &&str| content.trim().chars().count();
+        match select {
+            Select::Longest => contents.rev().max_by_key(len),
+            Select::Shortest => contents.min_by_key(len),
+            Select::First => contents.next(),
+        }
+    }
+
+    // the structured refusal of the first choice
+    pub fn refusal(&self) -> Option<&str> {
+        self.choices.first().and_then(|choice| choice.message.refusal.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{Param, WinstonConfigBuilder};
+
+    // test that disabled parameters don't appear in the serialized body
+    #[test]
+    fn omitted_parameters_test() {
+        let config = WinstonConfigBuilder::new()
+            .frequency_penalty(Param::Disabled)
+            .presence_penalty(Param::Disabled)
+            .stop(Param::Disabled)
+            .build()
+            .unwrap();
+        let request = ChatRequest::new(&config, vec![Message::new(Role::User, "hello")]);
+        let body = serde_json::to_value(&request).unwrap();
+        let body = body.as_object().unwrap();
+        assert!(!body.contains_key("frequency_penalty"));
+        assert!(!body.contains_key("presence_penalty"));
+        assert!(!body.contains_key("stop"));
+        assert_eq!(body["messages"][0]["role"], "user");
+        assert_eq!(body["messages"][0]["content"], "hello");
+        assert!(!body.contains_key("reasoning_effort"));
+    }
+
+    // test that max_tokens is left for the model to decide by default
+    #[test]
+    fn max_tokens_default_test() {
+        let config = WinstonConfigBuilder::new().build().unwrap();
+        let body = serde_json::to_value(ChatRequest::new(&config, Vec::new())).unwrap();
+        assert!(!body.as_object().unwrap().contains_key("max_tokens"));
+
+        let config = WinstonConfigBuilder::new().max_tokens(128).build().unwrap();
+        let body = serde_json::to_value(ChatRequest::new(&config, Vec::new())).unwrap();
+        assert_eq!(body["max_tokens"], 128);
+    }
+
+    // test that the sampling parameters of local servers are only sent when
+    // set, to either API
+    #[test]
+    fn local_sampling_params_test() {
+        let params = ["min_p", "top_k", "repeat_penalty"];
+        let config = WinstonConfigBuilder::new().build().unwrap();
+        let request = ChatRequest::new(&config, Vec::new());
+        let body = serde_json::to_value(&request).unwrap();
+        let completion = serde_json::to_value(CompletionRequest::from(&request)).unwrap();
+        for param in params {
+            assert!(!body.as_object().unwrap().contains_key(param), "{}", param);
+            assert!(!completion.as_object().unwrap().contains_key(param), "{}", param);
+        }
+
+        let config = WinstonConfigBuilder::new().min_p(0.05).top_k(40).repeat_penalty(1.1).build().unwrap();
+        let request = ChatRequest::new(&config, Vec::new());
+        let body = serde_json::to_value(&request).unwrap();
+        assert_eq!(body["min_p"], serde_json::json!(0.05f32));
+        assert_eq!(body["top_k"], 40);
+        assert_eq!(body["repeat_penalty"], serde_json::json!(1.1f32));
+        let completion = serde_json::to_value(CompletionRequest::from(&request)).unwrap();
+        assert_eq!(completion["top_k"], 40);
+
+        let config = WinstonConfigBuilder::new().top_k(20).build().unwrap();
+        let body = serde_json::to_value(ChatRequest::new(&config, Vec::new())).unwrap();
+        assert_eq!(body["top_k"], 20);
+        assert!(!body.as_object().unwrap().contains_key("min_p"));
+    }
+
+    // test that a configured reasoning effort is passed through
+    #[test]
+    fn reasoning_effort_test() {
+        let config = WinstonConfigBuilder::new()
+            .model("o3-mini".to_string())
+            .reasoning_effort("low".to_string())
+            .build()
+            .unwrap();
+        let request = ChatRequest::new(&config, Vec::new());
+        let body = serde_json::to_value(&request).unwrap();
+        assert_eq!(body["reasoning_effort"], "low");
+        assert!(supports_reasoning(&config.model));
+        assert!(!supports_reasoning("gpt-4o"));
+    }
+
+    // test that a structured refusal is detected in a response
+    #[test]
+    fn refusal_field_test() {
+        let mut server = mockito::Server::new();
+        let _mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"role":"assistant","content":null,"refusal":"I can't help with that."}}]}"#)
+            .create();
+
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .model("gpt-4o".to_string())
+            .build()
+            .unwrap();
+        let client = crate::client::Client::from_config(&config).unwrap();
+        let response = client.chat(&ChatRequest::new(&config, Vec::new())).unwrap();
+        assert_eq!(response.content(), Some(""));
+        assert_eq!(
+            refusal(response.content().unwrap(), response.refusal(), None).as_deref(),
+            Some("I can't help with that.")
+        );
+    }
+
+    // test that a configured pattern marks content as a refusal
+    #[test]
+    fn refusal_pattern_test() {
+        let content = "Sorry, I Can't Help with that request.";
+        assert_eq!(refusal(content, None, Some("can't help")).as_deref(), Some(content));
+        assert_eq!(refusal("Sure, here it is.", None, Some("can't help")), None);
+        assert_eq!(refusal(content, None, None), None);
+    }
+
+    // test that chat models send messages and legacy models a single prompt
+    #[test]
+    fn request_shape_test() {
+        let config = WinstonConfigBuilder::new().model("gpt-4o".to_string()).build().unwrap();
+        let request = ChatRequest::new(&config, vec![Message::new(Role::User, "hello")]);
+        assert_eq!(request.api, ApiKind::Chat);
+        let body = serde_json::to_value(&request).unwrap();
+        assert_eq!(body["messages"][0]["content"], "hello");
+        assert!(!body.as_object().unwrap().contains_key("prompt"));
+        assert!(!body.as_object().unwrap().contains_key("api"));
+
+        // the default model chats, so replies aren't cut at the first line
+        let config = WinstonConfigBuilder::new().build().unwrap();
+        let body = serde_json::to_value(ChatRequest::new(&config, vec![Message::new(Role::User, "hello")])).unwrap();
+        assert!(!body.as_object().unwrap().contains_key("stop"));
+        let config = WinstonConfigBuilder::new().model("davinci".to_string()).api(ApiKind::Chat).build().unwrap();
+        assert_eq!(config.stop, None);
+
+        let config = WinstonConfigBuilder::new().model("davinci".to_string()).build().unwrap();
+        let request = ChatRequest::new(&config, vec![Message::new(Role::User, "hello")]);
+        assert_eq!(request.api, ApiKind::Completions);
+        let body = serde_json::to_value(CompletionRequest::from(&request)).unwrap();
+        assert_eq!(body["model"], "davinci");
+        assert_eq!(body["prompt"], "hello");
+        assert_eq!(body["stop"], "\n");
+        assert!(!body.as_object().unwrap().contains_key("messages"));
+
+        // an explicit api overrides the model
+        let config = WinstonConfigBuilder::new()
+            .model("davinci".to_string())
+            .api(ApiKind::Chat)
+            .build()
+            .unwrap();
+        assert_eq!(ChatRequest::new(&config, Vec::new()).api, ApiKind::Chat);
+        assert_eq!(api_for_model("gpt-3.5-turbo-instruct"), ApiKind::Completions);
+    }
+
+    // test that only the first line of a multi-line completion is kept
+    #[test]
+    fn first_line_test() {
+        let mut server = mockito::Server::new();
+        let _mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"role":"assistant","content":"\n  git log --oneline -5  \r\n\nThis shows the last five commits."}}]}"#)
+            .create();
+
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .build()
+            .unwrap();
+        let client = crate::client::Client::from_config(&config).unwrap();
+        let response = client.chat(&ChatRequest::new(&config, Vec::new())).unwrap();
+        let content = response.content().unwrap();
+        assert_eq!(first_line(content, true), "git log --oneline -5");
+        assert_eq!(first_line(content, false), "");
+        assert_eq!(first_line("one line", true), "one line");
+        assert_eq!(first_line("", true), "");
+    }
+
+    // test that surrounding whitespace is trimmed from printed completions
+    #[test]
+    fn output_text_trim_test() {
+        let mut server = mockito::Server::new();
+        let _mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"role":"assistant","content":"\n\n  Hello there.\n "}}]}"#)
+            .create();
+
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .model("gpt-4o".to_string())
+            .build()
+            .unwrap();
+        let client = crate::client::Client::from_config(&config).unwrap();
+        let response = client.chat(&ChatRequest::new(&config, Vec::new())).unwrap();
+        let content = response.content().unwrap();
+        assert_eq!(output_text(content, true), "Hello there.");
+        assert_eq!(output_text(content, false), "\n\n  Hello there.\n ");
+    }
+
+    // test that the suffix file is appended to the last user message only
+    #[test]
+    fn prompt_suffix_file_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let fp = temp_dir.path().join("suffix.txt");
+        std::fs::write(&fp, "Answer in a bulleted list.\nKeep it under 50 words.\n").unwrap();
+        let mut messages = vec![
+            Message::new(Role::System, "Be brief."),
+            Message::new(Role::User, "Name a color."),
+            Message::new(Role::Assistant, "Blue."),
+            Message::new(Role::User, "Why is the sky blue?\n"),
+        ];
+        append_suffix_file(&mut messages, &fp, encoding_rs::UTF_8).unwrap();
+        assert_eq!(messages[1].content, "Name a color.");
+        assert_eq!(messages[3].content, "Why is the sky blue?\n\nAnswer in a bulleted list.\nKeep it under 50 words.");
+
+        let err = append_suffix_file(&mut messages, &temp_dir.path().join("missing.txt"), encoding_rs::UTF_8).unwrap_err();
+        assert!(err.to_string().starts_with("Could not read prompt suffix file: Could not read "), "{}", err);
+        assert!(append_suffix_file(&mut [Message::new(Role::System, "Be brief.")], &fp, encoding_rs::UTF_8).is_err());
+    }
+
+    // test that file references are sent as content parts of the last user
+    // message, read back the same way, and only for the flavors that take them
+    #[test]
+    fn file_references_test() {
+        let config = WinstonConfigBuilder::new().build().unwrap();
+        let mut request = ChatRequest::new(&config, vec![
+            Message::new(Role::User, "Earlier question"),
+            Message::new(Role::User, "Summarize the report."),
+        ]);
+        request.attach_files(&["file-abc123".to_string(), "file-def456".to_string()]);
+        let body = serde_json::to_value(&request).unwrap();
+
+        assert_eq!(body["messages"][0]["content"], "Earlier question");
+        assert_eq!(
+            body["messages"][1]["content"],
+            serde_json::json!([
+                {"type": "text", "text": "Summarize the report."},
+                {"type": "file", "file": {"file_id": "file-abc123"}},
+                {"type": "file", "file": {"file_id": "file-def456"}},
+            ])
+        );
+        let messages: Vec<Message> = serde_json::from_value(body["messages"].clone()).unwrap();
+        assert_eq!(messages, request.messages);
+
+        assert!(supports_files(ApiFlavor::OpenAi, ApiKind::Chat));
+        assert!(supports_files(ApiFlavor::Azure, ApiKind::Chat));
+        assert!(!supports_files(ApiFlavor::Anthropic, ApiKind::Chat));
+        assert!(!supports_files(ApiFlavor::OpenAi, ApiKind::Completions));
+    }
+
+    // test that a messages file is sent as the request messages unchanged
+    #[test]
+    fn messages_file_test() {
+        let messages = r#"[
+            {"role": "system", "content": "Answer in French."},
+            {"role": "user", "content": "Hello"},
+            {"role": "assistant", "content": "Bonjour"},
+            {"role": "user", "content": "How are you?"}
+        ]"#;
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("messages.json");
+        std::fs::write(&temp_file, messages).unwrap();
+
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJsonString(format!(r#"{{"messages":{}}}"#, messages)))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"role":"assistant","content":"Bien"}}]}"#)
+            .create();
+
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .model("gpt-4o".to_string())
+            .build()
+            .unwrap();
+        let client = crate::client::Client::from_config(&config).unwrap();
+        let request = ChatRequest::new(&config, load_messages(&temp_file).unwrap());
+        client.chat(&request).unwrap();
+        mock.assert();
+
+        std::fs::write(&temp_file, r#"[{"role": "bot", "content": "Hello"}]"#).unwrap();
+        let err = load_messages(&temp_file).unwrap_err().to_string();
+        assert!(err.contains("unknown variant `bot`"), "{}", err);
+    }
+
+    // test that the echoed prompt is printed ahead of the completion
+    #[test]
+    fn echo_prompt_test() {
+        let mut server = mockito::Server::new();
+        let _mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"role":"assistant","content":"Paris"}}]}"#)
+            .create();
+
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .model("gpt-4o".to_string())
+            .build()
+            .unwrap();
+        let client = crate::client::Client::from_config(&config).unwrap();
+        let messages = vec![
+            Message::new(Role::System, "Be brief."),
+            Message::new(Role::User, "What is the capital of France?"),
+        ];
+        let request = ChatRequest::new(&config, messages);
+        let response = client.chat(&request).unwrap();
+        let output = format!("{}{}\n", echo_prompt(&request.messages, None), response.content().unwrap());
+        assert_eq!(output, "system: Be brief.\nuser: What is the capital of France?\nassistant: Paris\n");
+
+        let messages = [Message::new(Role::User, "Hi"), Message::new(Role::Assistant, "Hello"), Message::new(Role::User, "Bye")];
+        assert_eq!(echo_prompt(&messages, Some("Winston")), "user: Hi\nWinston: Hello\nuser: Bye\nWinston: ");
+    }
+
+    // test each strategy for picking one of several choices
+    #[test]
+    fn select_choice_test() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJsonString(r#"{"n":4}"#.to_string()))
+            .with_status(200)
+            .with_body(
+                r#"{"choices":[
+                    {"message":{"role":"assistant","content":" "}},
+                    {"message":{"role":"assistant","content":"A medium answer"}},
+                    {"message":{"role":"assistant","content":"Short"}},
+                    {"message":{"role":"assistant","content":"The longest answer of them all"}}
+                ]}"#,
+            )
+            .create();
+
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .model("gpt-4o".to_string())
+            .build()
+            .unwrap();
+        let client = crate::client::Client::from_config(&config).unwrap();
+        let mut request = ChatRequest::new(&config, Vec::new());
+        request.n = Some(4);
+        let response = client.chat(&request).unwrap();
+
+        mock.assert();
+        assert_eq!(response.select(Select::Longest), Some("The longest answer of them all"));
+        assert_eq!(response.select(Select::Shortest), Some("Short"));
+        assert_eq!(response.select(Select::First), Some("A medium answer"));
+        assert_eq!(response.contents().count(), 4);
+    }
+
+    // test that --no-system keeps a configured system message out of the request
+    #[test]
+    fn no_system_test() {
+        use clap::Parser;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("config.toml");
+        std::fs::write(&temp_file, "system = \"You are a pirate.\"\n").unwrap();
+        let config_arg = temp_file.to_str().unwrap();
+        let messages = || vec![Message::new(Role::User, "hello")];
+
+        let opts = crate::cli::Options::try_parse_from(["winston", "--config", config_arg]).unwrap();
+        let config = crate::config::resolve_config(&opts).unwrap();
+        let request = ChatRequest::new(&config, messages());
+        assert_eq!(request.messages[0], Message::new(Role::System, "You are a pirate."));
+
+        let opts = crate::cli::Options::try_parse_from(["winston", "--config", config_arg, "--no-system"]).unwrap();
+        let config = crate::config::resolve_config(&opts).unwrap();
+        let request = ChatRequest::new(&config, messages());
+        assert!(request.messages.iter().all(|message| message.role != Role::System));
+        let body = serde_json::to_value(&request).unwrap();
+        assert_eq!(body["messages"].as_array().unwrap().len(), 1);
+    }
+
+    // test that stream_options is only sent when usage is wanted
+    #[test]
+    fn stream_options_test() {
+        let config = WinstonConfigBuilder::new().build().unwrap();
+        let body = serde_json::to_value(ChatRequest::new(&config, Vec::new())).unwrap();
+        assert!(!body.as_object().unwrap().contains_key("stream"));
+
+        let body = serde_json::to_value(ChatRequest::new(&config, Vec::new()).streaming(false)).unwrap();
+        assert_eq!(body["stream"], true);
+        assert!(!body.as_object().unwrap().contains_key("stream_options"));
+
+        let body = serde_json::to_value(ChatRequest::new(&config, Vec::new()).streaming(true)).unwrap();
+        assert_eq!(body["stream_options"]["include_usage"], true);
+    }
+
+    // test that parallel_tool_calls is sent next to the tools it applies to
+    #[test]
+    fn parallel_tool_calls_test() {
+        let config = WinstonConfigBuilder::new().build().unwrap();
+        let mut request = ChatRequest::new(&config, Vec::new());
+        let body = serde_json::to_value(&request).unwrap();
+        assert!(!body.as_object().unwrap().contains_key("tools"));
+        assert!(!body.as_object().unwrap().contains_key("parallel_tool_calls"));
+
+        let tool = serde_json::json!({
+            "type": "function",
+            "function": {"name": "get_weather", "parameters": {"type": "object", "properties": {}}},
+        });
+        request.tools = Some(vec![tool.clone()]);
+        request.parallel_tool_calls = Some(false);
+        let body = serde_json::to_value(&request).unwrap();
+        assert_eq!(body["tools"], serde_json::json!([tool]));
+        assert_eq!(body["parallel_tool_calls"], false);
+    }
+
+    // test that blank prompts are rejected unless another message has content
+    #[test]
+    fn empty_prompt_test() {
+        assert_eq!(check_prompt(&[]), Err(WinstonError::EmptyPrompt));
+        assert_eq!(check_prompt(&[Message::new(Role::User, " \n\t")]), Err(WinstonError::EmptyPrompt));
+        assert_eq!(
+            check_prompt(&[Message::new(Role::System, ""), Message::new(Role::User, "  ")]),
+            Err(WinstonError::EmptyPrompt)
+        );
+        assert_eq!(check_prompt(&[Message::new(Role::User, "hi")]), Ok(()));
+        assert_eq!(check_prompt(&[Message::new(Role::System, "Be brief."), Message::new(Role::User, "")]), Ok(()));
+        assert_eq!(check_prompt(&[Message::new(Role::User, ""), Message::new(Role::Assistant, "Once upon")]), Ok(()));
+    }
+}
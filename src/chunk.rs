@@ -0,0 +1,219 @@
+// Winston chunked input
+// With --chunk a document read from stdin that is too long for the model is
+// split into chunks, each sent with the instruction given as the prompt, and
+// the replies are printed one after another. A chunk holds as many tokens as
+// the model's context window leaves after max_tokens for the reply and the
+// instruction. Chunks end at paragraph breaks where possible, then at the end
+// of a sentence, and only a single sentence longer than a whole chunk is cut
+// mid-sentence. Tokens are estimated the same way as for cost estimates.
+// For a model whose context window isn't known the default window is assumed
+// with a warning that the chunk sizes are approximate, or with --strict-tokens
+// nothing is sent.
+
+use crate::chat::{ChatRequest, Message, Role};
+use crate::client::Client;
+use crate::config::WinstonConfig;
+use crate::cost::{estimate_tokens, strict_estimate, DEFAULT_COMPLETION_TOKENS, TOKENS_PER_MESSAGE};
+use crate::diagnostics::Diagnostic;
+use super::Result;
+
+// context window in tokens by model name prefix, more specific prefixes first
+pub const CONTEXT_WINDOWS: &[(&str, u32)] = &[
+    ("gpt-4o", 128_000),
+    ("gpt-4.1", 1_000_000),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4", 8_192),
+    ("gpt-3.5-turbo", 16_385),
+    ("gpt-5", 400_000),
+    ("o1-mini", 128_000),
+    ("o1", 200_000),
+    ("o3", 200_000),
+    ("o4-mini", 200_000),
+];
+
+// the context window assumed for unknown models
+pub const DEFAULT_CONTEXT_WINDOW: u32 = 8_192;
+
+// the context window of a model, when known
+pub fn known_context_window(model: &str) -> Option<u32> {
+    CONTEXT_WINDOWS
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|&(_, window)| window)
+}
+
+// the context window of a model
+pub fn context_window(model: &str) -> u32 {
+    known_context_window(model).unwrap_or(DEFAULT_CONTEXT_WINDOW)
+}
+
+// a warning when the context window of a model isn't known and the default
+// is assumed
+pub fn unknown_context_window(model: &str) -> Option<Diagnostic> {
+    if known_context_window(model).is_some() {
+        return None;
+    }
+    let message = format!(
+        "no context window known for {}, assuming {} tokens, so chunk sizes are approximate",
+        model, DEFAULT_CONTEXT_WINDOW
+    );
+    Some(
+        Diagnostic::warning("unknown_context_window", message)
+            .with("model", model)
+            .with("context_window", DEFAULT_CONTEXT_WINDOW),
+    )
+}
+
+// the tokens left for a chunk once the reply and the instruction fit. With
+// strict a model without a known context window is an error rather than a
+// warning.
+pub fn chunk_tokens(config: &WinstonConfig, instruction: &str, strict: bool) -> Result<u32> {
+    if let Some(warning) = unknown_context_window(&config.model) {
+        if strict {
+            strict_estimate(&config.model, "chunk sizes")?;
+        }
+        warning.emit();
+    }
+    let reserved = config.max_tokens.unwrap_or(DEFAULT_COMPLETION_TOKENS)
+        + estimate_tokens(instruction)
+        + 2 * TOKENS_PER_MESSAGE;
+    match context_window(&config.model).checked_sub(reserved) {
+        Some(tokens) if tokens > 0 => Ok(tokens),
+        _ => Err(format!(
+            "The context window of {} leaves no room for a chunk after max_tokens and the instruction",
+            config.model
+        )
+        .into()),
+    }
+}
+
+// text cut into sentences, each keeping its closing punctuation and spacing
+fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((_, c)) = chars.next() {
+        let end_of_sentence = matches!(c, '.' | '!' | '?' | '\n');
+        if let (true, Some(&(next, after))) = (end_of_sentence, chars.peek()) {
+            if after.is_whitespace() {
+                let end = next + after.len_utf8();
+                sentences.push(&text[start..end]);
+                start = end;
+                chars.next();
+            }
+        }
+    }
+    if start < text.len() {
+        sentences.push(&text[start..]);
+    }
+    sentences
+}
+
+// text cut into pieces of at most max_tokens, at character boundaries
+fn hard_split(text: &str, max_tokens: u32) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    chars.chunks(max_tokens as usize * 4).map(|piece| piece.iter().collect()).collect()
+}
+
+// split text into chunks of at most max_tokens, preferring to end them at
+// paragraph breaks and then at sentence ends
+pub fn split(text: &str, max_tokens: u32) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    let mut push = |piece: &str, separator: &str, chunk: &mut String| {
+        if !chunk.is_empty() && estimate_tokens(chunk) + estimate_tokens(separator) + estimate_tokens(piece) > max_tokens {
+            chunks.push(std::mem::take(chunk));
+        }
+        if !chunk.is_empty() {
+            chunk.push_str(separator);
+        }
+        chunk.push_str(piece);
+    };
+    for paragraph in text.split("\n\n").map(str::trim).filter(|paragraph| !paragraph.is_empty()) {
+        if estimate_tokens(paragraph) <= max_tokens {
+            push(paragraph, "\n\n", &mut chunk);
+            continue;
+        }
+        // a paragraph too long for one chunk is split between sentences
+        let mut separator = "\n\n";
+        for sentence in sentences(paragraph) {
+            if estimate_tokens(sentence) <= max_tokens {
+                push(sentence, separator, &mut chunk);
+            } else {
+                for piece in hard_split(sentence, max_tokens) {
+                    push(&piece, separator, &mut chunk);
+                    separator = "";
+                }
+            }
+            separator = "";
+        }
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+// send every chunk of document with the instruction, one after another, and
+// join the replies
+pub fn run(client: &Client, config: &WinstonConfig, instruction: &str, document: &str, strict: bool) -> Result<String> {
+    let chunks = split(document, chunk_tokens(config, instruction, strict)?);
+    let mut replies = Vec::new();
+    for chunk in &chunks {
+        let content = format!("{}\n\n{}", instruction, chunk);
+        let response = client.chat(&ChatRequest::new(config, vec![Message::new(Role::User, content)]))?;
+        replies.push(response.content().unwrap_or_default().trim().to_string());
+    }
+    Ok(replies.join("\n\n"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::WinstonConfigBuilder;
+
+    // test that chunks stay under the limit and end at paragraph breaks, then
+    // sentence ends, and only cut a sentence longer than a chunk
+    #[test]
+    fn split_boundaries_test() {
+        // paragraphs of 4 tokens each, two fit in a chunk of 12
+        let text = "aaaa bbbb ccccc.\n\nddddd eeee ffff.\n\n\n\ngggg hhhh iiiii.";
+        assert_eq!(split(text, 12), ["aaaa bbbb ccccc.\n\nddddd eeee ffff.", "gggg hhhh iiiii."]);
+        assert_eq!(split(text, 100), ["aaaa bbbb ccccc.\n\nddddd eeee ffff.\n\ngggg hhhh iiiii."]);
+
+        // a paragraph over the limit is split between its sentences
+        let paragraph = "One two three. Four five six! Seven eight nine? Ten.";
+        let chunks = split(paragraph, 8);
+        assert_eq!(chunks, ["One two three. Four five six! ", "Seven eight nine? Ten."]);
+        assert!(chunks.iter().all(|chunk| estimate_tokens(chunk) <= 8));
+        assert_eq!(chunks.concat(), paragraph);
+
+        // a sentence over the limit is cut
+        let chunks = split(&"x".repeat(30), 4);
+        assert_eq!(chunks, ["x".repeat(16), "x".repeat(14)]);
+        assert!(split(" \n\n ", 4).is_empty());
+
+        // the reply and the instruction are taken from the context window
+        let config = WinstonConfigBuilder::new().model("gpt-4".to_string()).max_tokens(1000).build().unwrap();
+        assert_eq!(chunk_tokens(&config, "Summarize this.", true).unwrap(), 8_192 - 1000 - 4 - 8);
+        let config = WinstonConfigBuilder::new().model("gpt-4".to_string()).max_tokens(9000).build().unwrap();
+        assert!(chunk_tokens(&config, "Summarize this.", false).is_err());
+    }
+
+    // test that an unknown model is chunked for the default context window
+    // with a warning, or fails with strict
+    #[test]
+    fn unknown_context_window_test() {
+        assert!(unknown_context_window("gpt-4o-mini").is_none());
+        let warning = unknown_context_window("llama-3-70b").unwrap();
+        assert_eq!(warning.code, "unknown_context_window");
+        assert_eq!(
+            warning.message,
+            "no context window known for llama-3-70b, assuming 8192 tokens, so chunk sizes are approximate"
+        );
+
+        let config = WinstonConfigBuilder::new().model("llama-3-70b".to_string()).max_tokens(1000).build().unwrap();
+        assert_eq!(chunk_tokens(&config, "Summarize this.", false).unwrap(), 8_192 - 1000 - 4 - 8);
+        assert!(chunk_tokens(&config, "Summarize this.", true).is_err());
+    }
+}
@@ -2,28 +2,480 @@
 // Winston should be able to parse all configuration options from the command
 // line. It should check and prioritize environment variables, command line
 // options are secondary and configuration files are tertiary.
+//
+// Options are left unset when they aren't given so that values from the
+// configuration file are only overridden by settings the user actually passed;
+// defaults are applied when the configuration is resolved.
+//
+// With --no-env the environment variables are ignored, leaving the flags, the
+// config file and the defaults.
 
-use crate::config::{OPENAI_ENDPOINT, MODEL, MAX_TOKENS, TEMPERATURE, TOP_P, STOP, FREQUENCY_PENALTY, PRESENCE_PENALTY};
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{CommandFactory, FromArgMatches, Parser};
+
+use crate::chat::Select;
+use crate::config::{ApiFlavor, ApiKind, ConfigFormat, Param, Preset, REASONING_EFFORTS};
+use crate::request_template::parse_var;
+use crate::session::ExportFormat;
+use crate::term::{ColorChoice, LineEndings, TRUNCATION_MARKER};
 
 #[derive(Debug, clap::Parser)]
 #[command(version, author, about)]
 pub struct Options {
-    #[arg(short = 'e', long, default_value_t = OPENAI_ENDPOINT.to_string())]
-    pub openai_endpoint: String,
-    #[arg(short = 'k', long, env = "OPENAI_API_KEY")]
-    pub openai_api_key: String,
-    #[arg(short = 'm', long, env = "OPENAI_MODEL", default_value_t = MODEL.to_string())]
-    pub openai_model: String,
-    #[arg(short = 'l', long, default_value_t = MAX_TOKENS)]
-    pub openai_max_tokens: u32,
-    #[arg(short = 't', long, default_value_t = TEMPERATURE)]
-    pub openai_temperature: f32,
-    #[arg(short = 'p', long, default_value_t = TOP_P)]
-    pub openai_top_p: f32,
-    #[arg(short = 'd', long, default_value_t = STOP.to_string())]
-    pub stop: String,
-    #[arg(short = 'f', long, default_value_t = FREQUENCY_PENALTY)]
-    pub frequency_penalty: f32,
-    #[arg(short = 'r', long, default_value_t = PRESENCE_PENALTY)]
-    pub presence_penalty: f32,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// The prompt to send, read from stdin when not given
+    pub prompt: Vec<String>,
+    /// Send the JSON array of {role, content} messages in this file instead of a prompt
+    #[arg(long, conflicts_with = "prompt")]
+    pub messages_file: Option<PathBuf>,
+    /// Send the prompt in this file, with parameters from its front-matter
+    #[arg(long, conflicts_with_all = ["prompt", "messages_file"])]
+    pub prompt_file: Option<PathBuf>,
+    /// Send the text fetched from this URL as the prompt, with instructions in --system
+    #[arg(long, value_name = "URL", conflicts_with_all = ["prompt", "messages_file", "prompt_file", "replay"])]
+    pub prompt_url: Option<String>,
+    /// Append the contents of this file to the user message, like standing format instructions
+    #[arg(long, value_name = "PATH")]
+    pub prompt_suffix_file: Option<PathBuf>,
+    /// Reference an uploaded file from the prompt, repeat for several (openai and azure flavors only)
+    #[arg(long, value_name = "ID")]
+    pub file_id: Vec<String>,
+    /// Encoding of prompts read from stdin or a file, such as latin1 or shift_jis
+    #[arg(long, global = true, default_value = "utf-8", value_parser = crate::input::parse_encoding)]
+    pub input_encoding: &'static encoding_rs::Encoding,
+    /// When to color output, auto colors a terminal unless NO_COLOR is set
+    #[arg(long, value_name = "WHEN", global = true, default_value = "auto")]
+    pub color: ColorChoice,
+    /// Never color output, the same as --color never
+    #[arg(long, global = true)]
+    pub no_color: bool,
+    /// Send a logged request again, given as a JSON record or a JSON lines file whose last record is used
+    #[arg(long, value_name = "RECORD", conflicts_with_all = ["prompt", "messages_file", "prompt_file"])]
+    pub replay: Option<String>,
+    #[arg(long = "config", global = true)]
+    pub config_file: Option<PathBuf>,
+    /// Format of the config file, detected from its extension when not given
+    #[arg(long, global = true)]
+    pub config_format: Option<ConfigFormat>,
+    /// Refuse a config file holding an API key that others can read, instead of warning
+    #[arg(long, global = true)]
+    pub strict_permissions: bool,
+    /// Don't read the system-wide config file beneath the user's
+    #[arg(long, global = true)]
+    pub no_system_config: bool,
+    /// Ignore environment variables like OPENAI_API_KEY, using only flags, the config file and defaults
+    #[arg(long, global = true)]
+    pub no_env: bool,
+    #[arg(short = 'e', long, global = true)]
+    pub openai_endpoint: Option<String>,
+    /// Use a named endpoint from the [endpoints] section of the config file
+    #[arg(long, global = true)]
+    pub endpoint: Option<String>,
+    #[arg(long, global = true)]
+    pub flavor: Option<ApiFlavor>,
+    /// Apply a named parameter preset from the [presets] section of the config file
+    #[arg(long, visible_alias = "profile", global = true)]
+    pub preset: Option<String>,
+    /// Don't apply the default_preset from the config file
+    #[arg(long, visible_alias = "no-profile", global = true, conflicts_with = "preset")]
+    pub no_preset: bool,
+    /// Print the version, commit and build profile as JSON and exit
+    #[arg(long)]
+    pub version_json: bool,
+    /// Print the presets defined in the config file and exit
+    #[arg(long)]
+    pub list_presets: bool,
+    /// Print every config field with its value and the source it came from, and exit
+    #[arg(long, global = true)]
+    pub explain_config: bool,
+    /// Request shape, inferred from the model when not given
+    #[arg(long, global = true)]
+    pub api: Option<ApiKind>,
+    #[arg(short = 'k', long, env = "OPENAI_API_KEY", hide_env_values = true, global = true)]
+    /// API key, repeat to rotate requests through several keys
+    pub openai_api_key: Vec<String>,
+    /// Bill requests to this organization instead of the configured one
+    #[arg(short = 'o', long, env = "OPENAI_ORG_ID", global = true)]
+    pub openai_org_id: Option<String>,
+    #[arg(short = 'm', long, env = "OPENAI_MODEL", global = true)]
+    pub openai_model: Option<String>,
+    /// Models to try in turn when the model is unavailable or overloaded
+    #[arg(long, global = true, value_name = "MODELS", value_delimiter = ',')]
+    pub fallback_models: Vec<String>,
+    #[arg(short = 'l', long, global = true)]
+    pub openai_max_tokens: Option<u32>,
+    #[arg(short = 't', long, global = true)]
+    pub openai_temperature: Option<f32>,
+    #[arg(short = 'p', long, global = true)]
+    pub openai_top_p: Option<f32>,
+    /// Sample only tokens at least this likely relative to the likeliest, for local servers (not supported by OpenAI)
+    #[arg(long, global = true)]
+    pub min_p: Option<f32>,
+    /// Sample only from this many of the likeliest tokens, for local servers (not supported by OpenAI)
+    #[arg(long, global = true)]
+    pub top_k: Option<u32>,
+    /// Penalize tokens that were already generated, for local servers (not supported by OpenAI)
+    #[arg(long, global = true)]
+    pub repeat_penalty: Option<f32>,
+    /// A sequence that ends the completion, up to four by repeating the flag, or none
+    #[arg(short = 'd', long, global = true)]
+    pub stop: Vec<Param<String>>,
+    #[arg(short = 'f', long, global = true)]
+    pub frequency_penalty: Option<Param<f32>>,
+    #[arg(short = 'r', long, global = true)]
+    pub presence_penalty: Option<Param<f32>>,
+    #[arg(long, global = true, value_parser = clap::builder::PossibleValuesParser::new(REASONING_EFFORTS))]
+    pub reasoning_effort: Option<String>,
+    /// System message sent ahead of the prompt
+    #[arg(long, global = true)]
+    pub system: Option<Param<String>>,
+    /// Never send a system message, even a configured one
+    #[arg(long, global = true, conflicts_with = "system")]
+    pub no_system: bool,
+    /// Ask for replies in this language, given as an ISO 639-1 code like fr
+    #[arg(long, global = true, value_name = "CODE")]
+    pub lang: Option<Param<String>>,
+    /// Don't ask for replies in a language, even a configured one
+    #[arg(long, global = true, conflicts_with = "lang")]
+    pub no_lang: bool,
+    /// Send this header with every request, repeat for several
+    #[arg(long, global = true, value_name = "NAME:VALUE", value_parser = parse_header)]
+    pub header: Vec<(String, String)>,
+    #[arg(long, global = true)]
+    pub timeout: Option<u64>,
+    /// Give up on a request that hasn't completed after this long, retries included, like 30s or 500ms
+    #[arg(long, global = true, value_name = "DURATION")]
+    pub deadline: Option<String>,
+    /// Wait at most this many seconds when a rate limited server sends a longer Retry-After
+    #[arg(long, global = true, value_name = "SECS")]
+    pub max_retry_after: Option<u64>,
+    /// Fail instead of waiting when the Retry-After is longer than --max-retry-after
+    #[arg(long, global = true)]
+    pub fail_on_retry_after: bool,
+    /// Lengthen each retry backoff by a random fraction of it up to this, from 0 (none) to 1
+    #[arg(long, global = true, value_name = "FRACTION")]
+    pub retry_jitter: Option<f64>,
+    /// Leave out the OpenAI-Organization header even when an organization is configured
+    #[arg(long, global = true)]
+    pub no_org_header: bool,
+    /// Retry requests failing with a transient connection error this many times
+    #[arg(long, global = true)]
+    pub max_retries: Option<u32>,
+    /// Most requests to have in flight at once in batch and bench
+    #[arg(long, global = true)]
+    pub concurrency: Option<u32>,
+    #[arg(short = 's', long)]
+    pub stream: bool,
+    /// Ask the model to continue a streamed completion cut off by the length limit or a dropped connection
+    #[arg(long, requires = "stream")]
+    pub auto_continue: bool,
+    /// The most times a completion is continued with --auto-continue
+    #[arg(long, default_value_t = 3)]
+    pub max_continuations: u32,
+    /// Ask for JSON matching the JSON Schema in this file and check the reply against it
+    #[arg(long)]
+    pub schema: Option<PathBuf>,
+    /// Let the model call the tools defined in this JSON file
+    #[arg(long)]
+    pub tools: Option<PathBuf>,
+    /// Run the tool calls of the model with this command, the name in WINSTON_TOOL_NAME and the arguments on stdin
+    #[arg(long, requires = "tools", conflicts_with_all = ["stream", "cache"])]
+    pub tool_runner: Option<String>,
+    /// Ask before running each tool call
+    #[arg(long, requires = "tool_runner")]
+    pub interactive_approve: bool,
+    /// Have the model call tools one at a time instead of several at once
+    #[arg(long)]
+    pub no_parallel_tools: bool,
+    /// Check every prompt with the moderations endpoint before it is sent, by any subcommand, failing without sending when one is flagged
+    #[arg(long, global = true)]
+    pub moderate: bool,
+    /// Split a long document from stdin into chunks that fit the model, sending each with the prompt as the instruction
+    #[arg(long, conflicts_with_all = ["stream", "messages_file", "prompt_file", "replay"])]
+    pub chunk: bool,
+    /// Fail instead of estimating tokens for a model whose context window is unknown, for --chunk, warn_tokens, --confirm-cost and --summarize-history
+    #[arg(long)]
+    pub strict_tokens: bool,
+    /// Write the request and its response to this directory as JSON files, the request replayable with --replay
+    #[arg(long, value_name = "DIR", conflicts_with = "stream")]
+    pub record: Option<PathBuf>,
+    /// Reuse the reply to an identical earlier request, caching this one's
+    #[arg(long, conflicts_with = "stream")]
+    pub cache: bool,
+    /// Print the estimated cost and ask before sending the request
+    #[arg(long)]
+    pub confirm_cost: bool,
+    /// Send without asking for confirmation
+    #[arg(short = 'y', long)]
+    pub yes: bool,
+    /// Exit with an error when the model refuses the request
+    #[arg(long)]
+    pub fail_on_refusal: bool,
+    /// Treat completions containing this text as refusals
+    #[arg(long)]
+    pub refusal_pattern: Option<String>,
+    /// Label replies with this name instead of assistant in --echo-prompt and session exports
+    #[arg(long, global = true)]
+    pub assistant_label: Option<String>,
+    /// Write the resolved configuration to this file
+    #[arg(long, global = true)]
+    pub dump_config: Option<PathBuf>,
+    /// Include API keys in the --dump-config file instead of masking them
+    #[arg(long, global = true, requires = "dump_config")]
+    pub include_secrets: bool,
+    /// Number of completions to request, all are printed unless --select is given
+    #[arg(long, conflicts_with = "stream")]
+    pub count: Option<u32>,
+    /// Print only one of the completions requested with --count
+    #[arg(long, requires = "count")]
+    pub select: Option<Select>,
+    /// Print the messages sent, labelled by role, before the completion
+    #[arg(long)]
+    pub echo_prompt: bool,
+    /// Remove <think>...</think> blocks from completions, or blocks of the given comma separated tags
+    #[arg(long, value_name = "TAGS", num_args = 0..=1, require_equals = true, value_delimiter = ',', default_missing_value = crate::think::THINK_TAG)]
+    pub strip_think_tags: Option<Vec<String>>,
+    /// Print completions exactly as received, without stripping tags or whitespace
+    #[arg(long)]
+    pub raw: bool,
+    /// Strip surrounding whitespace from printed completions, the default
+    #[arg(long, global = true, overrides_with = "no_trim")]
+    pub trim: bool,
+    /// Print only the first line of the completion, like a single shell command
+    #[arg(long)]
+    pub first_line: bool,
+    /// Print completions exactly as received
+    #[arg(long, global = true, overrides_with = "trim")]
+    pub no_trim: bool,
+    /// Line endings of printed and exported completions
+    #[arg(long, global = true, default_value = "native")]
+    pub line_endings: LineEndings,
+    /// Cut printed completions to this many characters
+    #[arg(long, global = true)]
+    pub max_chars: Option<usize>,
+    /// Text put at the end of a completion cut short by --max-chars
+    #[arg(long, global = true, default_value = TRUNCATION_MARKER)]
+    pub truncation_marker: String,
+    #[cfg(feature = "clipboard")]
+    #[arg(long, global = true)]
+    pub copy: bool,
+    #[arg(short = 'q', long, global = true)]
+    pub quiet: bool,
+    /// Page the completion through WINSTON_PAGER, PAGER or less when writing to a terminal
+    #[arg(long, global = true)]
+    pub pager: bool,
+    #[arg(short = 'v', long, global = true)]
+    pub verbose: bool,
+    #[arg(long, global = true)]
+    pub show_usage: bool,
+    /// Write warnings and errors to stderr as JSON objects
+    #[arg(long, global = true)]
+    pub diagnostics_json: bool,
+}
+
+impl Options {
+    // parse the arguments, parsing them again without the values clap takes
+    // from environment variables when --no-env is given
+    pub fn try_parse_args(args: impl IntoIterator<Item = impl Into<OsString>>) -> Result<Self, clap::Error> {
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        let opts = Self::try_parse_from(&args)?;
+        if !opts.no_env {
+            return Ok(opts);
+        }
+        let command = Self::command().mut_args(|arg| arg.env(None));
+        Self::from_arg_matches(&command.try_get_matches_from(&args)?)
+    }
+}
+
+// split a NAME:VALUE header argument
+fn parse_header(s: &str) -> Result<(String, String), String> {
+    match s.split_once(':') {
+        Some((name, value)) if !name.trim().is_empty() => Ok((name.trim().to_string(), value.trim().to_string())),
+        _ => Err(format!("Invalid header `{}`, expected NAME:VALUE", s)),
+    }
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum Command {
+    /// Check that the endpoint is reachable and report round-trip latency
+    Ping {
+        #[arg(short = 'c', long, default_value_t = 4)]
+        count: u32,
+    },
+    /// Measure endpoint throughput by sending identical requests, --concurrency at a time
+    Bench {
+        #[arg(short = 'n', long, default_value_t = 10)]
+        requests: u32,
+        /// The prompt to send, read from stdin when not given
+        prompt: Vec<String>,
+    },
+    /// Send the same prompt to several models and print each reply
+    Compare {
+        /// Comma separated models to compare
+        #[arg(long, required = true, value_delimiter = ',')]
+        models: Vec<String>,
+        /// The prompt to send, read from stdin when not given
+        prompt: Vec<String>,
+    },
+    /// Send one prompt per line and print a JSON result per line
+    Batch {
+        /// File of prompts, read from stdin when not given
+        file: Option<PathBuf>,
+        /// Skip prompts that completed in an earlier run of the same batch
+        #[arg(long)]
+        resume: bool,
+        /// Forget earlier runs before starting, implies --resume
+        #[arg(long)]
+        restart: bool,
+        /// Where completed prompts are recorded for --resume
+        #[arg(long)]
+        state_dir: Option<PathBuf>,
+        /// Stop sending prompts after the first one fails and exit with an error
+        #[arg(long)]
+        fail_fast: bool,
+        /// Write each reply as soon as it arrives instead of in the order of the prompts
+        #[arg(long)]
+        unordered: bool,
+        /// Send a tiny request first so the model is loaded before the batch starts
+        #[arg(long)]
+        warmup: bool,
+    },
+    /// Send a tiny request so a local server loads the model, and report when it is ready
+    Warmup,
+    /// Check text with the moderations endpoint and print the flagged categories with their scores
+    Moderate {
+        /// The text to check, read from stdin when not given
+        text: Vec<String>,
+    },
+    /// Ask a question with the system prompt of a persona, like rust, sql or shell
+    Ask {
+        /// The persona, bundled or from the [personas] table of the config file
+        #[arg(long = "as", value_name = "PERSONA")]
+        persona: String,
+        /// The question, read from stdin when not given
+        prompt: Vec<String>,
+    },
+    /// Edit text from stdin following an instruction, printing only the edited text
+    Edit {
+        /// What to change, like "fix the grammar"
+        #[arg(required = true)]
+        instruction: Vec<String>,
+    },
+    /// Send a request template from the requests directory of the config
+    Run {
+        /// The name of the template, requests/<name>.toml in the config directory
+        template: String,
+        /// A value for a {{variable}} in the template, as name=value
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+    },
+    /// Start an interactive chat session
+    Repl {
+        /// Stop accepting prompts once the session has used this many tokens
+        #[arg(long)]
+        budget_tokens: Option<u32>,
+        /// Condense older turns into a summary once the conversation passes this many tokens
+        #[arg(long, value_name = "TOKENS", num_args = 0..=1, require_equals = true, default_missing_value = "4000")]
+        summarize_history: Option<u32>,
+        /// Save the conversation under this name, continuing it if it exists
+        #[arg(long)]
+        session: Option<String>,
+        /// Save macros defined with /def for later sessions
+        #[arg(long)]
+        save_macros: bool,
+        /// Print the tokens used so far and their cost after every reply
+        #[arg(long)]
+        usage_footer: bool,
+    },
+    /// Work with saved repl sessions
+    Session {
+        #[command(subcommand)]
+        action: SessionCommand,
+    },
+    /// Inspect or clear the response cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheCommand,
+    },
+    /// Write the version, platform, masked config and a test request to a file for a bug report
+    DebugBundle {
+        path: PathBuf,
+    },
+    /// Inspect or create the configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+}
+
+impl Command {
+    // the parameters a subcommand uses unless the config file or flags set
+    // them, edit keeps close to the text it is given
+    pub fn defaults(&self) -> Preset {
+        match self {
+            Command::Edit { .. } => Preset {
+                temperature: Some(0.2),
+                ..Preset::default()
+            },
+            _ => Preset::default(),
+        }
+    }
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum CacheCommand {
+    /// Print the cache directory, the number of entries and their total size
+    Info,
+    /// Remove cached replies
+    Clear {
+        /// Only remove replies cached longer ago than this, like 24h or 7d
+        #[arg(long, value_parser = crate::session::parse_duration)]
+        older_than: Option<Duration>,
+    },
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum SessionCommand {
+    /// List saved sessions, most recently modified first
+    List {
+        /// Only list sessions modified within this long, like 24h or 7d
+        #[arg(long, value_parser = crate::session::parse_duration)]
+        since: Option<Duration>,
+    },
+    /// Render a saved session as a document
+    Export {
+        name: String,
+        #[arg(long, default_value = "md")]
+        format: ExportFormat,
+        /// Write to this file instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum ConfigCommand {
+    /// Print the resolved value of a single configuration field
+    Get { field: String },
+    /// Print all resolved configuration values with the API key masked
+    List,
+    /// Print shell export lines for the resolved fields that environment variables can set
+    ExportEnv {
+        /// Print the API key instead of masking it
+        #[arg(long)]
+        include_secrets: bool,
+    },
+    /// Interactively create a config file
+    Wizard,
+    /// Save the API key in the system keyring, use it with openai_api_key = "keyring"
+    #[cfg(feature = "keyring")]
+    StoreKey {
+        /// Save the organization instead, used with openai_org_id = "keyring"
+        #[arg(long)]
+        org: bool,
+    },
 }
@@ -0,0 +1,41 @@
+// Winston clipboard support
+// With the clipboard feature enabled, --copy puts the completion on the system
+// clipboard. Headless machines and sessions without a clipboard are common, so
+// failing to reach the clipboard is a warning rather than an error.
+
+use crate::diagnostics::Diagnostic;
+use super::Result;
+
+// copy text to the system clipboard
+pub fn copy(text: &str) -> bool {
+    copy_with(text, |text| Ok(arboard::Clipboard::new()?.set_text(text)?))
+}
+
+// copy text using set_text, warning when the clipboard is unavailable.
+// Returns whether the text was copied.
+pub fn copy_with(text: &str, set_text: impl FnOnce(&str) -> Result<()>) -> bool {
+    match set_text(text) {
+        Ok(()) => true,
+        Err(e) => {
+            Diagnostic::warning("clipboard_unavailable", format!("could not copy to the clipboard: {}", e)).emit();
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // test that the clipboard write is attempted with the completion
+    #[test]
+    fn copy_attempted_test() {
+        let mut copied = None;
+        assert!(copy_with("completion", |text| {
+            copied = Some(text.to_string());
+            Ok(())
+        }));
+        assert_eq!(copied.as_deref(), Some("completion"));
+        assert!(!copy_with("completion", |_| Err("no clipboard".into())));
+    }
+}
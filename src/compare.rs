@@ -0,0 +1,117 @@
+// Winston compare subcommand
+// Compare sends the same prompt to several models at once from one client and
+// prints each reply under a heading with the model name, ruled out to the
+// width of the terminal, followed by the
+// token usage it reported. A model that fails shows its error in place of a
+// reply, so the others are still compared.
+
+use std::fmt::Write;
+use std::thread;
+
+use crate::chat::{api_for_model, ChatRequest, Usage};
+use crate::client::Client;
+use crate::config::ApiKind;
+use crate::term;
+
+#[derive(Debug)]
+pub struct ModelReply {
+    pub model: String,
+    // the completion and its usage, or the error message
+    pub reply: std::result::Result<(String, Option<Usage>), String>,
+}
+
+// send request to each model concurrently, returning the replies in the order
+// of the models
+pub fn run(client: &Client, request: &ChatRequest, models: &[String], api: Option<ApiKind>) -> Vec<ModelReply> {
+    thread::scope(|scope| {
+        let handles: Vec<_> = models
+            .iter()
+            .map(|model| {
+                scope.spawn(move || {
+                    let mut request = request.clone();
+                    request.model = model.clone();
+                    request.api = api.unwrap_or_else(|| api_for_model(model));
+                    let reply = client
+                        .chat(&request)
+                        .map(|response| (response.content().unwrap_or_default().to_string(), response.usage))
+                        .map_err(|e| e.to_string());
+                    ModelReply {
+                        model: model.clone(),
+                        reply,
+                    }
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+impl ModelReply {
+    // the reply under its heading, ruled out to width
+    pub fn render(&self, width: usize) -> String {
+        let mut rendered = term::heading(&self.model, width);
+        rendered.push('\n');
+        let _ = match &self.reply {
+            Ok((content, usage)) => {
+                let _ = writeln!(rendered, "{}", content.trim());
+                match usage {
+                    Some(usage) => write!(rendered, "{}", usage),
+                    None => write!(rendered, "usage: not reported"),
+                }
+            }
+            Err(e) => write!(rendered, "error: {}", e),
+        };
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chat::{Message, Role};
+    use crate::config::WinstonConfigBuilder;
+
+    // test that each model gets the prompt and its reply is labelled with it
+    #[test]
+    fn compare_models_test() {
+        let mut server = mockito::Server::new();
+        let mocks: Vec<_> = [("gpt-4o", "Paris", 12), ("gpt-4o-mini", "Paris, France", 14)]
+            .iter()
+            .map(|(model, content, total)| {
+                server
+                    .mock("POST", "/v1/chat/completions")
+                    .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                        "model": model,
+                        "messages": [{"role": "user", "content": "Capital of France?"}],
+                    })))
+                    .with_status(200)
+                    .with_body(
+                        serde_json::json!({
+                            "choices": [{"message": {"role": "assistant", "content": content}}],
+                            "usage": {"prompt_tokens": 10, "completion_tokens": total - 10, "total_tokens": total},
+                        })
+                        .to_string(),
+                    )
+                    .expect(1)
+                    .create()
+            })
+            .collect();
+
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .build()
+            .unwrap();
+        let client = Client::from_config(&config).unwrap();
+        let request = ChatRequest::new(&config, vec![Message::new(Role::User, "Capital of France?")]);
+        let models = ["gpt-4o".to_string(), "gpt-4o-mini".to_string(), "missing".to_string()];
+        let replies = run(&client, &request, &models, None);
+
+        for mock in mocks {
+            mock.assert();
+        }
+        assert_eq!(replies[0].render(16), "== gpt-4o ======\nParis\nusage: 10 prompt + 2 completion = 12 tokens");
+        assert_eq!(replies[1].render(16), "== gpt-4o-mini ==\nParis, France\nusage: 10 prompt + 4 completion = 14 tokens");
+        assert!(replies[2].render(16).starts_with("== missing =====\nerror: "));
+    }
+}
@@ -10,169 +10,1485 @@
 // file located at XDG_CONFIG_HOME/winston/config.toml, if XDG_CONFIG_HOME is not
 // set, it will check $HOME/.config/winston/config.toml. If no key is found, winston
 // will exit with a KeyNotFound error.
+// The config file may also be JSON or YAML, detected from a .json, .yaml or .yml
+// extension or given with --config-format, and is saved in the same format.
+// A config file holding an API key that group or others can read is warned
+// about, or refused with --strict-permissions.
+// A system-wide config file, /etc/winston/config.toml or on Windows
+// %ProgramData%\winston\config.toml, is merged beneath the user's the same way,
+// so an admin can set defaults for everyone that each user may override. It is
+// skipped with --no-system-config.
 
+use std::collections::BTreeMap;
+use std::fmt;
 use std::io::Write;
-use std::path::PathBuf;
-use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use serde::{Deserialize, Deserializer, Serialize};
 
+use crate::chat::api_for_model;
+use crate::cli::{Command, Options};
+use crate::diagnostics::Diagnostic;
+use crate::error::WinstonError;
+use crate::lang::language;
+use crate::prompt_file::PromptFile;
+use crate::request_template::{self, RequestTemplate};
+use crate::session::parse_duration;
 use super::Result;
 
 pub const OPENAI_ENDPOINT: &str = "https://api.openai.com";
-#[allow(dead_code)]
 pub const OPENAI_CHAT: &str = "/v1/chat/completions";
-pub const MODEL: &str = "davinci";
-pub const MAX_TOKENS: u32 = 64;
+pub const OPENAI_COMPLETIONS: &str = "/v1/completions";
+pub const OPENAI_MODELS: &str = "/v1/models";
+pub const OPENAI_MODERATIONS: &str = "/v1/moderations";
+// the model used when none is configured and OPENAI_DEFAULT_MODEL is unset
+pub const MODEL: &str = "gpt-4o-mini";
+pub const DEFAULT_MODEL_ENV: &str = "OPENAI_DEFAULT_MODEL";
 pub const TEMPERATURE: f32 = 0.9;
 pub const TOP_P: f32 = 1.0;
 pub const FREQUENCY_PENALTY: f32 = 0.0;
 pub const PRESENCE_PENALTY: f32 = 0.0;
+// the default stop of one-line legacy completions, chat replies get none
 pub const STOP: &str = "\n";
+pub const AZURE_API_VERSION: &str = "2024-06-01";
+pub const ANTHROPIC_MESSAGES: &str = "/v1/messages";
+pub const ANTHROPIC_VERSION: &str = "2023-06-01";
+pub const REASONING_EFFORTS: &[&str] = &["low", "medium", "high"];
+pub const MAX_RETRIES: u32 = 2;
+// the delay before the first retry, doubled for each retry after it
+pub const RETRY_DELAY_MS: u64 = 250;
+// the largest random fraction added to a retry delay unless configured
+// otherwise, none so retries are evenly spaced
+pub const RETRY_JITTER: f64 = 0.0;
+// requests a client has in flight at once unless configured otherwise
+pub const MAX_CONCURRENCY: u32 = 4;
+// estimated prompt tokens above which a warning is printed before sending
+pub const WARN_TOKENS: u32 = 32_000;
+// the largest request body sent, well above any real prompt
+pub const MAX_REQUEST_BYTES: usize = 8 * 1024 * 1024;
+// the longest Retry-After in seconds waited for unless configured otherwise
+pub const MAX_RETRY_AFTER: u64 = 60;
+// where OpenAI compatible APIs put the completion, as a JSON pointer
+pub const RESPONSE_CONTENT_PATH: &str = "/choices/0/message/content";
+// headers winston sets itself, which would leak or replace the credentials if
+// they could be configured
+pub const RESERVED_HEADERS: &[&str] = &["authorization", "api-key", "x-api-key", "openai-organization"];
+
+// the model to use when none is configured anywhere, taken from the
+// deployment's OPENAI_DEFAULT_MODEL when it is set
+pub fn default_model(env: Option<String>) -> String {
+    env.filter(|model| !model.trim().is_empty())
+        .unwrap_or_else(|| MODEL.to_string())
+}
+
+// mask the values of the headers in a config table, they often hold tokens
+pub fn mask_headers(table: &mut toml::Table) {
+    if let Some(toml::Value::Table(headers)) = table.get_mut("headers") {
+        for (_, value) in headers.iter_mut() {
+            if let toml::Value::String(value) = value {
+                *value = mask_key(value);
+            }
+        }
+    }
+}
+
+// the default config file location, XDG_CONFIG_HOME/winston/config.toml
+pub fn default_config_path() -> Result<PathBuf> {
+    let mut fp = dirs::config_dir().ok_or("Could not find config directory")?;
+    fp.push("winston");
+    fp.push("config.toml");
+    Ok(fp)
+}
+
+// the system-wide config file, beneath the user's in precedence
+pub fn system_config_path() -> PathBuf {
+    if cfg!(windows) {
+        let mut fp = PathBuf::from(std::env::var_os("ProgramData").unwrap_or_else(|| "C:\\ProgramData".into()));
+        fp.push("winston");
+        fp.push("config.toml");
+        fp
+    } else {
+        PathBuf::from("/etc/winston/config.toml")
+    }
+}
+
+// mask an API key for display, keeping only enough to recognize it
+pub fn mask_key(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() <= 8 {
+        return "*".repeat(chars.len());
+    }
+    let head: String = chars[..3].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}...{}", head, tail)
+}
+
+// where the value of a config field came from, for --explain-config
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    Default,
+    System,
+    File,
+    Endpoint,
+    Preset,
+    PromptFile,
+    Template,
+    Command,
+    Env,
+    Flag,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Source::Default => "default",
+            Source::System => "system file",
+            Source::File => "file",
+            Source::Endpoint => "endpoint",
+            Source::Preset => "preset",
+            Source::PromptFile => "prompt file",
+            Source::Template => "template",
+            Source::Command => "command",
+            Source::Env => "env",
+            Source::Flag => "flag",
+        })
+    }
+}
+
+// the options clap fills from an environment variable when the flag isn't given
+const ENV_FIELDS: &[(&str, &str)] = &[
+    ("openai_api_key", "OPENAI_API_KEY"),
+    ("openai_org_id", "OPENAI_ORG_ID"),
+    ("model", "OPENAI_MODEL"),
+];
+
+// the source of each field that isn't a default, found by comparing the
+// builder before and after each layer is applied
+#[derive(Debug)]
+pub struct Sources {
+    last: serde_json::Value,
+    sources: BTreeMap<String, Source>,
+}
+
+impl Sources {
+    fn new(builder: &WinstonConfigBuilder) -> Result<Self> {
+        Ok(Self {
+            last: serde_json::to_value(builder)?,
+            sources: BTreeMap::new(),
+        })
+    }
+
+    // credit the fields the last layer changed to source
+    fn layer(&mut self, builder: &WinstonConfigBuilder, source: Source) -> Result<()> {
+        let now = serde_json::to_value(builder)?;
+        for (field, value) in now.as_object().into_iter().flatten() {
+            if self.last.get(field) != Some(value) {
+                // a flag left at a value clap took from the environment
+                let env = ENV_FIELDS
+                    .iter()
+                    .find(|(name, _)| *name == field)
+                    .and_then(|(_, var)| std::env::var(var).ok())
+                    .is_some_and(|env| *value == serde_json::Value::String(env));
+                let source = if source == Source::Flag && env { Source::Env } else { source };
+                self.sources.insert(field.clone(), source);
+            }
+        }
+        self.last = now;
+        Ok(())
+    }
+
+    // where a field's value came from
+    pub fn get(&self, field: &str) -> Source {
+        self.sources.get(field).copied().unwrap_or(Source::Default)
+    }
+}
+
+// resolve_config merges every configuration source into a WinstonConfig.
+// Command line options and environment variables (already merged by clap) take
+// precedence over the config file, which takes precedence over the system
+// config file and then the defaults. The config file given with --config must
+// exist, the default one and the system one are optional.
+pub fn resolve_config(opts: &Options) -> Result<WinstonConfig> {
+    Ok(resolve_config_sources(opts)?.0)
+}
+
+// resolve the config, recording which source each field came from
+pub fn resolve_config_sources(opts: &Options) -> Result<(WinstonConfig, Sources)> {
+    let system_config = (!opts.no_system_config).then(system_config_path);
+    resolve_config_layers(opts, system_config.as_deref())
+}
+
+// resolve the config over the system config file at system_config, when given
+fn resolve_config_layers(opts: &Options, system_config: Option<&Path>) -> Result<(WinstonConfig, Sources)> {
+    let mut builder = WinstonConfigBuilder::new().strict_permissions(opts.strict_permissions).no_env(opts.no_env);
+    let mut sources = Sources::new(&builder)?;
+    if let Some(fp) = system_config.filter(|fp| fp.exists()) {
+        builder = builder.load_config(fp)?;
+    }
+    sources.layer(&builder, Source::System)?;
+    match &opts.config_file {
+        Some(fp) => builder = builder.load_config_as(fp, ConfigFormat::of(fp, opts.config_format))?,
+        None => {
+            let fp = default_config_path()?;
+            if fp.exists() {
+                builder = builder.load_config(&fp)?;
+            }
+        }
+    }
+    sources.layer(&builder, Source::File)?;
+
+    // a named endpoint layers over the rest of the config file
+    if let Some(name) = opts.endpoint.clone().or_else(|| builder.default_endpoint.clone()) {
+        builder = builder.select_endpoint(&name)?;
+    }
+    sources.layer(&builder, Source::Endpoint)?;
+    // a preset layers over the config file and below the flags, the default
+    // one only when --preset isn't given and --no-preset isn't either
+    let preset = match opts.no_preset {
+        true => None,
+        false => opts.preset.clone().or_else(|| builder.default_preset.clone()),
+    };
+    if let Some(name) = &preset {
+        builder = builder.select_preset(name)?;
+    }
+    sources.layer(&builder, Source::Preset)?;
+    // and the front-matter of a prompt file over the preset
+    if let Some(fp) = &opts.prompt_file {
+        builder = builder.apply_preset(PromptFile::load(fp, opts.input_encoding)?.params);
+    }
+    sources.layer(&builder, Source::PromptFile)?;
+    // a request template's parameters likewise
+    if let Some(Command::Run { template, .. }) = &opts.command {
+        let fp = request_template::template_file(&request_template::requests_dir()?, template)?;
+        builder = builder.apply_preset(RequestTemplate::load(&fp)?.params);
+    }
+    sources.layer(&builder, Source::Template)?;
+    // the subcommand's own defaults fill in what none of those set
+    if let Some(command) = &opts.command {
+        builder = builder.apply_defaults(command.defaults());
+    }
+    sources.layer(&builder, Source::Command)?;
+
+    if !opts.openai_api_key.is_empty() {
+        builder = builder.openai_api_key(opts.openai_api_key.clone());
+    }
+    if let Some(openai_org_id) = &opts.openai_org_id {
+        builder = builder.openai_org_id(openai_org_id.clone());
+    }
+    if let Some(api_endpoint) = &opts.openai_endpoint {
+        builder = builder.api_endpoint(api_endpoint.clone());
+    }
+    if let Some(model) = &opts.openai_model {
+        builder = builder.model(model.clone());
+    }
+    if let Some(max_tokens) = opts.openai_max_tokens {
+        builder = builder.max_tokens(max_tokens);
+    }
+    if let Some(temperature) = opts.openai_temperature {
+        builder = builder.temperature(temperature);
+    }
+    if let Some(top_p) = opts.openai_top_p {
+        builder = builder.top_p(top_p);
+    }
+    if let Some(frequency_penalty) = &opts.frequency_penalty {
+        builder = builder.frequency_penalty(frequency_penalty.clone());
+    }
+    if let Some(presence_penalty) = &opts.presence_penalty {
+        builder = builder.presence_penalty(presence_penalty.clone());
+    }
+    // --stop may be repeated for several sequences, any of them none clears them
+    let stops: Option<Vec<String>> = opts
+        .stop
+        .iter()
+        .map(|stop| match stop {
+            Param::Value(stop) => Some(stop.clone()),
+            Param::Disabled => None,
+        })
+        .collect();
+    match stops {
+        None => builder = builder.stop(Param::Disabled),
+        Some(stops) if stops.len() == 1 => builder = builder.stop(Stop::One(stops[0].clone())),
+        Some(stops) if !stops.is_empty() => builder = builder.stop(Stop::Many(stops)),
+        Some(_) => {}
+    }
+    if let Some(timeout) = opts.timeout {
+        builder = builder.timeout(timeout);
+    }
+    if let Some(deadline) = &opts.deadline {
+        builder = builder.deadline(deadline.clone());
+    }
+    if let Some(max_retry_after) = opts.max_retry_after {
+        builder = builder.max_retry_after(max_retry_after);
+    }
+    if opts.fail_on_retry_after {
+        builder = builder.fail_on_retry_after(true);
+    }
+    if let Some(retry_jitter) = opts.retry_jitter {
+        builder = builder.retry_jitter(retry_jitter);
+    }
+    if opts.no_org_header {
+        builder = builder.no_org_header(true);
+    }
+    if let Some(min_p) = opts.min_p {
+        builder = builder.min_p(min_p);
+    }
+    if let Some(top_k) = opts.top_k {
+        builder = builder.top_k(top_k);
+    }
+    if let Some(repeat_penalty) = opts.repeat_penalty {
+        builder = builder.repeat_penalty(repeat_penalty);
+    }
+    if !opts.fallback_models.is_empty() {
+        builder = builder.fallback_models(opts.fallback_models.clone());
+    }
+    if let Some(reasoning_effort) = &opts.reasoning_effort {
+        builder = builder.reasoning_effort(reasoning_effort.clone());
+    }
+    if let Some(flavor) = opts.flavor {
+        builder = builder.flavor(flavor);
+    }
+    if let Some(refusal_pattern) = &opts.refusal_pattern {
+        builder = builder.refusal_pattern(refusal_pattern.clone());
+    }
+    if let Some(assistant_label) = &opts.assistant_label {
+        builder = builder.assistant_label(assistant_label.clone());
+    }
+    if let Some(api) = opts.api {
+        builder = builder.api(api);
+    }
+    if let Some(system) = &opts.system {
+        builder = builder.system(system.clone());
+    }
+    if opts.no_system {
+        builder = builder.system(Param::Disabled);
+    }
+    if let Some(lang) = &opts.lang {
+        builder = builder.lang(lang.clone());
+    }
+    if opts.no_lang {
+        builder = builder.lang(Param::Disabled);
+    }
+    if let Some(max_retries) = opts.max_retries {
+        builder = builder.max_retries(max_retries);
+    }
+    if let Some(concurrency) = opts.concurrency {
+        builder = builder.max_concurrency(concurrency);
+    }
+    for (name, value) in &opts.header {
+        builder = builder.header(name.clone(), value.clone());
+    }
+    sources.layer(&builder, Source::Flag)?;
+
+    let defaulted = builder.model.is_none();
+    let config = builder.build()?;
+    if defaulted && !opts.no_env && std::env::var(DEFAULT_MODEL_ENV).is_ok_and(|model| !model.trim().is_empty()) {
+        sources.sources.insert("model".to_string(), Source::Env);
+    }
+    if defaulted && opts.verbose {
+        Diagnostic::info("default_model", format!("no model configured, using {}", config.model))
+            .with("model", &config.model)
+            .emit();
+    }
+    Ok((config, sources))
+}
+
+// the format of a config file, from --config-format or the file extension,
+// TOML unless the extension is .json, .yaml or .yml
+#[derive(Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ConfigFormat {
+    #[default]
+    Toml,
+    Json,
+    Yaml,
+}
+
+impl ConfigFormat {
+    pub fn of(fp: &Path, format: Option<ConfigFormat>) -> Self {
+        if let Some(format) = format {
+            return format;
+        }
+        match fp.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => ConfigFormat::Json,
+            Some("yaml" | "yml") => ConfigFormat::Yaml,
+            _ => ConfigFormat::Toml,
+        }
+    }
+
+    // a config table written out in this format
+    pub fn serialize(&self, table: &toml::Table) -> Result<String> {
+        Ok(match self {
+            ConfigFormat::Toml => toml::to_string(table)?,
+            ConfigFormat::Json => serde_json::to_string_pretty(table)? + "\n",
+            ConfigFormat::Yaml => serde_yaml::to_string(table)?,
+        })
+    }
+}
+
+// the mode of a file that group or others can read, never on other platforms
+#[cfg(unix)]
+fn loose_permissions(fp: &Path) -> Result<Option<u32>> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = std::fs::metadata(fp)?.permissions().mode() & 0o777;
+    Ok((mode & 0o044 != 0).then_some(mode))
+}
+
+#[cfg(not(unix))]
+fn loose_permissions(_fp: &Path) -> Result<Option<u32>> {
+    Ok(None)
+}
+
+// the shape of the API behind an endpoint
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiFlavor {
+    #[default]
+    #[value(name = "openai")]
+    #[serde(rename = "openai")]
+    OpenAi,
+    // Azure OpenAI addresses models by deployment and authenticates with an
+    // api-key header
+    Azure,
+    // the Anthropic messages API, authenticated with an x-api-key header
+    Anthropic,
+}
+
+// the request shape, chat models take a list of messages while legacy models
+// take a single prompt
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiKind {
+    #[default]
+    Chat,
+    Completions,
+}
+
+// one API key or a list of keys that requests rotate through
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ApiKey {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl ApiKey {
+    pub fn keys(&self) -> &[String] {
+        match self {
+            ApiKey::One(key) => std::slice::from_ref(key),
+            ApiKey::Many(keys) => keys,
+        }
+    }
+
+    pub fn first(&self) -> Option<&str> {
+        self.keys().first().map(String::as_str)
+    }
+}
+
+impl From<String> for ApiKey {
+    fn from(key: String) -> Self {
+        ApiKey::One(key)
+    }
+}
+
+impl From<Vec<String>> for ApiKey {
+    fn from(mut keys: Vec<String>) -> Self {
+        if keys.len() == 1 {
+            ApiKey::One(keys.remove(0))
+        } else {
+            ApiKey::Many(keys)
+        }
+    }
+}
+
+// a named backend from an [endpoints.<name>] section of the config file
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Endpoint {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_endpoint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub openai_api_key: Option<ApiKey>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub openai_org_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flavor: Option<ApiFlavor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub no_org_header: Option<bool>,
+}
+
+// a named bundle of parameters from a [presets.<name>] section of the config
+// file, applied with --preset
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Preset {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<Param<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<Param<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Param<Stop>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<String>,
+}
+
+// the most stop sequences the API accepts
+pub const MAX_STOP_SEQUENCES: usize = 4;
+
+// one stop sequence or a list of them, sent in the same shape as configured.
+// Sequences are sent exactly as given, special tokens and control characters
+// included.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Stop {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Stop {
+    pub fn sequences(&self) -> Vec<&str> {
+        match self {
+            Stop::One(stop) => vec![stop.as_str()],
+            Stop::Many(stops) => stops.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+impl From<String> for Stop {
+    fn from(stop: String) -> Self {
+        Stop::One(stop)
+    }
+}
+
+impl From<Vec<String>> for Stop {
+    fn from(stops: Vec<String>) -> Self {
+        Stop::Many(stops)
+    }
+}
+
+// the sentinel that disables a parameter instead of setting a value
+pub const NONE_SENTINEL: &str = "none";
+
+// A parameter that can be disabled with the "none" sentinel, either on the
+// command line or in the config file. A disabled parameter is left out of the
+// request body entirely rather than falling back to its default, since some
+// gateways treat an omitted parameter differently from a zero value.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Param<T> {
+    #[serde(deserialize_with = "deserialize_sentinel")]
+    Disabled,
+    Value(T),
+}
+
+impl<T> Param<T> {
+    // resolve a possibly unset parameter, applying the default when unset
+    pub fn resolve(param: Option<Self>, default: T) -> Option<T> {
+        match param {
+            None => Some(default),
+            Some(Param::Value(value)) => Some(value),
+            Some(Param::Disabled) => None,
+        }
+    }
+}
+
+impl<T: Serialize> Serialize for Param<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            Param::Disabled => serializer.serialize_str(NONE_SENTINEL),
+            Param::Value(value) => value.serialize(serializer),
+        }
+    }
+}
+
+impl<T> From<T> for Param<T> {
+    fn from(value: T) -> Self {
+        Param::Value(value)
+    }
+}
+
+impl<T: FromStr> FromStr for Param<T> {
+    type Err = T::Err;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case(NONE_SENTINEL) {
+            Ok(Param::Disabled)
+        } else {
+            s.parse().map(Param::Value)
+        }
+    }
+}
+
+fn deserialize_sentinel<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<(), D::Error> {
+    let value = String::deserialize(deserializer)?;
+    if value.eq_ignore_ascii_case(NONE_SENTINEL) {
+        Ok(())
+    } else {
+        Err(serde::de::Error::custom("not the none sentinel"))
+    }
+}
+
+// names of the fields of WinstonConfig as used in the config file, along with
+// a description of the type each one expects
+pub const FIELDS: &[(&str, &str)] = &[
+    ("openai_org_id", "a string"),
+    ("openai_api_key", "a string or an array of strings"),
+    ("api_endpoint", "a string"),
+    ("model", "a string"),
+    ("max_tokens", "an integer"),
+    ("temperature", "a number"),
+    ("top_p", "a number"),
+    ("frequency_penalty", "a number or \"none\""),
+    ("presence_penalty", "a number or \"none\""),
+    ("stop", "a string, an array of up to four strings or \"none\""),
+    ("timeout", "an integer"),
+    ("reasoning_effort", "one of low, medium, high"),
+    ("flavor", "\"openai\", \"azure\" or \"anthropic\""),
+    ("default_endpoint", "a string"),
+    ("endpoints", "a table of endpoints"),
+    ("refusal_pattern", "a string"),
+    ("summary_model", "a string"),
+    ("assistant_label", "a string"),
+    ("api", "\"chat\" or \"completions\""),
+    ("max_retries", "an integer"),
+    ("presets", "a table of presets"),
+    ("default_preset", "a string"),
+    ("system", "a string or \"none\""),
+    ("max_concurrency", "an integer"),
+    ("headers", "a table of strings"),
+    ("warn_tokens", "an integer"),
+    ("personas", "a table of strings"),
+    ("max_request_bytes", "an integer"),
+    ("lang", "an ISO 639-1 code or \"none\""),
+    ("response_content_path", "a JSON pointer string"),
+    ("deadline", "a duration like \"30s\""),
+    ("max_retry_after", "an integer"),
+    ("fail_on_retry_after", "a boolean"),
+    ("fallback_models", "an array of strings"),
+    ("no_org_header", "a boolean"),
+    ("min_p", "a number"),
+    ("top_k", "an integer"),
+    ("repeat_penalty", "a number"),
+    ("retry_jitter", "a number from 0 to 1"),
+];
+
+// explain a config file that doesn't deserialize, naming the offending field
+// and the type it expects along with the line it is on when toml knows it
+fn config_error(source: &str, err: toml::de::Error) -> String {
+    // syntax errors are already as precise as they can be
+    let Ok(table) = source.parse::<toml::Table>() else {
+        return err.to_string();
+    };
+    let line = err.span().map(|span| source[..span.start].matches('\n').count() + 1);
+    let key = line
+        .and_then(|line| source.lines().nth(line - 1))
+        .and_then(|text| text.split_once('='))
+        .map(|(key, _)| key.trim().trim_matches('"'));
+    // without a usable span, find the first field that fails on its own
+    let invalid = |name: &str| {
+        table.get(name).is_some_and(|value| {
+            toml::Table::from_iter([(name.to_string(), value.clone())])
+                .try_into::<WinstonConfigBuilder>()
+                .is_err()
+        })
+    };
+    let field = FIELDS
+        .iter()
+        .find(|(name, _)| key == Some(*name))
+        .or_else(|| FIELDS.iter().find(|(name, _)| invalid(name)));
+
+    match field.and_then(|(name, expected)| Some((name, expected, table.get(*name)?))) {
+        Some((name, expected, value)) => {
+            let mut message = format!("`{}` must be {}, found {}", name, expected, value.type_str());
+            if let Some(line) = line {
+                message.push_str(&format!(" (line {})", line));
+            }
+            message
+        }
+        None => err.message().to_string(),
+    }
+}
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct WinstonConfig {
-    pub openai_org_id: String,
-    pub openai_api_key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub openai_org_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub openai_api_key: Option<ApiKey>,
     pub api_endpoint: String,
     pub model: String,
-    pub max_tokens: u32,
+    // unset by default so chat models may use their remaining context
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
     pub temperature: f32,
     pub top_p: f32,
-    pub frequency_penalty: f32,
-    pub presence_penalty: f32,
-    pub stop: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Stop>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<String>,
+    pub flavor: ApiFlavor,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_endpoint: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub endpoints: BTreeMap<String, Endpoint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refusal_pattern: Option<String>,
+    // the model that condenses repl history, the main model when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary_model: Option<String>,
+    // the name replies are labelled with, the role name when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assistant_label: Option<String>,
+    // inferred from the model when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api: Option<ApiKind>,
+    // retries of requests that failed with a transient connection error
+    pub max_retries: u32,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub presets: BTreeMap<String, Preset>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_preset: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    // requests a client sends at once, shared by batch and bench
+    pub max_concurrency: u32,
+    // extra headers sent with every request, keyed by lowercase name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    // warn about prompts estimated at more tokens than this, 0 never warns
+    pub warn_tokens: u32,
+    // system prompts for ask --as, over the bundled ones
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub personas: BTreeMap<String, String>,
+    // requests whose body is larger than this are refused before sending
+    pub max_request_bytes: usize,
+    // the language replies are asked for in, as an ISO 639-1 code
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    // JSON pointer to the completion in responses of a non-standard API, the
+    // flavor's own response shape when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_content_path: Option<String>,
+    // the longest a request may take with its retries, like 30s
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<String>,
+    // the longest Retry-After in seconds waited for before retrying
+    pub max_retry_after: u64,
+    // fail instead of waiting max_retry_after when the server asks for longer
+    pub fail_on_retry_after: bool,
+    // the models tried in turn when the model is unavailable
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fallback_models: Vec<String>,
+    // leave out the organization header for gateways that reject it
+    pub no_org_header: bool,
+    // sampling parameters of local servers like llama.cpp and vLLM, only sent
+    // when set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat_penalty: Option<f32>,
+    // the largest fraction of a retry delay added to it at random
+    pub retry_jitter: f64,
 }
 
 #[allow(dead_code)]
 impl WinstonConfig {
     // save configuration to provided filepath or the default config file location
-    pub fn save_config(self, fp: Option<PathBuf>) -> Result<()> {
+    pub fn save_config(self, fp: Option<PathBuf>, format: Option<ConfigFormat>) -> Result<()> {
         // check if provided filepath is valid
         let fp = match fp {
             Some(fp) => fp,
-            None => {
-                let mut fp = dirs::config_dir().ok_or("Could not find config directory")?;
-                fp.push("winston");
-                fp.push("config.toml");
-                fp
-            }
+            None => default_config_path()?,
         };
-        // write config to file
-        let config = toml::to_string(&self)?;
-        let mut file = std::fs::File::create(fp)?;
+        // create the config directory on first save
+        if let Some(dir) = fp.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Could not create config directory {}: {}", dir.display(), e))?;
+        }
+        // write config to file, in the format it would be loaded in
+        let config = ConfigFormat::of(&fp, format).serialize(&self.table()?)?;
+        let mut file = std::fs::File::create(&fp)
+            .map_err(|e| format!("Could not write config file {}: {}", fp.display(), e))?;
+        // the file may hold the API key, so only its owner may read it
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        }
         file.write_all(config.as_bytes())?;
 
         Ok(())
     }
-    // load configuration from provided filepath or the default config file location
-    pub fn load_config(fp: Option<PathBuf>) -> Result<Self> {
-        let fp = match fp {
-            Some(fp) => fp,
-            None => {
-                let mut fp = dirs::config_dir().ok_or("Could not find config directory")?;
-                fp.push("winston");
-                fp.push("config.toml");
-                fp
-            }
-        };
-        let v2 = WinstonConfigBuilder::new().load_config(&fp).build()?;
+    // load configuration from provided filepath or the default config file location
+    pub fn load_config(fp: Option<PathBuf>) -> Result<Self> {
+        let fp = match fp {
+            Some(fp) => fp,
+            None => default_config_path()?,
+        };
+        let v2 = WinstonConfigBuilder::new().load_config(&fp)?.build()?;
+
+        Ok(v2)
+    }
+
+    // the longest a request may take with its retries
+    pub fn deadline(&self) -> Result<Option<Duration>> {
+        Ok(self.deadline.as_deref().map(parse_duration).transpose()?)
+    }
+
+    // the API keys, at least one is required before any request can be made
+    pub fn api_keys(&self) -> Result<&[String]> {
+        match self.openai_api_key.as_ref().map(ApiKey::keys) {
+            Some(keys) if !keys.is_empty() => Ok(keys),
+            _ => Err("KeyNotFound: no OpenAI API key was provided".into()),
+        }
+    }
+
+    // the first API key
+    pub fn api_key(&self) -> Result<&str> {
+        Ok(&self.api_keys()?[0])
+    }
+
+    // the configuration as a TOML table. Parameters are f32 and would print
+    // their widened f64 value, like 0.8999999761581421 for 0.9, so floats are
+    // replaced with the shortest decimal that reads back as the same f32.
+    fn table(&self) -> Result<toml::Table> {
+        fn round(value: &mut toml::Value) {
+            match value {
+                toml::Value::Float(float) => {
+                    if let Ok(rounded) = (*float as f32).to_string().parse() {
+                        *float = rounded;
+                    }
+                }
+                toml::Value::Table(table) => table.iter_mut().for_each(|(_, value)| round(value)),
+                toml::Value::Array(array) => array.iter_mut().for_each(round),
+                _ => {}
+            }
+        }
+        let mut table = toml::Table::try_from(self)?;
+        table.iter_mut().for_each(|(_, value)| round(value));
+        Ok(table)
+    }
+
+    // the configuration as a TOML table with the API keys masked
+    pub fn masked_table(&self) -> Result<toml::Table> {
+        let mut table = self.table()?;
+        let mask = |table: &mut toml::Table| {
+            match table.get_mut("openai_api_key") {
+                Some(toml::Value::String(key)) => *key = mask_key(key),
+                Some(toml::Value::Array(keys)) => {
+                    for key in keys.iter_mut() {
+                        if let toml::Value::String(key) = key {
+                            *key = mask_key(key);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        };
+        mask(&mut table);
+        if let Some(toml::Value::Table(endpoints)) = table.get_mut("endpoints") {
+            for (_, endpoint) in endpoints.iter_mut() {
+                if let toml::Value::Table(endpoint) = endpoint {
+                    mask(endpoint);
+                }
+            }
+        }
+        Ok(table)
+    }
+
+    // get the value of a single named field, formatted for use in scripts.
+    // Unset optional fields print as an empty string.
+    pub fn get(&self, field: &str) -> Result<String> {
+        if !FIELDS.iter().any(|(name, _)| *name == field) {
+            return Err(format!("Unknown config field `{}`", field).into());
+        }
+        let table = self.table()?;
+        let value = match table.get(field) {
+            Some(toml::Value::String(value)) => value.clone(),
+            Some(value) => value.to_string(),
+            None => String::new(),
+        };
+
+        Ok(value)
+    }
+
+    // list every resolved field as TOML, with the API key masked
+    pub fn list(&self) -> Result<String> {
+        Ok(toml::to_string(&self.masked_table()?)?)
+    }
+
+    // a line per config field with its value and where it came from, with the
+    // API keys and header values masked
+    pub fn explain(&self, sources: &Sources) -> Result<String> {
+        let mut table = self.masked_table()?;
+        mask_headers(&mut table);
+        let width = FIELDS.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        let mut explained = String::new();
+        for (name, _) in FIELDS {
+            let source = sources.get(name);
+            let value = match table.get(*name) {
+                // escaped so a value like a newline stop sequence stays on its line
+                Some(toml::Value::String(value)) => format!("{:?}", value),
+                Some(value) => value.to_string(),
+                // a field unset by one of the sources was disabled there
+                None if source != Source::Default => NONE_SENTINEL.to_string(),
+                None => "unset".to_string(),
+            };
+            explained.push_str(&format!("{:<width$}  {}  ({})\n", name, value, source, width = width));
+        }
+        Ok(explained)
+    }
+
+    // one line per preset with the parameters it sets
+    pub fn list_presets(&self) -> Result<String> {
+        let mut list = String::new();
+        let table = self.table()?;
+        let Some(presets) = table.get("presets").and_then(toml::Value::as_table) else {
+            return Ok(list);
+        };
+        for (name, preset) in presets {
+            let values: Vec<String> = preset
+                .as_table()
+                .into_iter()
+                .flatten()
+                .map(|(key, value)| format!("{} = {}", key, value))
+                .collect();
+            list.push_str(&format!("{}: {}\n", name, values.join(", ")));
+        }
+        Ok(list)
+    }
+
+    // an export line for every set field that an environment variable can
+    // set, with the API key masked unless include_secrets is set. The
+    // variable holds a single key, so only the first of several is exported.
+    pub fn export_env(&self, include_secrets: bool) -> Result<String> {
+        let table = if include_secrets { self.table()? } else { self.masked_table()? };
+        let mut exports = String::new();
+        for (field, var) in ENV_FIELDS {
+            let value = match table.get(*field) {
+                Some(toml::Value::String(value)) => value.clone(),
+                Some(toml::Value::Array(values)) => {
+                    let Some(first) = values.first().and_then(toml::Value::as_str) else {
+                        continue;
+                    };
+                    if values.len() > 1 {
+                        exports.push_str(&format!("# {} holds one key, the first of {} is exported\n", var, values.len()));
+                    }
+                    first.to_string()
+                }
+                Some(value) => value.to_string(),
+                None => continue,
+            };
+            exports.push_str(&format!("export {}='{}'\n", var, value.replace('\'', "'\\''")));
+        }
+        Ok(exports)
+    }
+
+    // write the resolved config to fp so a run can be reproduced, with the API
+    // keys masked unless include_secrets is set. Disabled parameters are
+    // written as the "none" sentinel so they don't come back as defaults.
+    pub fn dump_config(&self, fp: &Path, include_secrets: bool) -> Result<()> {
+        let mut table = if include_secrets {
+            self.table()?
+        } else {
+            self.masked_table()?
+        };
+        for field in ["frequency_penalty", "presence_penalty", "stop"] {
+            table
+                .entry(field)
+                .or_insert_with(|| toml::Value::String(NONE_SENTINEL.to_string()));
+        }
+        if let Some(dir) = fp.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Could not create directory {}: {}", dir.display(), e))?;
+        }
+        std::fs::write(fp, ConfigFormat::of(fp, None).serialize(&table)?)
+            .map_err(|e| format!("Could not write config dump {}: {}", fp.display(), e))?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WinstonConfigBuilder {
+    pub openai_org_id: Option<String>,
+    pub openai_api_key: Option<ApiKey>,
+    pub api_endpoint: Option<String>,
+    pub model: Option<String>,
+    pub max_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub frequency_penalty: Option<Param<f32>>,
+    pub presence_penalty: Option<Param<f32>>,
+    pub stop: Option<Param<Stop>>,
+    pub timeout: Option<u64>,
+    pub reasoning_effort: Option<String>,
+    pub flavor: Option<ApiFlavor>,
+    pub default_endpoint: Option<String>,
+    pub endpoints: Option<BTreeMap<String, Endpoint>>,
+    pub refusal_pattern: Option<String>,
+    pub summary_model: Option<String>,
+    pub assistant_label: Option<String>,
+    pub api: Option<ApiKind>,
+    pub max_retries: Option<u32>,
+    pub presets: Option<BTreeMap<String, Preset>>,
+    #[serde(alias = "default_profile")]
+    pub default_preset: Option<String>,
+    pub system: Option<Param<String>>,
+    pub max_concurrency: Option<u32>,
+    pub headers: Option<BTreeMap<String, String>>,
+    pub warn_tokens: Option<u32>,
+    pub personas: Option<BTreeMap<String, String>>,
+    pub max_request_bytes: Option<usize>,
+    pub lang: Option<Param<String>>,
+    pub response_content_path: Option<String>,
+    pub deadline: Option<String>,
+    pub max_retry_after: Option<u64>,
+    pub fail_on_retry_after: Option<bool>,
+    pub fallback_models: Option<Vec<String>>,
+    pub no_org_header: Option<bool>,
+    pub min_p: Option<f32>,
+    pub top_k: Option<u32>,
+    pub repeat_penalty: Option<f32>,
+    pub retry_jitter: Option<f64>,
+    // refuse config files with an API key that others can read, set from
+    // --strict-permissions rather than the file
+    #[serde(skip)]
+    pub strict_permissions: bool,
+    // leave out the default model from the environment, set from --no-env
+    #[serde(skip)]
+    pub no_env: bool,
+}
+
+#[allow(dead_code)]
+impl WinstonConfigBuilder {
+    pub fn new() -> Self {
+        Self {
+            openai_org_id: None,
+            openai_api_key: None,
+            api_endpoint: None,
+            model: None,
+            max_tokens: None,
+            temperature: None,
+            top_p: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop: None,
+            timeout: None,
+            reasoning_effort: None,
+            flavor: None,
+            default_endpoint: None,
+            endpoints: None,
+            refusal_pattern: None,
+            summary_model: None,
+            assistant_label: None,
+            api: None,
+            max_retries: None,
+            presets: None,
+            default_preset: None,
+            system: None,
+            max_concurrency: None,
+            headers: None,
+            warn_tokens: None,
+            personas: None,
+            max_request_bytes: None,
+            lang: None,
+            response_content_path: None,
+            deadline: None,
+            max_retry_after: None,
+            fail_on_retry_after: None,
+            fallback_models: None,
+            no_org_header: None,
+            min_p: None,
+            top_k: None,
+            repeat_penalty: None,
+            retry_jitter: None,
+            strict_permissions: false,
+            no_env: false,
+        }
+    }
+
+    pub fn strict_permissions(mut self, strict: bool) -> Self {
+        self.strict_permissions = strict;
+        self
+    }
+
+    pub fn no_env(mut self, no_env: bool) -> Self {
+        self.no_env = no_env;
+        self
+    }
+
+    pub fn openai_org_id(mut self, id: String) -> Self {
+        self.openai_org_id = Some(id);
+        self
+    }
+
+    pub fn openai_api_key(mut self, key: impl Into<ApiKey>) -> Self {
+        self.openai_api_key = Some(key.into());
+        self
+    }
+
+    pub fn api_endpoint(mut self, endpoint: String) -> Self {
+        self.api_endpoint = Some(endpoint);
+        self
+    }
+
+    pub fn model(mut self, model: String) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    pub fn top_p(mut self, top_p: f32) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    pub fn frequency_penalty(mut self, frequency_penalty: impl Into<Param<f32>>) -> Self {
+        self.frequency_penalty = Some(frequency_penalty.into());
+        self
+    }
+
+    pub fn presence_penalty(mut self, presence_penalty: impl Into<Param<f32>>) -> Self {
+        self.presence_penalty = Some(presence_penalty.into());
+        self
+    }
+
+    pub fn stop(mut self, stop: impl Into<Param<Stop>>) -> Self {
+        self.stop = Some(stop.into());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: u64) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn reasoning_effort(mut self, reasoning_effort: String) -> Self {
+        self.reasoning_effort = Some(reasoning_effort);
+        self
+    }
+
+    pub fn flavor(mut self, flavor: ApiFlavor) -> Self {
+        self.flavor = Some(flavor);
+        self
+    }
+
+    pub fn default_endpoint(mut self, default_endpoint: String) -> Self {
+        self.default_endpoint = Some(default_endpoint);
+        self
+    }
+
+    pub fn endpoints(mut self, endpoints: BTreeMap<String, Endpoint>) -> Self {
+        self.endpoints = Some(endpoints);
+        self
+    }
+
+    // layer the settings of the named endpoint over the builder
+    pub fn select_endpoint(mut self, name: &str) -> Result<Self> {
+        let endpoint = self
+            .endpoints
+            .as_ref()
+            .and_then(|endpoints| endpoints.get(name))
+            .cloned()
+            .ok_or_else(|| format!("Unknown endpoint `{}`", name))?;
+        if let Some(api_endpoint) = endpoint.api_endpoint {
+            self.api_endpoint = Some(api_endpoint);
+        }
+        if let Some(openai_api_key) = endpoint.openai_api_key {
+            self.openai_api_key = Some(openai_api_key);
+        }
+        if let Some(openai_org_id) = endpoint.openai_org_id {
+            self.openai_org_id = Some(openai_org_id);
+        }
+        if let Some(flavor) = endpoint.flavor {
+            self.flavor = Some(flavor);
+        }
+        if let Some(no_org_header) = endpoint.no_org_header {
+            self.no_org_header = Some(no_org_header);
+        }
+        Ok(self)
+    }
+
+    // layer the parameters of the named preset over the current values
+    pub fn select_preset(self, name: &str) -> Result<Self> {
+        let preset = self
+            .presets
+            .as_ref()
+            .and_then(|presets| presets.get(name))
+            .cloned()
+            .ok_or_else(|| format!("Unknown preset `{}`", name))?;
+        Ok(self.apply_preset(preset))
+    }
+
+    // set every parameter the preset has a value for
+    pub fn apply_preset(mut self, preset: Preset) -> Self {
+        if let Some(model) = preset.model {
+            self.model = Some(model);
+        }
+        if let Some(max_tokens) = preset.max_tokens {
+            self.max_tokens = Some(max_tokens);
+        }
+        if let Some(temperature) = preset.temperature {
+            self.temperature = Some(temperature);
+        }
+        if let Some(top_p) = preset.top_p {
+            self.top_p = Some(top_p);
+        }
+        if let Some(frequency_penalty) = preset.frequency_penalty {
+            self.frequency_penalty = Some(frequency_penalty);
+        }
+        if let Some(presence_penalty) = preset.presence_penalty {
+            self.presence_penalty = Some(presence_penalty);
+        }
+        if let Some(stop) = preset.stop {
+            self.stop = Some(stop);
+        }
+        if let Some(reasoning_effort) = preset.reasoning_effort {
+            self.reasoning_effort = Some(reasoning_effort);
+        }
+        self
+    }
+
+    // apply_preset for the parameters that aren't set yet
+    pub fn apply_defaults(mut self, defaults: Preset) -> Self {
+        self.model = self.model.or(defaults.model);
+        self.max_tokens = self.max_tokens.or(defaults.max_tokens);
+        self.temperature = self.temperature.or(defaults.temperature);
+        self.top_p = self.top_p.or(defaults.top_p);
+        self.frequency_penalty = self.frequency_penalty.or(defaults.frequency_penalty);
+        self.presence_penalty = self.presence_penalty.or(defaults.presence_penalty);
+        self.stop = self.stop.or(defaults.stop);
+        self.reasoning_effort = self.reasoning_effort.or(defaults.reasoning_effort);
+        self
+    }
+
+    pub fn refusal_pattern(mut self, refusal_pattern: String) -> Self {
+        self.refusal_pattern = Some(refusal_pattern);
+        self
+    }
+
+    pub fn summary_model(mut self, summary_model: String) -> Self {
+        self.summary_model = Some(summary_model);
+        self
+    }
+
+    pub fn assistant_label(mut self, assistant_label: String) -> Self {
+        self.assistant_label = Some(assistant_label);
+        self
+    }
+
+    pub fn api(mut self, api: ApiKind) -> Self {
+        self.api = Some(api);
+        self
+    }
+
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = Some(max_retries);
+        self
+    }
+
+    pub fn presets(mut self, presets: BTreeMap<String, Preset>) -> Self {
+        self.presets = Some(presets);
+        self
+    }
+
+    pub fn default_preset(mut self, default_preset: String) -> Self {
+        self.default_preset = Some(default_preset);
+        self
+    }
+
+    pub fn system(mut self, system: impl Into<Param<String>>) -> Self {
+        self.system = Some(system.into());
+        self
+    }
+
+    pub fn max_concurrency(mut self, max_concurrency: u32) -> Self {
+        self.max_concurrency = Some(max_concurrency);
+        self
+    }
+
+    pub fn warn_tokens(mut self, warn_tokens: u32) -> Self {
+        self.warn_tokens = Some(warn_tokens);
+        self
+    }
+
+    pub fn personas(mut self, personas: BTreeMap<String, String>) -> Self {
+        self.personas = Some(personas);
+        self
+    }
 
-        Ok(v2)
+    pub fn max_request_bytes(mut self, max_request_bytes: usize) -> Self {
+        self.max_request_bytes = Some(max_request_bytes);
+        self
     }
-}
 
-#[derive(Debug, Deserialize)]
-pub struct WinstonConfigBuilder {
-    pub openai_org_id: Option<String>,
-    pub openai_api_key: Option<String>,
-    pub api_endpoint: Option<String>,
-    pub model: Option<String>,
-    pub max_tokens: Option<u32>,
-    pub temperature: Option<f32>,
-    pub top_p: Option<f32>,
-    pub frequency_penalty: Option<f32>,
-    pub presence_penalty: Option<f32>,
-    pub stop: Option<String>,
-}
+    pub fn lang(mut self, lang: impl Into<Param<String>>) -> Self {
+        self.lang = Some(lang.into());
+        self
+    }
 
-#[allow(dead_code)]
-impl WinstonConfigBuilder {
-    pub fn new() -> Self {
-        Self {
-            openai_org_id: None,
-            openai_api_key: None,
-            api_endpoint: None,
-            model: None,
-            max_tokens: None,
-            temperature: None,
-            top_p: None,
-            frequency_penalty: None,
-            presence_penalty: None,
-            stop: None,
-        }
+    pub fn response_content_path(mut self, response_content_path: String) -> Self {
+        self.response_content_path = Some(response_content_path);
+        self
     }
 
-    pub fn openai_org_id(mut self, id: String) -> Self {
-        self.openai_org_id = Some(id);
+    pub fn deadline(mut self, deadline: String) -> Self {
+        self.deadline = Some(deadline);
         self
     }
 
-    pub fn openai_api_key(mut self, key: String) -> Self {
-        self.openai_api_key = Some(key);
+    pub fn max_retry_after(mut self, max_retry_after: u64) -> Self {
+        self.max_retry_after = Some(max_retry_after);
         self
     }
 
-    pub fn api_endpoint(mut self, endpoint: String) -> Self {
-        self.api_endpoint = Some(endpoint);
+    pub fn fail_on_retry_after(mut self, fail_on_retry_after: bool) -> Self {
+        self.fail_on_retry_after = Some(fail_on_retry_after);
         self
     }
 
-    pub fn model(mut self, model: String) -> Self {
-        self.model = Some(model);
+    pub fn min_p(mut self, min_p: f32) -> Self {
+        self.min_p = Some(min_p);
         self
     }
 
-    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
-        self.max_tokens = Some(max_tokens);
+    pub fn top_k(mut self, top_k: u32) -> Self {
+        self.top_k = Some(top_k);
         self
     }
 
-    pub fn temperature(mut self, temperature: f32) -> Self {
-        self.temperature = Some(temperature);
+    pub fn repeat_penalty(mut self, repeat_penalty: f32) -> Self {
+        self.repeat_penalty = Some(repeat_penalty);
         self
     }
 
-    pub fn top_p(mut self, top_p: f32) -> Self {
-        self.top_p = Some(top_p);
+    pub fn retry_jitter(mut self, retry_jitter: f64) -> Self {
+        self.retry_jitter = Some(retry_jitter);
         self
     }
 
-    pub fn frequency_penalty(mut self, frequency_penalty: f32) -> Self {
-        self.frequency_penalty = Some(frequency_penalty);
+    pub fn no_org_header(mut self, no_org_header: bool) -> Self {
+        self.no_org_header = Some(no_org_header);
         self
     }
 
-    pub fn presence_penalty(mut self, presence_penalty: f32) -> Self {
-        self.presence_penalty = Some(presence_penalty);
+    pub fn fallback_models(mut self, fallback_models: Vec<String>) -> Self {
+        self.fallback_models = Some(fallback_models);
         self
     }
 
-    pub fn stop(mut self, stop: String) -> Self {
-        self.stop = Some(stop);
+    // add a header, replacing a configured one of the same name
+    pub fn header(mut self, name: String, value: String) -> Self {
+        let headers = self.headers.get_or_insert_with(BTreeMap::new);
+        headers.retain(|configured, _| !configured.eq_ignore_ascii_case(&name));
+        headers.insert(name, value);
         self
     }
 
     pub fn build(self) -> Result<WinstonConfig> {
-        let openai_org_id = self.openai_org_id.expect("Missing OpenAI Organization ID");
-        let openai_api_key = self.openai_api_key.expect("Missing OpenAI API Key");
+        // the organization is optional and the key is only required once a
+        // request is made, so neither is checked here
+        let openai_org_id = self.openai_org_id;
+        let openai_api_key = self.openai_api_key;
         let api_endpoint = self.api_endpoint.unwrap_or_else(|| OPENAI_ENDPOINT.to_string());
-        let model = self.model.unwrap_or_else(|| MODEL.to_string());
-        let max_tokens = self.max_tokens.unwrap_or_else(|| MAX_TOKENS);
-        let temperature = self.temperature.unwrap_or_else(|| TEMPERATURE);
-        let top_p = self.top_p.unwrap_or_else(|| TOP_P);
-        let frequency_penalty = self.frequency_penalty.unwrap_or_else(|| FREQUENCY_PENALTY);
-        let presence_penalty = self.presence_penalty.unwrap_or_else(|| PRESENCE_PENALTY);
-        let stop = self.stop.unwrap_or_else(|| STOP.to_string());
-        
+        let model = self
+            .model
+            .unwrap_or_else(|| default_model(std::env::var(DEFAULT_MODEL_ENV).ok().filter(|_| !self.no_env)));
+        let max_tokens = self.max_tokens;
+        let temperature = self.temperature.unwrap_or(TEMPERATURE);
+        let top_p = self.top_p.unwrap_or(TOP_P);
+        let frequency_penalty = Param::resolve(self.frequency_penalty, FREQUENCY_PENALTY);
+        let presence_penalty = Param::resolve(self.presence_penalty, PRESENCE_PENALTY);
+        let stop = match self.stop {
+            None if self.api.unwrap_or_else(|| api_for_model(&model)) == ApiKind::Chat => None,
+            stop => Param::resolve(stop, Stop::One(STOP.to_string())),
+        };
+        if let Some(stop) = &stop {
+            let count = stop.sequences().len();
+            if count > MAX_STOP_SEQUENCES {
+                return Err(WinstonError::TooManyStopSequences { count }.into());
+            }
+        }
+        let timeout = self.timeout;
+        let reasoning_effort = match self.reasoning_effort {
+            Some(effort) if !REASONING_EFFORTS.contains(&effort.as_str()) => {
+                return Err(format!("Invalid reasoning effort `{}`, expected one of {}", effort, REASONING_EFFORTS.join(", ")).into());
+            }
+            effort => effort,
+        };
+        let flavor = self.flavor.unwrap_or_default();
+        let default_endpoint = self.default_endpoint;
+        let endpoints = self.endpoints.unwrap_or_default();
+        let refusal_pattern = self.refusal_pattern;
+        let summary_model = self.summary_model;
+        let assistant_label = self.assistant_label;
+        let api = self.api;
+        let max_retries = self.max_retries.unwrap_or(MAX_RETRIES);
+        let presets = self.presets.unwrap_or_default();
+        let default_preset = self.default_preset;
+        let system = match self.system {
+            Some(Param::Value(system)) => Some(system),
+            _ => None,
+        };
+        let max_concurrency = self.max_concurrency.unwrap_or(MAX_CONCURRENCY);
+        if max_concurrency == 0 {
+            return Err("max_concurrency must be at least 1".into());
+        }
+        let mut headers = BTreeMap::new();
+        for (name, value) in self.headers.unwrap_or_default() {
+            let name = name.trim().to_ascii_lowercase();
+            if RESERVED_HEADERS.contains(&name.as_str()) {
+                return Err(format!("Header `{}` can't be set, winston sends it from the configured credentials", name).into());
+            }
+            headers.insert(name, value.trim().to_string());
+        }
+        let lang = match self.lang {
+            Some(Param::Value(lang)) => {
+                language(&lang)?;
+                Some(lang)
+            }
+            _ => None,
+        };
+        let response_content_path = match self.response_content_path {
+            Some(path) if !path.starts_with('/') => {
+                return Err(format!(
+                    "Invalid response_content_path `{}`, expected a JSON pointer like {}",
+                    path, RESPONSE_CONTENT_PATH
+                )
+                .into());
+            }
+            path => path,
+        };
+        if let Some(deadline) = &self.deadline {
+            parse_duration(deadline)?;
+        }
+        let retry_jitter = self.retry_jitter.unwrap_or(RETRY_JITTER);
+        if !(0.0..=1.0).contains(&retry_jitter) {
+            return Err(format!("Invalid retry_jitter {}, expected a fraction from 0 to 1", retry_jitter).into());
+        }
+
         Ok(WinstonConfig {
             openai_org_id,
             openai_api_key,
@@ -184,16 +1500,89 @@ impl WinstonConfigBuilder {
             frequency_penalty,
             presence_penalty,
             stop,
+            timeout,
+            reasoning_effort,
+            flavor,
+            default_endpoint,
+            endpoints,
+            refusal_pattern,
+            summary_model,
+            assistant_label,
+            api,
+            max_retries,
+            presets,
+            default_preset,
+            system,
+            max_concurrency,
+            headers,
+            warn_tokens: self.warn_tokens.unwrap_or(WARN_TOKENS),
+            personas: self.personas.unwrap_or_default(),
+            max_request_bytes: self.max_request_bytes.unwrap_or(MAX_REQUEST_BYTES),
+            lang,
+            response_content_path,
+            deadline: self.deadline,
+            max_retry_after: self.max_retry_after.unwrap_or(MAX_RETRY_AFTER),
+            fail_on_retry_after: self.fail_on_retry_after.unwrap_or(false),
+            fallback_models: self.fallback_models.unwrap_or_default(),
+            no_org_header: self.no_org_header.unwrap_or(false),
+            min_p: self.min_p,
+            top_k: self.top_k,
+            repeat_penalty: self.repeat_penalty,
+            retry_jitter,
         })
     }
 
     // load_config takes a PathBuf and returns a WinstonConfigBuilder. The config
     // file should be in the TOML format. Using the TOML crate, the function should
     // only replace fields that have valid values in the config file. If the config
-    // file is missing a field, the default value should be used.
-    pub fn load_config(mut self, fp: &PathBuf) -> Self {
-        let config = std::fs::read_to_string(fp).unwrap();
-        let config: WinstonConfigBuilder = toml::from_str(&config).unwrap();
+    // file is missing a field, the default value should be used. A value of the
+    // wrong type is an error naming the field and the type it expects.
+    pub fn load_config(self, fp: &Path) -> Result<Self> {
+        self.load_config_as(fp, ConfigFormat::of(fp, None))
+    }
+
+    // whether a loaded config holds an API key itself, not one kept in the keyring
+    fn has_secret(&self) -> bool {
+        let stored = |key: &Option<ApiKey>| {
+            key.as_ref().is_some_and(|key| key.keys().iter().any(|key| key != crate::secrets::KEYRING))
+        };
+        stored(&self.openai_api_key) || self.endpoints.iter().flatten().any(|(_, endpoint)| stored(&endpoint.openai_api_key))
+    }
+
+    // like ssh with private keys, a config file holding an API key should only
+    // be readable by its owner. One that group or others can read is a warning,
+    // or an error with strict_permissions.
+    fn check_permissions(&self, fp: &Path, config: &Self) -> Result<Option<Diagnostic>> {
+        if !config.has_secret() {
+            return Ok(None);
+        }
+        let Some(mode) = loose_permissions(fp)? else {
+            return Ok(None);
+        };
+        let err = WinstonError::InsecurePermissions { path: fp.to_path_buf(), mode };
+        if self.strict_permissions {
+            return Err(err.into());
+        }
+        Ok(Some(
+            Diagnostic::warning("insecure_permissions", err.to_string())
+                .with("path", fp.display())
+                .with("mode", format!("{:o}", mode)),
+        ))
+    }
+
+    // load_config for a file in the given format
+    pub fn load_config_as(mut self, fp: &Path, format: ConfigFormat) -> Result<Self> {
+        let source = std::fs::read_to_string(fp)
+            .map_err(|e| format!("Could not read config file {}: {}", fp.display(), e))?;
+        let invalid = |e: String| format!("Invalid config file {}: {}", fp.display(), e);
+        let config: WinstonConfigBuilder = match format {
+            ConfigFormat::Toml => toml::from_str(&source).map_err(|e| invalid(config_error(&source, e)))?,
+            ConfigFormat::Json => serde_json::from_str(&source).map_err(|e| invalid(e.to_string()))?,
+            ConfigFormat::Yaml => serde_yaml::from_str(&source).map_err(|e| invalid(e.to_string()))?,
+        };
+        if let Some(warning) = self.check_permissions(fp, &config)? {
+            warning.emit();
+        }
         // check each config field, if a valid value exists, replace the self field
         if let Some(openai_org_id) = config.openai_org_id {
             self.openai_org_id = Some(openai_org_id);
@@ -225,7 +1614,94 @@ impl WinstonConfigBuilder {
         if let Some(stop) = config.stop {
             self.stop = Some(stop);
         }
-        self
+        if let Some(timeout) = config.timeout {
+            self.timeout = Some(timeout);
+        }
+        if let Some(reasoning_effort) = config.reasoning_effort {
+            self.reasoning_effort = Some(reasoning_effort);
+        }
+        if let Some(flavor) = config.flavor {
+            self.flavor = Some(flavor);
+        }
+        if let Some(default_endpoint) = config.default_endpoint {
+            self.default_endpoint = Some(default_endpoint);
+        }
+        if let Some(endpoints) = config.endpoints {
+            self.endpoints = Some(endpoints);
+        }
+        if let Some(refusal_pattern) = config.refusal_pattern {
+            self.refusal_pattern = Some(refusal_pattern);
+        }
+        if let Some(summary_model) = config.summary_model {
+            self.summary_model = Some(summary_model);
+        }
+        if let Some(assistant_label) = config.assistant_label {
+            self.assistant_label = Some(assistant_label);
+        }
+        if let Some(api) = config.api {
+            self.api = Some(api);
+        }
+        if let Some(max_retries) = config.max_retries {
+            self.max_retries = Some(max_retries);
+        }
+        if let Some(presets) = config.presets {
+            self.presets = Some(presets);
+        }
+        if let Some(default_preset) = config.default_preset {
+            self.default_preset = Some(default_preset);
+        }
+        if let Some(system) = config.system {
+            self.system = Some(system);
+        }
+        if let Some(max_concurrency) = config.max_concurrency {
+            self.max_concurrency = Some(max_concurrency);
+        }
+        if let Some(headers) = config.headers {
+            self.headers = Some(headers);
+        }
+        if let Some(warn_tokens) = config.warn_tokens {
+            self.warn_tokens = Some(warn_tokens);
+        }
+        if let Some(personas) = config.personas {
+            self.personas = Some(personas);
+        }
+        if let Some(max_request_bytes) = config.max_request_bytes {
+            self.max_request_bytes = Some(max_request_bytes);
+        }
+        if let Some(lang) = config.lang {
+            self.lang = Some(lang);
+        }
+        if let Some(response_content_path) = config.response_content_path {
+            self.response_content_path = Some(response_content_path);
+        }
+        if let Some(deadline) = config.deadline {
+            self.deadline = Some(deadline);
+        }
+        if let Some(max_retry_after) = config.max_retry_after {
+            self.max_retry_after = Some(max_retry_after);
+        }
+        if let Some(fail_on_retry_after) = config.fail_on_retry_after {
+            self.fail_on_retry_after = Some(fail_on_retry_after);
+        }
+        if let Some(fallback_models) = config.fallback_models {
+            self.fallback_models = Some(fallback_models);
+        }
+        if let Some(no_org_header) = config.no_org_header {
+            self.no_org_header = Some(no_org_header);
+        }
+        if let Some(min_p) = config.min_p {
+            self.min_p = Some(min_p);
+        }
+        if let Some(top_k) = config.top_k {
+            self.top_k = Some(top_k);
+        }
+        if let Some(repeat_penalty) = config.repeat_penalty {
+            self.repeat_penalty = Some(repeat_penalty);
+        }
+        if let Some(retry_jitter) = config.retry_jitter {
+            self.retry_jitter = Some(retry_jitter);
+        }
+        Ok(self)
     }
 }
 
@@ -242,6 +1718,7 @@ stop = "\n"
 "#;
 
     use super::*;
+    use clap::Parser;
 
     // test that the config file is parsed correctly
     #[test]
@@ -253,15 +1730,560 @@ stop = "\n"
 
         // create a new config builder and load the test config        
         let config = WinstonConfigBuilder::new() 
-            .load_config(&std::path::PathBuf::from(temp_file))
+            .load_config(&temp_file).unwrap()
             .build().unwrap();
         // check that the config fields are set correctly
         assert_eq!(config.model, "davinci");
-        assert_eq!(config.max_tokens, 2048);
+        assert_eq!(config.max_tokens, Some(2048));
         assert_eq!(config.temperature, 0.9);
         assert_eq!(config.top_p, 1.0);
-        assert_eq!(config.frequency_penalty, 0.0);
-        assert_eq!(config.presence_penalty, 0.0);
-        assert_eq!(config.stop, "\n");
+        assert_eq!(config.frequency_penalty, Some(0.0));
+        assert_eq!(config.presence_penalty, Some(0.0));
+        assert_eq!(config.stop, Some(Stop::One("\n".to_string())));
+    }
+
+    // test that single fields can be read from a resolved config
+    #[test]
+    fn config_get_test() {
+        let config = WinstonConfigBuilder::new()
+            .model("gpt-4o".to_string())
+            .max_tokens(256)
+            .build()
+            .unwrap();
+        assert_eq!(config.get("model").unwrap(), "gpt-4o");
+        assert_eq!(config.get("max_tokens").unwrap(), "256");
+        assert_eq!(config.get("openai_org_id").unwrap(), "");
+        assert!(config.get("not_a_field").is_err());
+    }
+
+    // test that the system config file is merged beneath the user's, which
+    // overrides it, and left out with --no-system-config
+    #[test]
+    fn system_config_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let system_file = temp_dir.path().join("system.toml");
+        std::fs::write(&system_file, "api_endpoint = \"https://llm.example.com\"\nmodel = \"gpt-4o-mini\"\n").unwrap();
+        let user_file = temp_dir.path().join("config.toml");
+        std::fs::write(&user_file, "model = \"gpt-4o\"\n").unwrap();
+        let user_arg = user_file.to_str().unwrap();
+
+        let opts = Options::try_parse_args(["winston", "--no-env", "--config", user_arg]).unwrap();
+        let (config, sources) = resolve_config_layers(&opts, Some(&system_file)).unwrap();
+        assert_eq!(config.api_endpoint, "https://llm.example.com");
+        assert_eq!(sources.get("api_endpoint"), Source::System);
+        assert_eq!(config.model, "gpt-4o");
+        assert_eq!(sources.get("model"), Source::File);
+
+        // a missing system config file is skipped
+        let (config, _) = resolve_config_layers(&opts, Some(&temp_dir.path().join("missing.toml"))).unwrap();
+        assert_eq!(config.api_endpoint, OPENAI_ENDPOINT);
+
+        let opts = Options::try_parse_from(["winston", "--no-system-config"]).unwrap();
+        assert!(opts.no_system_config);
+        if cfg!(unix) {
+            assert_eq!(system_config_path(), Path::new("/etc/winston/config.toml"));
+        }
+    }
+
+    // test that the fields with environment variables are exported, quoted
+    // for the shell and with the key masked unless secrets are included
+    #[test]
+    fn config_export_env_test() {
+        let config = WinstonConfigBuilder::new()
+            .openai_api_key("sk-abcdefghijklmnop".to_string())
+            .openai_org_id("org-o'brien".to_string())
+            .model("gpt-4o".to_string())
+            .temperature(0.5)
+            .build()
+            .unwrap();
+        assert_eq!(
+            config.export_env(false).unwrap(),
+            "export OPENAI_API_KEY='sk-...mnop'\nexport OPENAI_ORG_ID='org-o'\\''brien'\nexport OPENAI_MODEL='gpt-4o'\n"
+        );
+        assert!(config.export_env(true).unwrap().starts_with("export OPENAI_API_KEY='sk-abcdefghijklmnop'\n"));
+
+        let config = WinstonConfigBuilder::new()
+            .openai_api_key(vec!["sk-first-key-1234".to_string(), "sk-second-key-5678".to_string()])
+            .build()
+            .unwrap();
+        let exports = config.export_env(true).unwrap();
+        assert!(exports.starts_with("# OPENAI_API_KEY holds one key, the first of 2 is exported\nexport OPENAI_API_KEY='sk-first-key-1234'\n"));
+        assert!(!exports.contains("OPENAI_ORG_ID"));
+    }
+
+    // test that listing the config masks the API key
+    #[test]
+    fn config_list_masks_key_test() {
+        let config = WinstonConfigBuilder::new()
+            .openai_api_key("sk-abcdefghijklmnop".to_string())
+            .build()
+            .unwrap();
+        let list = config.list().unwrap();
+        assert!(list.contains(r#"openai_api_key = "sk-...mnop""#));
+        assert!(!list.contains("sk-abcdefghijklmnop"));
+    }
+
+    // test that command line options override the config file
+    #[test]
+    fn resolve_config_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("test.toml");
+        std::fs::write(&temp_file, TEST_CONFIG).unwrap();
+
+        let opts = Options::try_parse_from([
+            "winston",
+            "--config",
+            temp_file.to_str().unwrap(),
+            "--openai-model",
+            "gpt-4o",
+        ])
+        .unwrap();
+        let config = resolve_config(&opts).unwrap();
+        assert_eq!(config.model, "gpt-4o");
+        assert_eq!(config.max_tokens, Some(2048));
+    }
+
+    // test that --no-env ignores OPENAI_API_KEY and the default model from the
+    // environment but keeps the flags and the config file. clap reads the
+    // environment itself, so the checks run in a child process with it set.
+    #[test]
+    fn no_env_test() {
+        if std::env::var("WINSTON_NO_ENV_TEST").is_err() {
+            let output = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "config::test::no_env_test"])
+                .env("WINSTON_NO_ENV_TEST", "1")
+                .env_remove("OPENAI_MODEL")
+                .env_remove("OPENAI_ORG_ID")
+                .env("OPENAI_API_KEY", "sk-from-env")
+                .env(DEFAULT_MODEL_ENV, "gpt-from-env")
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+            return;
+        }
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("config.toml");
+        std::fs::write(&temp_file, "temperature = 0.5\n").unwrap();
+        let config_arg = temp_file.to_str().unwrap();
+        let resolve = |args: &[&str]| {
+            let opts = Options::try_parse_args(["winston", "--no-system-config", "--config", config_arg].iter().chain(args)).unwrap();
+            resolve_config(&opts).unwrap()
+        };
+
+        let config = resolve(&[]);
+        assert_eq!(config.api_keys().unwrap(), ["sk-from-env"]);
+        assert_eq!(config.model, "gpt-from-env");
+
+        let config = resolve(&["--no-env"]);
+        assert!(config.openai_api_key.is_none());
+        assert_eq!(config.model, default_model(None));
+        assert_eq!(config.temperature, 0.5);
+
+        let config = resolve(&["--no-env", "-k", "sk-from-flag"]);
+        assert_eq!(config.api_keys().unwrap(), ["sk-from-flag"]);
+    }
+
+    // test that each field is credited to the layer that last set it
+    #[test]
+    fn explain_config_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("test.toml");
+        std::fs::write(
+            &temp_file,
+            "temperature = 0.5\nmax_tokens = 100\nstop = \"none\"\nheaders = { x-proxy-token = \"proxy-secret-value\" }\n[presets.creative]\ntop_p = 0.9\n",
+        )
+        .unwrap();
+        let opts = Options::try_parse_from([
+            "winston",
+            "--config",
+            temp_file.to_str().unwrap(),
+            "--preset",
+            "creative",
+            "--openai-temperature",
+            "1.2",
+        ])
+        .unwrap();
+        let (config, sources) = resolve_config_sources(&opts).unwrap();
+
+        assert_eq!(config.temperature, 1.2);
+        assert_eq!(sources.get("temperature"), Source::Flag);
+        assert_eq!(sources.get("max_tokens"), Source::File);
+        assert_eq!(sources.get("top_p"), Source::Preset);
+        assert_eq!(sources.get("timeout"), Source::Default);
+
+        let explained = config.explain(&sources).unwrap();
+        let line = |name: &str| explained.lines().find(|line| line.starts_with(&format!("{} ", name))).unwrap().to_string();
+        assert!(line("temperature").ends_with(" 1.2  (flag)"), "{}", line("temperature"));
+        assert!(line("stop").ends_with(" none  (file)"));
+        assert!(line("timeout").ends_with(" unset  (default)"));
+        assert_eq!(explained.lines().count(), FIELDS.len());
+        assert!(!explained.contains("proxy-secret-value"));
+    }
+
+    // test that the none sentinel disables parameters from the file and flags
+    #[test]
+    fn none_sentinel_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("test.toml");
+        std::fs::write(&temp_file, "stop = \"none\"\nfrequency_penalty = 0.5\n").unwrap();
+
+        let opts = Options::try_parse_from([
+            "winston",
+            "--config",
+            temp_file.to_str().unwrap(),
+            "--frequency-penalty",
+            "none",
+        ])
+        .unwrap();
+        let config = resolve_config(&opts).unwrap();
+        assert_eq!(config.stop, None);
+        assert_eq!(config.frequency_penalty, None);
+        assert_eq!(config.presence_penalty, Some(PRESENCE_PENALTY));
+    }
+
+    // test that up to four stop sequences are accepted and sent exactly as given
+    #[test]
+    fn stop_sequences_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("test.toml");
+        std::fs::write(&temp_file, "stop = [\"<|endoftext|>\", \"\\u0000\", \"\\t\\r\\n\", \"\\\\n\"]\n").unwrap();
+        let config = WinstonConfigBuilder::new().load_config(&temp_file).unwrap().build().unwrap();
+        let stops = ["<|endoftext|>", "\0", "\t\r\n", "\\n"];
+        assert_eq!(config.stop.as_ref().unwrap().sequences(), stops);
+        let body = serde_json::to_value(crate::chat::ChatRequest::new(&config, Vec::new())).unwrap();
+        assert_eq!(body["stop"], serde_json::json!(stops));
+
+        let empty_file = temp_dir.path().join("empty.toml");
+        std::fs::write(&empty_file, "").unwrap();
+        let parse = |args: &[&str]| {
+            let mut argv = vec!["winston", "--no-env", "--no-system-config", "--config", empty_file.to_str().unwrap()];
+            argv.extend(args);
+            Options::try_parse_args(argv).unwrap()
+        };
+        let config = resolve_config(&parse(&["--stop", "<|im_end|>", "--stop", "###"])).unwrap();
+        assert_eq!(config.stop, Some(Stop::Many(vec!["<|im_end|>".to_string(), "###".to_string()])));
+
+        let err = resolve_config(&parse(&["-d", "a", "-d", "b", "-d", "c", "-d", "d", "-d", "e"])).unwrap_err();
+        assert_eq!(err.downcast_ref::<WinstonError>(), Some(&WinstonError::TooManyStopSequences { count: 5 }));
+        assert_eq!(err.to_string(), "5 stop sequences were given, at most 4 are allowed");
+    }
+
+    const ENDPOINTS_CONFIG: &str = r#"api_endpoint = "https://api.openai.com"
+default_endpoint = "local"
+
+[endpoints.local]
+api_endpoint = "http://localhost:8080"
+openai_api_key = "sk-local"
+
+[endpoints.azure]
+api_endpoint = "https://example.openai.azure.com"
+openai_api_key = "azure-key"
+flavor = "azure"
+"#;
+
+    // test that the default model comes from the environment when it is set
+    #[test]
+    fn default_model_env_test() {
+        assert_eq!(default_model(Some("gpt-4.1".to_string())), "gpt-4.1");
+        assert_eq!(default_model(Some(" ".to_string())), MODEL);
+        assert_eq!(default_model(None), MODEL);
+        assert_eq!(crate::chat::api_for_model(MODEL), ApiKind::Chat);
+    }
+
+    // test that a named endpoint is selected with --endpoint
+    #[test]
+    fn select_endpoint_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("test.toml");
+        std::fs::write(&temp_file, ENDPOINTS_CONFIG).unwrap();
+
+        let opts = Options::try_parse_from([
+            "winston",
+            "--config",
+            temp_file.to_str().unwrap(),
+            "--endpoint",
+            "azure",
+        ])
+        .unwrap();
+        let config = resolve_config(&opts).unwrap();
+        assert_eq!(config.api_endpoint, "https://example.openai.azure.com");
+        assert_eq!(config.flavor, ApiFlavor::Azure);
+        assert!(!config.list().unwrap().contains("azure-key"));
+
+        let opts = Options::try_parse_from(["winston", "--config", temp_file.to_str().unwrap(), "--endpoint", "nope"]).unwrap();
+        assert_eq!(resolve_config(&opts).unwrap_err().to_string(), "Unknown endpoint `nope`");
+    }
+
+    const PRESETS_CONFIG: &str = r#"temperature = 0.9
+
+[presets.precise]
+temperature = 0.25
+top_p = 0.5
+stop = "none"
+
+[presets.creative]
+model = "gpt-4o"
+temperature = 1.5
+"#;
+
+    // test that a preset layers over the file and below explicit flags
+    #[test]
+    fn select_preset_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("test.toml");
+        std::fs::write(&temp_file, PRESETS_CONFIG).unwrap();
+
+        let opts = Options::try_parse_from(["winston", "--config", temp_file.to_str().unwrap(), "--preset", "precise"]).unwrap();
+        let config = resolve_config(&opts).unwrap();
+        assert_eq!(config.temperature, 0.25);
+        assert_eq!(config.top_p, 0.5);
+        assert_eq!(config.stop, None);
+
+        let opts = Options::try_parse_from([
+            "winston",
+            "--config",
+            temp_file.to_str().unwrap(),
+            "--preset",
+            "precise",
+            "--openai-temperature",
+            "0.4",
+        ])
+        .unwrap();
+        assert_eq!(resolve_config(&opts).unwrap().temperature, 0.4);
+
+        let opts = Options::try_parse_from(["winston", "--config", temp_file.to_str().unwrap(), "--preset", "nope"]).unwrap();
+        assert_eq!(resolve_config(&opts).unwrap_err().to_string(), "Unknown preset `nope`");
+    }
+
+    // test that the default preset applies unless another one or none is asked for
+    #[test]
+    fn default_preset_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("test.toml");
+        std::fs::write(&temp_file, format!("default_preset = \"creative\"\n{}", PRESETS_CONFIG)).unwrap();
+        let config_file = temp_file.to_str().unwrap();
+
+        let opts = Options::try_parse_args(["winston", "--no-env", "--no-system-config", "--config", config_file]).unwrap();
+        let config = resolve_config(&opts).unwrap();
+        assert_eq!(config.model, "gpt-4o");
+        assert_eq!(config.temperature, 1.5);
+
+        let opts = Options::try_parse_args(["winston", "--no-env", "--no-system-config", "--config", config_file, "--profile", "precise"]).unwrap();
+        let config = resolve_config(&opts).unwrap();
+        assert_eq!(config.model, MODEL);
+        assert_eq!(config.temperature, 0.25);
+
+        let opts = Options::try_parse_args(["winston", "--no-env", "--no-system-config", "--config", config_file, "--no-profile"]).unwrap();
+        assert_eq!(resolve_config(&opts).unwrap().temperature, 0.9);
+    }
+
+    // test that presets are listed with the values they set
+    #[test]
+    fn list_presets_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("test.toml");
+        std::fs::write(&temp_file, PRESETS_CONFIG).unwrap();
+
+        let config = WinstonConfigBuilder::new().load_config(&temp_file).unwrap().build().unwrap();
+        assert_eq!(
+            config.list_presets().unwrap(),
+            "creative: model = \"gpt-4o\", temperature = 1.5\nprecise: stop = \"none\", temperature = 0.25, top_p = 0.5\n"
+        );
+    }
+
+    // test that the default endpoint applies when none is given
+    #[test]
+    fn default_endpoint_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("test.toml");
+        std::fs::write(&temp_file, ENDPOINTS_CONFIG).unwrap();
+
+        let opts = Options::try_parse_from(["winston", "--config", temp_file.to_str().unwrap()]).unwrap();
+        let config = resolve_config(&opts).unwrap();
+        assert_eq!(config.api_endpoint, "http://localhost:8080");
+        assert_eq!(config.flavor, ApiFlavor::OpenAi);
+    }
+
+    // test that saving creates missing parent directories
+    #[test]
+    fn save_config_creates_dirs_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("nested/winston/config.toml");
+        let config = WinstonConfigBuilder::new().model("gpt-4o".to_string()).build().unwrap();
+        config.save_config(Some(temp_file.clone()), None).unwrap();
+
+        let config = WinstonConfigBuilder::new().load_config(&temp_file).unwrap().build().unwrap();
+        assert_eq!(config.model, "gpt-4o");
+    }
+
+    // test that a dumped config loads back to the same resolved values
+    #[test]
+    fn dump_config_round_trip_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("dump.toml");
+        let config = WinstonConfigBuilder::new()
+            .openai_api_key("sk-secret-key-1234".to_string())
+            .model("gpt-4o".to_string())
+            .max_tokens(256)
+            .stop(Param::Disabled)
+            .build()
+            .unwrap();
+
+        config.dump_config(&temp_file, true).unwrap();
+        let loaded = WinstonConfigBuilder::new().load_config(&temp_file).unwrap().build().unwrap();
+        assert_eq!(toml::Table::try_from(&loaded).unwrap(), toml::Table::try_from(&config).unwrap());
+        assert_eq!(loaded.stop, None);
+
+        config.dump_config(&temp_file, false).unwrap();
+        let loaded = WinstonConfigBuilder::new().load_config(&temp_file).unwrap().build().unwrap();
+        assert_eq!(loaded.api_key().unwrap(), "sk-...1234");
+        assert_eq!(loaded.model, "gpt-4o");
+    }
+
+    // test that equivalent TOML, JSON and YAML configs resolve to the same values
+    // and are saved back in the format they were loaded in
+    #[test]
+    fn config_format_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let configs = [
+            ("config.toml", "model = \"gpt-4o\"\ntemperature = 0.4\nmax_tokens = 512\nstop = [\"###\", \"\\n\"]\n"),
+            ("config.json", "{\"model\": \"gpt-4o\", \"temperature\": 0.4, \"max_tokens\": 512, \"stop\": [\"###\", \"\\n\"]}"),
+            ("config.yml", "model: gpt-4o\ntemperature: 0.4\nmax_tokens: 512\nstop:\n  - \"###\"\n  - \"\\n\"\n"),
+            ("config.conf", "model: gpt-4o\ntemperature: 0.4\nmax_tokens: 512\nstop: [\"###\", \"\\n\"]\n"),
+        ];
+        let mut tables = Vec::new();
+        for (name, source) in configs {
+            let temp_file = temp_dir.path().join(name);
+            std::fs::write(&temp_file, source).unwrap();
+            let mut args = vec!["winston", "--config", temp_file.to_str().unwrap()];
+            if name.ends_with(".conf") {
+                args.extend(["--config-format", "yaml"]);
+            }
+            let opts = Options::try_parse_from(args).unwrap();
+            let config = resolve_config(&opts).unwrap();
+            tables.push(config.table().unwrap());
+
+            config.save_config(Some(temp_file.clone()), opts.config_format).unwrap();
+            let format = ConfigFormat::of(&temp_file, opts.config_format);
+            let saved = WinstonConfigBuilder::new().load_config_as(&temp_file, format).unwrap().build().unwrap();
+            assert_eq!(saved.table().unwrap(), tables[0], "{}", name);
+        }
+        assert!(tables.iter().all(|table| *table == tables[0]));
+        assert_eq!(tables[0]["temperature"].as_float(), Some(0.4));
+
+        let temp_file = temp_dir.path().join("bad.json");
+        std::fs::write(&temp_file, "{\"model\": 4}").unwrap();
+        let err = WinstonConfigBuilder::new().load_config(&temp_file).unwrap_err();
+        assert!(err.to_string().starts_with("Invalid config file"));
+    }
+
+    // test that a readable config file with an API key is warned about, and
+    // refused with strict permissions
+    #[cfg(unix)]
+    #[test]
+    fn insecure_permissions_test() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("config.toml");
+        std::fs::write(&temp_file, "openai_api_key = \"sk-abcdefghijklmnop\"\n").unwrap();
+        std::fs::set_permissions(&temp_file, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let config = WinstonConfigBuilder::new().load_config(&temp_file).unwrap();
+
+        let warning = WinstonConfigBuilder::new().check_permissions(&temp_file, &config).unwrap().unwrap();
+        assert_eq!(warning.code, "insecure_permissions");
+        assert_eq!(warning.context["mode"], "644");
+        let err = WinstonConfigBuilder::new()
+            .strict_permissions(true)
+            .load_config(&temp_file)
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<WinstonError>(),
+            Some(&WinstonError::InsecurePermissions { path: temp_file.clone(), mode: 0o644 })
+        );
+
+        // keyring references aren't secrets, and owner only files are fine
+        let keyring = WinstonConfigBuilder::new().openai_api_key(crate::secrets::KEYRING.to_string());
+        assert!(WinstonConfigBuilder::new().check_permissions(&temp_file, &keyring).unwrap().is_none());
+        std::fs::set_permissions(&temp_file, std::fs::Permissions::from_mode(0o600)).unwrap();
+        assert!(WinstonConfigBuilder::new().strict_permissions(true).load_config(&temp_file).is_ok());
+
+        // a saved config is only readable by its owner
+        let saved = temp_dir.path().join("saved.toml");
+        config.build().unwrap().save_config(Some(saved.clone()), None).unwrap();
+        assert_eq!(std::fs::metadata(&saved).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    // test that edit defaults to a low temperature, unlike ask, unless the
+    // config file or a flag sets one
+    #[test]
+    fn command_defaults_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let empty_file = temp_dir.path().join("empty.toml");
+        std::fs::write(&empty_file, "").unwrap();
+        let temp_file = temp_dir.path().join("test.toml");
+        std::fs::write(&temp_file, "temperature = 0.5\n").unwrap();
+        let temperature = |config: &Path, args: &[&str]| {
+            let mut argv = vec!["winston", "--no-env", "--no-system-config", "--config", config.to_str().unwrap()];
+            argv.extend(args);
+            resolve_config(&Options::try_parse_args(argv).unwrap()).unwrap().temperature
+        };
+        assert_eq!(temperature(&empty_file, &["edit", "fix the grammar"]), 0.2);
+        assert_eq!(temperature(&empty_file, &["ask", "--as", "rust", "what is a trait?"]), TEMPERATURE);
+        assert_eq!(temperature(&empty_file, &["-t", "0.7", "edit", "fix the grammar"]), 0.7);
+        assert_eq!(temperature(&temp_file, &["edit", "fix the grammar"]), 0.5);
+    }
+
+    // test that dumped floats are written as the value that was configured
+    #[test]
+    fn dump_config_rounded_floats_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("dump.toml");
+        let config = WinstonConfigBuilder::new()
+            .temperature(0.9)
+            .top_p(0.3)
+            .frequency_penalty(0.1)
+            .build()
+            .unwrap();
+        config.dump_config(&temp_file, false).unwrap();
+
+        let dump = std::fs::read_to_string(&temp_file).unwrap();
+        assert!(dump.contains("temperature = 0.9\n"), "{}", dump);
+        assert!(dump.contains("top_p = 0.3\n"), "{}", dump);
+        assert!(dump.contains("frequency_penalty = 0.1\n"), "{}", dump);
+        assert_eq!(config.get("temperature").unwrap(), "0.9");
+    }
+
+    // test that only known reasoning efforts are accepted
+    #[test]
+    fn reasoning_effort_validation_test() {
+        let config = WinstonConfigBuilder::new()
+            .reasoning_effort("high".to_string())
+            .build()
+            .unwrap();
+        assert_eq!(config.reasoning_effort.as_deref(), Some("high"));
+        let err = WinstonConfigBuilder::new()
+            .reasoning_effort("extreme".to_string())
+            .build()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Invalid reasoning effort `extreme`, expected one of low, medium, high");
+    }
+
+    // test that type mismatches in the config file name the field and type
+    #[test]
+    fn config_type_error_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("test.toml");
+        let cases = [
+            ("model = \"davinci\"\nmax_tokens = \"2048\"\n", "`max_tokens` must be an integer, found string (line 2)"),
+            ("temperature = \"hot\"\n", "`temperature` must be a number, found string (line 1)"),
+            ("model = 3\n", "`model` must be a string, found integer (line 1)"),
+            ("stop = true\n", "`stop` must be a string, an array of up to four strings or \"none\", found boolean (line 1)"),
+        ];
+        for (source, expected) in cases {
+            std::fs::write(&temp_file, source).unwrap();
+            let err = WinstonConfigBuilder::new().load_config(&temp_file).unwrap_err();
+            assert!(err.to_string().ends_with(expected), "{}", err);
+        }
     }
 }
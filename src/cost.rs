@@ -0,0 +1,207 @@
+// Winston cost estimates
+// With --confirm-cost the cost of a request is estimated before it is sent, and
+// on a terminal the request is only sent once the estimate is confirmed. Tokens
+// are counted with the rule of thumb of four characters per token plus a few
+// tokens of framing per message, which is close enough for English text to
+// catch an expensive mistake. The completion is assumed to use all of
+// max_tokens. Prices are per million tokens and matched by model name prefix.
+// Prompts estimated above warn_tokens get a warning, and on a terminal the same
+// confirmation, even without --confirm-cost. With --strict-tokens a model whose
+// context window isn't known fails instead of being estimated, as its tokens
+// may be counted differently.
+
+use std::fmt;
+use std::io::{BufRead, Write};
+
+use crate::chat::ChatRequest;
+use crate::chunk::known_context_window;
+use crate::diagnostics::Diagnostic;
+use super::Result;
+
+// completion tokens assumed when max_tokens is unset
+pub const DEFAULT_COMPLETION_TOKENS: u32 = 4096;
+// tokens of framing around each message
+pub const TOKENS_PER_MESSAGE: u32 = 4;
+
+// input and output price in dollars per million tokens, by model name prefix.
+// More specific prefixes come first.
+pub const PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-4o-mini", 0.15, 0.60),
+    ("gpt-4o", 2.50, 10.00),
+    ("gpt-4.1-nano", 0.10, 0.40),
+    ("gpt-4.1-mini", 0.40, 1.60),
+    ("gpt-4.1", 2.00, 8.00),
+    ("gpt-4-turbo", 10.00, 30.00),
+    ("gpt-4", 30.00, 60.00),
+    ("gpt-3.5-turbo", 0.50, 1.50),
+    ("o1-mini", 1.10, 4.40),
+    ("o1", 15.00, 60.00),
+    ("o3-mini", 1.10, 4.40),
+    ("o4-mini", 1.10, 4.40),
+    ("o3", 2.00, 8.00),
+];
+
+// the approximate number of tokens in text
+pub fn estimate_tokens(text: &str) -> u32 {
+    text.chars().count().div_ceil(4) as u32
+}
+
+// the input and output price of a model, when known
+pub fn price(model: &str) -> Option<(f64, f64)> {
+    PRICES
+        .iter()
+        .find(|(prefix, _, _)| model.starts_with(prefix))
+        .map(|&(_, input, output)| (input, output))
+}
+
+// the cost in dollars of prompt and completion tokens, when the model's price
+// is known
+pub fn cost(model: &str, prompt_tokens: u32, completion_tokens: u32) -> Option<f64> {
+    price(model).map(|(input, output)| (prompt_tokens as f64 * input + completion_tokens as f64 * output) / 1_000_000.0)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Estimate {
+    pub model: String,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    // in dollars, unknown for models without a price
+    pub cost: Option<f64>,
+}
+
+impl Estimate {
+    pub fn new(request: &ChatRequest) -> Self {
+        let prompt_tokens = request
+            .messages
+            .iter()
+            .map(|message| estimate_tokens(&message.content) + TOKENS_PER_MESSAGE)
+            .sum();
+        let completion_tokens = request.max_tokens.unwrap_or(DEFAULT_COMPLETION_TOKENS) * request.n.unwrap_or(1);
+        let cost = cost(&request.model, prompt_tokens, completion_tokens);
+        Self {
+            model: request.model.clone(),
+            prompt_tokens,
+            completion_tokens,
+            cost,
+        }
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "estimated cost: ~{} prompt + up to {} completion tokens",
+            self.prompt_tokens, self.completion_tokens
+        )?;
+        match self.cost {
+            Some(cost) => write!(f, " = ${:.4}", cost),
+            None => write!(f, ", no price known for {}", self.model),
+        }
+    }
+}
+
+// a warning when the prompt is estimated above warn_tokens, unless it is 0
+pub fn large_prompt(estimate: &Estimate, warn_tokens: u32) -> Option<Diagnostic> {
+    if warn_tokens == 0 || estimate.prompt_tokens <= warn_tokens {
+        return None;
+    }
+    let message = format!(
+        "the prompt is about {} tokens, more than warn_tokens of {}, check the cost before sending it",
+        estimate.prompt_tokens, warn_tokens
+    );
+    Some(
+        Diagnostic::warning("large_prompt", message)
+            .with("prompt_tokens", estimate.prompt_tokens)
+            .with("warn_tokens", warn_tokens),
+    )
+}
+
+// with --strict-tokens, an error for a model whose token estimates can't be
+// relied on, what names the estimate that was refused
+pub fn strict_estimate(model: &str, what: &str) -> Result<()> {
+    match known_context_window(model) {
+        Some(_) => Ok(()),
+        None => Err(format!("No context window known for {}, not estimating {} with --strict-tokens", model, what).into()),
+    }
+}
+
+// print the estimate and ask whether to send the request. Without a terminal
+// to answer on, or with yes, the request is sent without asking.
+pub fn confirm(estimate: &Estimate, mut input: impl BufRead, mut out: impl Write, interactive: bool, yes: bool) -> Result<bool> {
+    writeln!(out, "{}", estimate)?;
+    if yes || !interactive {
+        return Ok(true);
+    }
+    write!(out, "Send the request? [y/N] ")?;
+    out.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chat::{Message, Role};
+    use crate::config::WinstonConfigBuilder;
+
+    // test the estimate of a request and that it is confirmed without asking
+    // when not interactive
+    #[test]
+    fn confirm_cost_test() {
+        let config = WinstonConfigBuilder::new()
+            .model("gpt-4o-mini".to_string())
+            .max_tokens(1000)
+            .build()
+            .unwrap();
+        let request = ChatRequest::new(&config, vec![Message::new(Role::User, "x".repeat(399))]);
+        let estimate = Estimate::new(&request);
+        assert_eq!(estimate.prompt_tokens, 104);
+        assert_eq!(estimate.completion_tokens, 1000);
+        // 104 * 0.15 + 1000 * 0.60 per million
+        assert!((estimate.cost.unwrap() - 0.0006156).abs() < 1e-9);
+        assert_eq!(
+            estimate.to_string(),
+            "estimated cost: ~104 prompt + up to 1000 completion tokens = $0.0006"
+        );
+
+        let mut out = Vec::new();
+        assert!(confirm(&estimate, std::io::empty(), &mut out, false, false).unwrap());
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{}\n", estimate));
+        assert!(confirm(&estimate, std::io::empty(), Vec::new(), true, true).unwrap());
+        assert!(confirm(&estimate, "y\n".as_bytes(), Vec::new(), true, false).unwrap());
+        assert!(!confirm(&estimate, "\n".as_bytes(), Vec::new(), true, false).unwrap());
+
+        let config = WinstonConfigBuilder::new().model("local-llama".to_string()).build().unwrap();
+        let estimate = Estimate::new(&ChatRequest::new(&config, Vec::new()));
+        assert_eq!(estimate.cost, None);
+        assert!(estimate.to_string().ends_with("no price known for local-llama"));
+    }
+
+    // test that only prompts above the threshold are warned about
+    #[test]
+    fn large_prompt_test() {
+        let config = WinstonConfigBuilder::new().warn_tokens(100).build().unwrap();
+        let estimate = |prompt: String| Estimate::new(&ChatRequest::new(&config, vec![Message::new(Role::User, prompt)]));
+
+        let warning = large_prompt(&estimate("x".repeat(1000)), config.warn_tokens).unwrap();
+        assert_eq!(warning.code, "large_prompt");
+        assert_eq!(
+            warning.message,
+            "the prompt is about 254 tokens, more than warn_tokens of 100, check the cost before sending it"
+        );
+        assert!(large_prompt(&estimate("x".repeat(100)), config.warn_tokens).is_none());
+        assert!(large_prompt(&estimate("x".repeat(1000)), 0).is_none());
+    }
+
+    // test that strict estimates are refused only for unknown models
+    #[test]
+    fn strict_estimate_test() {
+        assert!(strict_estimate("gpt-4o-mini", "the prompt size").is_ok());
+        assert_eq!(
+            strict_estimate("llama-3-70b", "the prompt size").unwrap_err().to_string(),
+            "No context window known for llama-3-70b, not estimating the prompt size with --strict-tokens"
+        );
+    }
+}
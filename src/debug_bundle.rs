@@ -0,0 +1,102 @@
+// Winston debug bundle
+// `debug-bundle <path>` writes what is usually asked for in a bug report to a
+// single file: the version winston was built from, the platform, which of the
+// environment variables winston reads are set, the resolved configuration and
+// the result of a request to the models endpoint. API keys and header values
+// are masked and only the names of environment variables are included, never
+// their values, so the file can be attached as it is.
+
+use std::fmt::Write;
+
+use crate::client::Client;
+use crate::config::{mask_headers, WinstonConfig, DEFAULT_MODEL_ENV};
+use crate::ping::millis;
+use crate::version::VersionInfo;
+use super::Result;
+
+// the environment variables winston reads
+pub const ENV_VARS: &[&str] = &[
+    "OPENAI_API_KEY",
+    "OPENAI_ORG_ID",
+    "OPENAI_MODEL",
+    DEFAULT_MODEL_ENV,
+    "NO_COLOR",
+    "COLUMNS",
+    "WINSTON_PAGER",
+    "PAGER",
+    "VISUAL",
+    "EDITOR",
+    "XDG_CONFIG_HOME",
+    "XDG_CACHE_HOME",
+    "XDG_DATA_HOME",
+    "HTTPS_PROXY",
+    "HTTP_PROXY",
+    "NO_PROXY",
+];
+
+// the result of a request to the models endpoint, as reported in the bundle
+pub fn test_request(client: &Client) -> String {
+    match client.ping() {
+        Ok(ping) => format!("status {} in {:.1} ms", ping.status, millis(ping.elapsed)),
+        Err(e) => format!("failed: {}", e),
+    }
+}
+
+// the bundle, given which environment variables are set and the result of the
+// test request
+pub fn render(config: &WinstonConfig, is_set: impl Fn(&str) -> bool, test_request: &str) -> Result<String> {
+    let version = VersionInfo::current();
+    let mut table = config.masked_table()?;
+    mask_headers(&mut table);
+    let set: Vec<&str> = ENV_VARS.iter().copied().filter(|name| is_set(name)).collect();
+
+    let mut bundle = String::new();
+    writeln!(bundle, "# winston debug bundle")?;
+    writeln!(bundle, "\n## version")?;
+    writeln!(bundle, "version: {}\ngit_sha: {}\nbuild: {}", version.version, version.git_sha, version.build)?;
+    writeln!(bundle, "\n## system")?;
+    writeln!(bundle, "os: {}\narch: {}", std::env::consts::OS, std::env::consts::ARCH)?;
+    writeln!(bundle, "\n## environment")?;
+    match set.is_empty() {
+        true => writeln!(bundle, "none set")?,
+        false => writeln!(bundle, "set: {}", set.join(", "))?,
+    }
+    writeln!(bundle, "\n## config")?;
+    write!(bundle, "{}", toml::to_string(&table)?)?;
+    writeln!(bundle, "\n## test request")?;
+    writeln!(bundle, "{}", test_request)?;
+    Ok(bundle)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::WinstonConfigBuilder;
+
+    // test that the bundle has every section and no raw key or header value
+    #[test]
+    fn debug_bundle_test() {
+        let mut server = mockito::Server::new();
+        let mock = server.mock("GET", "/v1/models").with_status(200).with_body("{}").create();
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("sk-abcdefghijklmnop".to_string())
+            .header("x-proxy-token".to_string(), "proxy-secret-value".to_string())
+            .build()
+            .unwrap();
+        let result = test_request(&Client::from_config(&config).unwrap());
+        mock.assert();
+        assert!(result.starts_with("status 200 OK in "));
+
+        let bundle = render(&config, |name| name == "OPENAI_API_KEY" || name == "NO_COLOR", &result).unwrap();
+        for section in ["## version", "## system", "## environment", "## config", "## test request"] {
+            assert!(bundle.contains(section), "{}", section);
+        }
+        assert!(bundle.contains(&format!("version: {}\n", env!("CARGO_PKG_VERSION"))));
+        assert!(bundle.contains(&format!("os: {}\n", std::env::consts::OS)));
+        assert!(bundle.contains("set: OPENAI_API_KEY, NO_COLOR\n"));
+        assert!(bundle.contains("openai_api_key = \"sk-...mnop\""));
+        assert!(!bundle.contains("sk-abcdefghijklmnop"));
+        assert!(!bundle.contains("proxy-secret-value"));
+    }
+}
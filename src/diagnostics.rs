@@ -0,0 +1,175 @@
+// Winston diagnostics
+// Warnings and errors are written to stderr so that stdout only carries the
+// completion. With --diagnostics-json each one is written as a single JSON
+// object with a level, a stable code, the message and any context, so programs
+// embedding winston don't have to parse the human readable form. The human
+// readable form has its level colored when color is on.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::Serialize;
+
+use crate::client::ApiError;
+use crate::error::WinstonError;
+use crate::term::paint;
+
+static JSON: AtomicBool = AtomicBool::new(false);
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+// write diagnostics as JSON for the rest of the run
+pub fn set_json(json: bool) {
+    JSON.store(json, Ordering::SeqCst);
+}
+
+pub fn json() -> bool {
+    JSON.load(Ordering::SeqCst)
+}
+
+// color the level of diagnostics for the rest of the run
+pub fn set_color(color: bool) {
+    COLOR.store(color, Ordering::SeqCst);
+}
+
+pub fn color() -> bool {
+    COLOR.load(Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub context: BTreeMap<&'static str, String>,
+}
+
+impl Diagnostic {
+    pub fn new(level: Level, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            level,
+            code,
+            message: message.into(),
+            context: BTreeMap::new(),
+        }
+    }
+
+    pub fn info(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Level::Info, code, message)
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Level::Warning, code, message)
+    }
+
+    // an error returned from the run, known errors keep their details
+    pub fn from_error(err: &(dyn std::error::Error + 'static)) -> Self {
+        if let Some(api_error) = err.downcast_ref::<ApiError>() {
+            return Self::new(Level::Error, "api_error", api_error.message.clone())
+                .with("status", api_error.status.as_u16())
+                .with("url", &api_error.url);
+        }
+        match err.downcast_ref::<WinstonError>() {
+            Some(WinstonError::TruncatedResponse { bytes }) => {
+                Self::new(Level::Error, "truncated_response", err.to_string()).with("bytes", bytes)
+            }
+            Some(WinstonError::StreamInterrupted { received, .. }) => {
+                Self::new(Level::Error, "stream_interrupted", err.to_string()).with("received", received)
+            }
+            Some(WinstonError::EmptyPrompt) => Self::new(Level::Error, "empty_prompt", err.to_string()),
+            Some(WinstonError::TooManyStopSequences { count }) => {
+                Self::new(Level::Error, "too_many_stop_sequences", err.to_string()).with("count", count)
+            }
+            Some(WinstonError::InsecurePermissions { path, mode }) => {
+                Self::new(Level::Error, "insecure_permissions", err.to_string())
+                    .with("path", path.display())
+                    .with("mode", format!("{:o}", mode))
+            }
+            Some(WinstonError::RequestTooLarge { size, limit }) => {
+                Self::new(Level::Error, "request_too_large", err.to_string()).with("size", size).with("limit", limit)
+            }
+            Some(WinstonError::ContentPathNotFound { path }) => {
+                Self::new(Level::Error, "content_path_not_found", err.to_string()).with("path", path)
+            }
+            Some(WinstonError::DeadlineExceeded { deadline }) => {
+                Self::new(Level::Error, "deadline_exceeded", err.to_string()).with("deadline_ms", deadline.as_millis())
+            }
+            Some(WinstonError::RetryAfterTooLong { retry_after, limit }) => {
+                Self::new(Level::Error, "retry_after_too_long", err.to_string())
+                    .with("retry_after", retry_after.as_secs())
+                    .with("max_retry_after", limit.as_secs())
+            }
+            Some(WinstonError::PromptFlagged { categories }) => {
+                Self::new(Level::Error, "prompt_flagged", err.to_string()).with("categories", categories.join(","))
+            }
+            None => Self::new(Level::Error, "error", err.to_string()),
+        }
+    }
+
+    // add a context value
+    pub fn with(mut self, key: &'static str, value: impl ToString) -> Self {
+        self.context.insert(key, value.to_string());
+        self
+    }
+
+    // the line written to stderr
+    pub fn render(&self, json: bool, color: bool) -> String {
+        if json {
+            return serde_json::to_string(self).unwrap_or_else(|_| self.message.clone());
+        }
+        match self.level {
+            Level::Info => self.message.clone(),
+            Level::Warning => format!("{}: {}", paint("warning", "1;33", color), self.message),
+            Level::Error => format!("{}: {}", paint("error", "1;31", color), self.message),
+        }
+    }
+
+    pub fn emit(&self) {
+        eprintln!("{}", self.render(json(), color()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // test that a JSON diagnostic carries the level, code, message and context
+    #[test]
+    fn diagnostic_json_test() {
+        let diagnostic = Diagnostic::warning("unsupported_parameter", "model `gpt-4o` may not support reasoning_effort")
+            .with("model", "gpt-4o")
+            .with("parameter", "reasoning_effort");
+        let value: serde_json::Value = serde_json::from_str(&diagnostic.render(true, false)).unwrap();
+        assert_eq!(value["level"], "warning");
+        assert_eq!(value["code"], "unsupported_parameter");
+        assert_eq!(value["message"], "model `gpt-4o` may not support reasoning_effort");
+        assert_eq!(value["context"]["model"], "gpt-4o");
+        assert_eq!(value["context"]["parameter"], "reasoning_effort");
+        assert_eq!(diagnostic.render(false, false), "warning: model `gpt-4o` may not support reasoning_effort");
+
+        let err: Box<dyn std::error::Error> = "No prompt given".into();
+        let value: serde_json::Value = serde_json::from_str(&Diagnostic::from_error(err.as_ref()).render(true, false)).unwrap();
+        assert_eq!(value["level"], "error");
+        assert_eq!(value["code"], "error");
+        assert!(value.get("context").is_none());
+    }
+
+    // test that the level is only colored when color is on
+    #[test]
+    fn diagnostic_color_test() {
+        let diagnostic = Diagnostic::new(Level::Error, "error", "No prompt given");
+        assert_eq!(diagnostic.render(false, true), "\x1b[1;31merror\x1b[0m: No prompt given");
+        // --color never on a terminal
+        let color = crate::term::ColorChoice::Never.enabled(None, true);
+        assert!(!diagnostic.render(false, color).contains('\x1b'));
+        assert!(!Diagnostic::warning("unsupported_parameter", "careful").render(false, color).contains('\x1b'));
+    }
+}
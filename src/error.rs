@@ -0,0 +1,112 @@
+// Winston errors
+// Most errors are reported as a message and never inspected, but the ones
+// callers need to tell apart, for instance to decide whether to retry, have
+// their own variant here.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Debug, PartialEq)]
+pub enum WinstonError {
+    // the response body ended before the JSON was complete, usually because a
+    // proxy cut it off
+    TruncatedResponse { bytes: usize },
+    // a streamed response stopped before its [DONE] line, after the content
+    // received so far was already passed on
+    StreamInterrupted { received: usize, reason: String },
+    // every message to be sent is empty or only whitespace
+    EmptyPrompt,
+    // more stop sequences were configured than the API accepts
+    TooManyStopSequences { count: usize },
+    // a config file holding an API key can be read by group or others
+    InsecurePermissions { path: PathBuf, mode: u32 },
+    // the request body is larger than max_request_bytes, so it wasn't sent
+    RequestTooLarge { size: usize, limit: usize },
+    // response_content_path doesn't lead to a string in the response
+    ContentPathNotFound { path: String },
+    // a request with its retries took longer than --deadline
+    DeadlineExceeded { deadline: Duration },
+    // a rate limited server asked to wait longer than max_retry_after, with
+    // --fail-on-retry-after
+    RetryAfterTooLong { retry_after: Duration, limit: Duration },
+    // the moderations endpoint flagged the prompt with --moderate, so it
+    // wasn't sent
+    PromptFlagged { categories: Vec<String> },
+}
+
+impl WinstonError {
+    // whether the request may succeed when sent again
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            WinstonError::TruncatedResponse { .. } => true,
+            WinstonError::StreamInterrupted { .. } => false,
+            WinstonError::EmptyPrompt => false,
+            WinstonError::TooManyStopSequences { .. } => false,
+            WinstonError::InsecurePermissions { .. } => false,
+            WinstonError::RequestTooLarge { .. } => false,
+            WinstonError::ContentPathNotFound { .. } => false,
+            WinstonError::DeadlineExceeded { .. } => false,
+            WinstonError::RetryAfterTooLong { .. } => false,
+            WinstonError::PromptFlagged { .. } => false,
+        }
+    }
+}
+
+impl fmt::Display for WinstonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WinstonError::TruncatedResponse { bytes } => write!(
+                f,
+                "The response was cut off after {} bytes, retrying the request may help",
+                bytes
+            ),
+            WinstonError::StreamInterrupted { received, reason } => write!(
+                f,
+                "The stream was interrupted after {} bytes of content: {}",
+                received, reason
+            ),
+            WinstonError::EmptyPrompt => f.write_str("The prompt is empty, nothing was sent"),
+            WinstonError::TooManyStopSequences { count } => write!(
+                f,
+                "{} stop sequences were given, at most {} are allowed",
+                count,
+                crate::config::MAX_STOP_SEQUENCES
+            ),
+            WinstonError::InsecurePermissions { path, mode } => write!(
+                f,
+                "{} holds an API key and can be read by other users (mode {:o}), run chmod 600 on it",
+                path.display(),
+                mode
+            ),
+            WinstonError::RequestTooLarge { size, limit } => write!(
+                f,
+                "The request is {} bytes, more than max_request_bytes of {}, nothing was sent",
+                size, limit
+            ),
+            WinstonError::ContentPathNotFound { path } => write!(
+                f,
+                "The response has no text at response_content_path `{}`",
+                path
+            ),
+            WinstonError::DeadlineExceeded { deadline } => write!(
+                f,
+                "The request didn't complete within the deadline of {} ms, retries included",
+                deadline.as_millis()
+            ),
+            WinstonError::RetryAfterTooLong { retry_after, limit } => write!(
+                f,
+                "The server is rate limiting and asked to wait {} s before retrying, more than max_retry_after of {} s",
+                retry_after.as_secs(),
+                limit.as_secs()
+            ),
+            WinstonError::PromptFlagged { categories } => write!(
+                f,
+                "The prompt was flagged by moderation for {}, nothing was sent",
+                categories.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WinstonError {}
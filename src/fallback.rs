@@ -0,0 +1,110 @@
+// Winston model fallback
+// With --fallback-models, or fallback_models in the config file, a request the
+// model can't take right now goes to the next model in the chain: when the API
+// answers 404 because the model doesn't exist or isn't available to the key,
+// or 429 or 503 because it is overloaded once the client's own retries are
+// used up. Other errors are returned as they are. The model that answered is
+// reported when it isn't the first one.
+
+use crate::chat::{api_for_model, ChatRequest};
+use crate::client::ApiError;
+use crate::config::ApiKind;
+use crate::diagnostics::Diagnostic;
+use super::Result;
+
+// statuses that say the model can't answer now, but another might
+const UNAVAILABLE: &[u16] = &[404, 429, 503];
+
+// whether an error means the next model in the chain should be tried
+pub fn is_unavailable(err: &(dyn std::error::Error + 'static)) -> bool {
+    err.downcast_ref::<ApiError>()
+        .is_some_and(|err| UNAVAILABLE.contains(&err.status.as_u16()))
+}
+
+// send the request with send, then again with each fallback model in turn
+// while the model is unavailable, returning the result and the model that
+// answered. The request shape follows api, or each model when it is unset.
+pub fn send<T>(
+    request: &ChatRequest,
+    fallbacks: &[String],
+    api: Option<ApiKind>,
+    mut send: impl FnMut(&ChatRequest) -> Result<T>,
+) -> Result<(T, String)> {
+    let mut request = request.clone();
+    let mut fallbacks = fallbacks.iter();
+    loop {
+        match send(&request) {
+            Ok(result) => return Ok((result, request.model)),
+            Err(err) if is_unavailable(err.as_ref()) => {
+                let Some(next) = fallbacks.next() else {
+                    return Err(err);
+                };
+                Diagnostic::warning("model_fallback", format!("model `{}` is unavailable ({}), trying `{}`", request.model, err, next))
+                    .with("model", &request.model)
+                    .with("fallback", next)
+                    .emit();
+                request.model = next.clone();
+                request.api = api.unwrap_or_else(|| api_for_model(next));
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chat::{Message, Role};
+    use crate::client::Client;
+    use crate::config::WinstonConfigBuilder;
+
+    // test that a model answering 404 falls back to the next one, and that
+    // other errors don't
+    #[test]
+    fn model_fallback_test() {
+        let mut server = mockito::Server::new();
+        let missing = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJsonString(r#"{"model":"gpt-5-preview"}"#.to_string()))
+            .with_status(404)
+            .with_body(r#"{"error":{"message":"The model `gpt-5-preview` does not exist"}}"#)
+            .expect(2)
+            .create();
+        let fallback = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJsonString(r#"{"model":"gpt-4o"}"#.to_string()))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"role":"assistant","content":"Hello from gpt-4o"}}]}"#)
+            .expect(1)
+            .create();
+        let unauthorized = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJsonString(r#"{"model":"gpt-4o-mini"}"#.to_string()))
+            .with_status(401)
+            .with_body(r#"{"error":{"message":"Incorrect API key provided"}}"#)
+            .expect(1)
+            .create();
+
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .model("gpt-5-preview".to_string())
+            .build()
+            .unwrap();
+        let client = Client::from_config(&config).unwrap();
+        let request = ChatRequest::new(&config, vec![Message::new(Role::User, "hello")]);
+
+        let chain = ["gpt-4o".to_string(), "gpt-4o-mini".to_string()];
+        let (response, model) = send(&request, &chain, None, |request| client.chat(request)).unwrap();
+        assert_eq!(model, "gpt-4o");
+        assert_eq!(response.content(), Some("Hello from gpt-4o"));
+
+        let chain = ["gpt-4o-mini".to_string(), "gpt-4o".to_string()];
+        let err = send(&request, &chain, None, |request| client.chat(request)).unwrap_err();
+        assert!(err.to_string().contains("Incorrect API key provided"));
+
+        missing.assert();
+        fallback.assert();
+        unauthorized.assert();
+    }
+}
@@ -0,0 +1,55 @@
+// Winston input decoding
+// Prompts read from stdin or a file are UTF-8 by default. --input-encoding
+// names another encoding, by any of its WHATWG labels such as latin1 or
+// shift_jis, and the input is transcoded to UTF-8 before it is sent. Bytes
+// that aren't valid in the chosen encoding are an error rather than being
+// replaced, so a wrong guess doesn't quietly garble the prompt.
+
+use encoding_rs::Encoding;
+
+use super::Result;
+
+// the encoding named by a label, for parsing --input-encoding
+pub fn parse_encoding(label: &str) -> std::result::Result<&'static Encoding, String> {
+    Encoding::for_label(label.trim().as_bytes()).ok_or_else(|| format!("Unknown encoding `{}`", label))
+}
+
+// transcode input read from source to UTF-8
+pub fn decode(bytes: &[u8], encoding: &'static Encoding, source: &str) -> Result<String> {
+    if encoding == encoding_rs::UTF_8 {
+        return String::from_utf8(bytes.to_vec()).map_err(|e| {
+            let at = e.utf8_error().valid_up_to();
+            format!("{} is not valid UTF-8 at byte {}, set --input-encoding to its encoding", source, at).into()
+        });
+    }
+    encoding
+        .decode_without_bom_handling_and_without_replacement(bytes)
+        .map(|text| text.into_owned())
+        .ok_or_else(|| format!("{} is not valid {}", source, encoding.name()).into())
+}
+
+// read a file and transcode it to UTF-8
+pub fn read_file(fp: &std::path::Path, encoding: &'static Encoding) -> Result<String> {
+    let bytes = std::fs::read(fp).map_err(|e| format!("Could not read {}: {}", fp.display(), e))?;
+    decode(&bytes, encoding, &fp.display().to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // test that Latin-1 input is transcoded and invalid input reported
+    #[test]
+    fn decode_latin1_test() {
+        let latin1 = parse_encoding("latin1").unwrap();
+        assert_eq!(decode(b"caf\xe9 cr\xe8me", latin1, "stdin").unwrap(), "café crème");
+        assert_eq!(
+            decode(b"caf\xe9", encoding_rs::UTF_8, "stdin").unwrap_err().to_string(),
+            "stdin is not valid UTF-8 at byte 3, set --input-encoding to its encoding"
+        );
+        let shift_jis = parse_encoding("shift_jis").unwrap();
+        assert_eq!(decode(b"\x82\xa0", shift_jis, "stdin").unwrap(), "あ");
+        assert_eq!(decode(b"\x82", shift_jis, "stdin").unwrap_err().to_string(), "stdin is not valid Shift_JIS");
+        assert!(parse_encoding("klingon").is_err());
+    }
+}
@@ -0,0 +1,107 @@
+// Winston response language
+// With --lang <code>, or lang in the config file, every request carries a
+// short directive to respond in that language, named from its ISO 639-1 code.
+// The directive goes at the end of the system message, after any configured or
+// persona prompt, and makes up the system message on its own when there is
+// none. --no-lang, or lang = "none", leaves it out.
+
+use super::Result;
+
+// ISO 639-1 codes and the languages they name
+pub const LANGUAGES: &[(&str, &str)] = &[
+    ("ar", "Arabic"),
+    ("bn", "Bengali"),
+    ("cs", "Czech"),
+    ("da", "Danish"),
+    ("de", "German"),
+    ("el", "Greek"),
+    ("en", "English"),
+    ("es", "Spanish"),
+    ("fa", "Persian"),
+    ("fi", "Finnish"),
+    ("fr", "French"),
+    ("he", "Hebrew"),
+    ("hi", "Hindi"),
+    ("hu", "Hungarian"),
+    ("id", "Indonesian"),
+    ("it", "Italian"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+    ("nl", "Dutch"),
+    ("no", "Norwegian"),
+    ("pl", "Polish"),
+    ("pt", "Portuguese"),
+    ("ro", "Romanian"),
+    ("ru", "Russian"),
+    ("sv", "Swedish"),
+    ("sw", "Swahili"),
+    ("th", "Thai"),
+    ("tr", "Turkish"),
+    ("uk", "Ukrainian"),
+    ("vi", "Vietnamese"),
+    ("zh", "Chinese"),
+];
+
+// the language named by a code, ignoring case and any region as in pt-BR
+pub fn language(code: &str) -> Result<&'static str> {
+    let base = code.split(['-', '_']).next().unwrap_or_default().to_ascii_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(known, _)| *known == base)
+        .map(|&(_, name)| name)
+        .ok_or_else(|| format!("Unknown language code `{}`, expected an ISO 639-1 code like fr or de", code).into())
+}
+
+// the directive sent for a code
+pub fn directive(code: &str) -> Result<String> {
+    Ok(format!("Respond in {}.", language(code)?))
+}
+
+// a system message with the directive added at the end, unless it already
+// ends with it
+pub fn compose(system: Option<&str>, directive: &str) -> String {
+    match system.map(str::trim_end) {
+        Some(system) if system.ends_with(directive) => system.to_string(),
+        Some(system) if !system.is_empty() => format!("{}\n\n{}", system, directive),
+        _ => directive.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chat::{ChatRequest, Message, Role};
+    use clap::Parser;
+
+    // test that the language directive is added to the system message, or
+    // sent as one, and left out with --no-lang
+    #[test]
+    fn lang_directive_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("config.toml");
+        std::fs::write(&temp_file, "system = \"Be brief.\"\nlang = \"de\"\n").unwrap();
+        let config_arg = temp_file.to_str().unwrap();
+        let request = |args: &[&str]| {
+            let opts = crate::cli::Options::try_parse_from(["winston", "--config", config_arg].iter().chain(args)).unwrap();
+            let config = crate::config::resolve_config(&opts).unwrap();
+            ChatRequest::new(&config, vec![Message::new(Role::User, "hello")])
+        };
+
+        assert_eq!(request(&[]).messages[0], Message::new(Role::System, "Be brief.\n\nRespond in German."));
+        assert_eq!(request(&["--lang", "FR"]).messages[0], Message::new(Role::System, "Be brief.\n\nRespond in French."));
+        assert_eq!(request(&["--lang", "fr", "--no-system"]).messages[0], Message::new(Role::System, "Respond in French."));
+        assert_eq!(request(&["--no-lang"]).messages[0], Message::new(Role::System, "Be brief."));
+        assert_eq!(request(&["--no-lang", "--no-system"]).messages.len(), 1);
+
+        // a request built again from its own messages gets the directive once
+        let config = crate::config::WinstonConfigBuilder::new().lang("pt-BR".to_string()).build().unwrap();
+        let first = ChatRequest::new(&config, vec![Message::new(Role::User, "hello")]);
+        let again = ChatRequest::new(&config, first.messages.clone());
+        assert_eq!(again.messages, first.messages);
+        assert_eq!(first.messages[0].content, "Respond in Portuguese.");
+
+        assert!(language("xx").is_err());
+        let opts = crate::cli::Options::try_parse_from(["winston", "--lang", "klingon"]).unwrap();
+        assert!(crate::config::resolve_config(&opts).is_err());
+    }
+}
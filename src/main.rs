@@ -1,12 +1,646 @@
+mod anthropic;
+mod batch;
+mod bench;
+mod cache;
+mod chat;
+mod chunk;
 mod cli;
+mod client;
+mod compare;
+#[cfg(feature = "clipboard")]
+mod clipboard;
 mod config;
+mod cost;
+mod debug_bundle;
+mod diagnostics;
+mod error;
+mod fallback;
+mod input;
+mod lang;
+mod moderate;
+mod pager;
+mod persona;
+mod ping;
+mod prompt_file;
+mod prompt_url;
+mod repl;
+mod record;
+mod replay;
+mod request_template;
+mod schema;
+mod secrets;
+mod session;
+mod stream;
+mod term;
+mod think;
+mod tools;
+mod version;
+mod warmup;
+mod wizard;
 
-use clap::Parser;
+use std::io::{IsTerminal, Read, Write};
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
-fn main() -> Result<()> {
-    let opts = cli::Options::parse();
-    println!("{:?}", opts);
+fn main() {
+    let opts = cli::Options::try_parse_args(std::env::args_os()).unwrap_or_else(|e| e.exit());
+    diagnostics::set_json(opts.diagnostics_json);
+    let color = if opts.no_color { term::ColorChoice::Never } else { opts.color };
+    diagnostics::set_color(color.enabled(std::env::var("NO_COLOR").ok().as_deref(), std::io::stderr().is_terminal()));
+    // errors go through diagnostics so they are colored or written as JSON
+    // like warnings, the human form shows the whole error
+    if let Err(e) = run(&opts) {
+        let mut diagnostic = diagnostics::Diagnostic::from_error(e.as_ref());
+        if !diagnostics::json() {
+            diagnostic.message = e.to_string();
+        }
+        diagnostic.emit();
+        std::process::exit(1);
+    }
+}
+
+fn run(opts: &cli::Options) -> Result<()> {
+    if opts.version_json {
+        println!("{}", version::VersionInfo::current().json());
+        return Ok(());
+    }
+    if opts.explain_config {
+        let (config, sources) = config::resolve_config_sources(opts)?;
+        print!("{}", config.explain(&sources)?);
+        return Ok(());
+    }
+    let config = config::resolve_config(opts)?;
+    if let Some(path) = &opts.dump_config {
+        config.dump_config(path, opts.include_secrets)?;
+    }
+    if opts.list_presets {
+        print!("{}", config.list_presets()?);
+        return Ok(());
+    }
+    match opts.command {
+        Some(cli::Command::Ping { count }) => {
+            let client = client::Client::from_config(&config)?;
+            let stdout = std::io::stdout();
+            let rate_limit = ping::run(&client, count, stdout.is_terminal(), &mut stdout.lock())?;
+            if let (true, Some(rate_limit)) = (opts.verbose || opts.show_usage, rate_limit) {
+                eprintln!("{}", rate_limit);
+            }
+        }
+        Some(cli::Command::Moderate { ref text }) => {
+            let client = client::Client::from_config(&config)?;
+            let text = read_prompt(text, opts.input_encoding)?;
+            chat::check_prompt(&[chat::Message::new(chat::Role::User, text.as_str())])?;
+            print!("{}", client.moderate(&text)?.render());
+        }
+        Some(cli::Command::Warmup) => {
+            let client = client::Client::from_config(&config)?;
+            let elapsed = warmup::run(&client, &config)?;
+            println!("{} ready in {:.1} ms", config.model, ping::millis(elapsed));
+        }
+        Some(cli::Command::Bench { requests, ref prompt }) => {
+            warn_unsupported(&config);
+            let client = client::Client::from_config(&config)?;
+            let request = chat::ChatRequest::new(&config, vec![chat::Message::new(chat::Role::User, read_prompt(prompt, opts.input_encoding)?)]);
+            check_prompt(opts, &client, &request.messages)?;
+            println!("{}", bench::run(&client, &request, requests, config.max_concurrency));
+        }
+        Some(cli::Command::Ask { ref persona, ref prompt }) => {
+            warn_unsupported(&config);
+            let client = client::Client::from_config(&config)?;
+            let request = persona::request(&config, persona, read_prompt(prompt, opts.input_encoding)?)?;
+            check_prompt(opts, &client, &request.messages[1..])?;
+            let response = client.chat(&request)?;
+            let content = response.content().unwrap_or_default();
+            print!("{}{}", opts.line_endings.normalize(chat::output_text(content, !opts.no_trim)), opts.line_endings.normalize("\n"));
+        }
+        Some(cli::Command::Edit { ref instruction }) => {
+            warn_unsupported(&config);
+            let client = client::Client::from_config(&config)?;
+            let text = read_prompt(&[], opts.input_encoding)?;
+            let request = chat::edit_request(&config, &instruction.join(" "), &text);
+            check_prompt(opts, &client, &request.messages[1..])?;
+            let response = client.chat(&request)?;
+            let content = response.content().unwrap_or_default();
+            print!("{}{}", opts.line_endings.normalize(chat::output_text(content, !opts.no_trim)), opts.line_endings.normalize("\n"));
+        }
+        Some(cli::Command::Run { ref template, ref vars }) => {
+            warn_unsupported(&config);
+            let client = client::Client::from_config(&config)?;
+            let fp = request_template::template_file(&request_template::requests_dir()?, template)?;
+            let vars = vars.iter().cloned().collect();
+            let request = request_template::RequestTemplate::load(&fp)?.request(&config, &vars)?;
+            check_prompt(opts, &client, &request.messages)?;
+            let response = client.chat(&request)?;
+            let content = response.content().unwrap_or_default();
+            print!("{}{}", opts.line_endings.normalize(chat::output_text(content, !opts.no_trim)), opts.line_endings.normalize("\n"));
+        }
+        Some(cli::Command::Compare { ref models, ref prompt }) => {
+            let client = client::Client::from_config(&config)?;
+            let request = chat::ChatRequest::new(&config, vec![chat::Message::new(chat::Role::User, read_prompt(prompt, opts.input_encoding)?)]);
+            check_prompt(opts, &client, &request.messages)?;
+            let replies = compare::run(&client, &request, models, config.api);
+            let width = term::width();
+            let replies: Vec<String> = replies.iter().map(|reply| reply.render(width)).collect();
+            println!("{}", replies.join("\n\n"));
+        }
+        Some(cli::Command::Batch { ref file, resume, restart, ref state_dir, fail_fast, unordered, warmup }) => {
+            warn_unsupported(&config);
+            let client = client::Client::from_config(&config)?;
+            if warmup {
+                let elapsed = warmup::run(&client, &config)?;
+                if !opts.quiet {
+                    diagnostics::Diagnostic::info("warmup", format!("endpoint ready in {:.1} ms", ping::millis(elapsed)))
+                        .with("elapsed_ms", elapsed.as_millis())
+                        .emit();
+                }
+            }
+            let input = match file {
+                Some(file) => input::read_file(file, opts.input_encoding)?,
+                None => read_prompt(&[], opts.input_encoding)?,
+            };
+            let prompts: Vec<String> = input.lines().map(str::to_string).collect();
+            // every prompt is checked before any is sent, blank lines are skipped
+            for prompt in prompts.iter().map(|prompt| prompt.trim()).filter(|prompt| !prompt.is_empty()) {
+                check_prompt(opts, &client, &[chat::Message::new(chat::Role::User, prompt)])?;
+            }
+            let state = if resume || restart {
+                let path = match state_dir {
+                    Some(path) => path.clone(),
+                    None => batch::StateDir::default_path()?,
+                };
+                let state = batch::StateDir::new(path)?;
+                if restart {
+                    state.clear()?;
+                }
+                Some(state)
+            } else {
+                None
+            };
+            let report = batch::run(&client, &config, &prompts, state.as_ref(), fail_fast, unordered, &mut std::io::stdout().lock())?;
+            if report.skipped > 0 && !opts.quiet {
+                diagnostics::Diagnostic::info("batch_skipped", format!("skipped {} prompts completed in an earlier run", report.skipped))
+                    .with("skipped", report.skipped)
+                    .emit();
+            }
+            if fail_fast && report.failed > 0 {
+                return Err("The batch stopped at the first failed prompt".into());
+            }
+        }
+        Some(cli::Command::Repl { budget_tokens, summarize_history, ref session, save_macros, usage_footer }) => {
+            warn_unsupported(&config);
+            let client = client::Client::from_config(&config)?;
+            let mut session = match session {
+                Some(name) => {
+                    let file = session::session_file(&session::sessions_dir()?, name)?;
+                    let mut session = repl::Session::new(budget_tokens, !opts.no_trim);
+                    session.messages = session::load(&file)?;
+                    session.file = Some(file);
+                    session
+                }
+                None => repl::Session::new(budget_tokens, !opts.no_trim),
+            };
+            session.summarize_above = summarize_history;
+            session.strict_tokens = opts.strict_tokens;
+            session.moderate = opts.moderate;
+            session.footer = usage_footer;
+            let macros_file = repl::macros_file()?;
+            session.macros = repl::load_macros(&macros_file)?;
+            if save_macros {
+                session.macros_file = Some(macros_file);
+            }
+            repl::run(&client, &config, &mut session, std::io::stdin().lock(), &mut std::io::stdout())?;
+        }
+        Some(cli::Command::Session { ref action }) => match action {
+            cli::SessionCommand::List { since } => {
+                let sessions = session::list(&session::sessions_dir()?, *since, std::time::SystemTime::now())?;
+                let names: Vec<&str> = sessions.iter().map(|entry| entry.name.as_str()).collect();
+                // one name per line when piped, for scripts
+                match std::io::stdout().is_terminal() {
+                    true => print!("{}", term::columns(&names, term::width())),
+                    false => names.iter().for_each(|name| println!("{}", name)),
+                }
+            }
+            cli::SessionCommand::Export { name, format, output } => {
+                let file = session::session_file(&session::sessions_dir()?, name)?;
+                if !file.exists() {
+                    return Err(format!("No session named `{}`", name).into());
+                }
+                let document = session::export(&session::load(&file)?, *format, config.assistant_label.as_deref());
+                let document = opts.line_endings.normalize(&document);
+                match output {
+                    Some(output) => std::fs::write(output, document.as_bytes())
+                        .map_err(|e| format!("Could not write {}: {}", output.display(), e))?,
+                    None => print!("{}", document),
+                }
+            }
+        },
+        Some(cli::Command::Cache { ref action }) => {
+            let dir = cache::cache_dir()?;
+            match action {
+                cli::CacheCommand::Info => println!("{}", cache::CacheInfo::new(&dir)?),
+                cli::CacheCommand::Clear { older_than } => {
+                    let removed = cache::clear(&dir, *older_than, std::time::SystemTime::now())?;
+                    println!("Removed {} cache entries", removed);
+                }
+            }
+        }
+        Some(cli::Command::DebugBundle { ref path }) => {
+            let test_request = match client::Client::from_config(&config) {
+                Ok(client) => debug_bundle::test_request(&client),
+                Err(e) => format!("failed: {}", e),
+            };
+            let bundle = debug_bundle::render(&config, |name| std::env::var_os(name).is_some(), &test_request)?;
+            std::fs::write(path, bundle).map_err(|e| format!("Could not write debug bundle {}: {}", path.display(), e))?;
+            println!("Wrote the debug bundle to {}", path.display());
+        }
+        Some(cli::Command::Config { ref action }) => match action {
+            cli::ConfigCommand::Get { field } => println!("{}", config.get(field)?),
+            cli::ConfigCommand::List => print!("{}", config.list()?),
+            cli::ConfigCommand::ExportEnv { include_secrets } => print!("{}", config.export_env(*include_secrets)?),
+            cli::ConfigCommand::Wizard => {
+                let mut prompter = wizard::Prompter::new(std::io::stdin().lock(), std::io::stdout(), std::io::stdin().is_terminal());
+                wizard::run(&mut prompter, config, opts.config_file.clone(), opts.config_format)?;
+            }
+            #[cfg(feature = "keyring")]
+            cli::ConfigCommand::StoreKey { org } => {
+                let (field, label) = if *org { ("openai_org_id", "Organization ID") } else { ("openai_api_key", "API key") };
+                let mut prompter = wizard::Prompter::new(std::io::stdin().lock(), std::io::stdout(), std::io::stdin().is_terminal());
+                let secret = prompter.ask_secret(label, None)?;
+                if secret.is_empty() {
+                    return Err(format!("No {} given", label).into());
+                }
+                secrets::store(field, &secret)?;
+                println!("Saved {} in the keyring, set {} = \"keyring\" in the config file to use it", label, field);
+            }
+        },
+        None if opts.chunk => {
+            warn_unsupported(&config);
+            let client = client::Client::from_config(&config)?;
+            if opts.prompt.is_empty() {
+                return Err("--chunk needs the instruction as the prompt and the document on stdin".into());
+            }
+            let mut stdin = std::io::stdin();
+            if stdin.is_terminal() {
+                return Err("No document given on stdin to --chunk".into());
+            }
+            let mut document = Vec::new();
+            stdin.read_to_end(&mut document)?;
+            let document = input::decode(&document, opts.input_encoding, "stdin")?;
+            chat::check_prompt(&[chat::Message::new(chat::Role::User, document.as_str())])?;
+            let instruction = opts.prompt.join(" ");
+            let messages = [
+                chat::Message::new(chat::Role::User, instruction.as_str()),
+                chat::Message::new(chat::Role::User, document.as_str()),
+            ];
+            check_prompt(opts, &client, &messages)?;
+            let content = chunk::run(&client, &config, &instruction, &document, opts.strict_tokens)?;
+            print!("{}{}", opts.line_endings.normalize(&content), opts.line_endings.normalize("\n"));
+        }
+        None => send_prompt(opts, &config)?,
+    }
+    Ok(())
+}
+
+// check a prompt before it is sent, and with --moderate have it moderated,
+// every subcommand that sends a prompt goes through here
+fn check_prompt(opts: &cli::Options, client: &client::Client, messages: &[chat::Message]) -> Result<()> {
+    chat::check_prompt(messages)?;
+    if opts.moderate {
+        moderate::check(client, messages)?;
+    }
     Ok(())
 }
+
+// send the prompt and print the completion
+fn send_prompt(opts: &cli::Options, config: &config::WinstonConfig) -> Result<()> {
+    warn_unsupported(config);
+    let mut client = client::Client::from_config(config)?;
+    if let Some(dir) = &opts.record {
+        client = client.record(dir);
+    }
+    let mut request = match &opts.replay {
+        Some(record) => replay::parse(&replay::read_record(record)?, config)?,
+        None => chat::ChatRequest::new(config, read_messages(opts, config)?),
+    };
+    request.n = opts.count.or(request.n);
+    check_prompt(opts, &client, &request.messages)?;
+    if !opts.file_id.is_empty() {
+        if chat::supports_files(config.flavor, request.api) {
+            request.attach_files(&opts.file_id);
+        } else {
+            diagnostics::Diagnostic::warning("ignored_option", "file references aren't supported by this flavor or API, ignoring --file-id")
+                .with("option", "file_id")
+                .emit();
+        }
+    }
+    if let Some(fp) = &opts.schema {
+        request.response_format = Some(schema::ResponseFormat::load(fp)?);
+    }
+    if let Some(fp) = &opts.tools {
+        request.tools = Some(chat::load_tools(fp)?);
+    }
+    if opts.no_parallel_tools {
+        if request.tools.is_some() {
+            request.parallel_tool_calls = Some(false);
+        } else {
+            diagnostics::Diagnostic::warning("ignored_option", "--no-parallel-tools has no effect without tools")
+                .with("option", "no_parallel_tools")
+                .emit();
+        }
+    }
+    if opts.strict_tokens && (config.warn_tokens > 0 || opts.confirm_cost) {
+        cost::strict_estimate(&request.model, "the prompt size")?;
+    }
+    let estimate = cost::Estimate::new(&request);
+    let warning = cost::large_prompt(&estimate, config.warn_tokens);
+    if let Some(warning) = &warning {
+        warning.emit();
+    }
+    if opts.confirm_cost || warning.is_some() {
+        let stdin = std::io::stdin();
+        if !cost::confirm(&estimate, stdin.lock(), std::io::stderr(), stdin.is_terminal(), opts.yes)? {
+            return Err("The request was not sent".into());
+        }
+    }
+    let pager = pager::command(
+        opts.pager,
+        std::io::stdout().is_terminal(),
+        opts.quiet,
+        std::env::var("WINSTON_PAGER").ok(),
+        std::env::var("PAGER").ok(),
+    );
+    let mut out = pager::output(pager)?;
+    if opts.echo_prompt && !opts.quiet {
+        write!(out, "{}", chat::echo_prompt(&request.messages, config.assistant_label.as_deref()))?;
+    }
+    // --raw prints the content exactly as received
+    let tags = opts.strip_think_tags.as_deref().filter(|_| !opts.raw);
+    let trim = !opts.no_trim && !opts.raw;
+    let strip = |content: &str| match tags {
+        Some(tags) => think::TagStripper::strip(tags, content),
+        None => content.to_string(),
+    };
+    let newline = opts.line_endings.normalize("\n");
+    let mut truncator = term::Truncator::new(opts.max_chars, &opts.truncation_marker);
+    // the model that answered, another than requested after a fallback
+    let requested = request.model.clone();
+    let mut answered_by = requested.clone();
+    let (content, refusal, usage) = if opts.stream {
+        let request = request.streaming(opts.show_usage);
+        let mut stripper = tags.map(think::TagStripper::new);
+        // leading whitespace can be trimmed as it arrives, trailing can't
+        let mut started = !trim;
+        // with --first-line nothing is printed after the first line break
+        let mut ended = false;
+        let mut endings = term::StreamLineEndings::new(opts.line_endings);
+        let mut print = |delta: &str| -> Result<()> {
+            let delta = if started { delta } else { delta.trim_start() };
+            started |= !delta.is_empty();
+            let delta = match delta.split_once('\n') {
+                _ if ended => "",
+                Some((line, _)) if opts.first_line => {
+                    ended = true;
+                    line.trim_end_matches('\r')
+                }
+                _ => delta,
+            };
+            if !opts.quiet {
+                let delta = truncator.push(delta);
+                out.write_all(endings.push(&delta).as_bytes())?;
+                out.flush()?;
+            }
+            Ok(())
+        };
+        let mut timer = stream::StreamTimer::start();
+        let mut on_delta = |delta: &str| {
+            timer.delta(delta);
+            match &mut stripper {
+                Some(stripper) => print(&stripper.push(delta)),
+                None => print(delta),
+            }
+        };
+        let summary = fallback::send(&request, &config.fallback_models, config.api, |request| match opts.auto_continue {
+            true => client.chat_stream_continued(request, opts.max_continuations, &mut on_delta),
+            false => client.chat_stream(request, &mut on_delta),
+        })
+        .map(|(summary, model)| {
+            answered_by = model;
+            summary
+        });
+        // text held back as a possible tag is still part of the completion
+        if let Some(stripper) = &mut stripper {
+            print(&stripper.finish())?;
+        }
+        if !opts.quiet {
+            out.write_all(endings.finish().as_bytes())?;
+        }
+        // end the partial completion before saying it is incomplete
+        let summary = match summary {
+            Err(e) if matches!(e.downcast_ref(), Some(error::WinstonError::StreamInterrupted { .. })) => {
+                if !opts.quiet {
+                    write!(out, "{}", newline)?;
+                }
+                drop(out);
+                eprintln!("[stream interrupted]");
+                return Err(e);
+            }
+            summary => summary?,
+        };
+        if !opts.quiet {
+            write!(out, "{}", newline)?;
+        }
+        if opts.show_usage || opts.verbose {
+            eprintln!("{}", timer.finish());
+        }
+        if let Some(format) = &request.response_format {
+            format.validate(&summary.content)?;
+        }
+        (strip(&summary.content), summary.refusal, summary.usage)
+    } else {
+        let cached = match opts.cache {
+            true => cache::get(&cache::cache_dir()?, config, &request)?,
+            false => None,
+        };
+        let response = match cached {
+            Some(response) => response,
+            None if opts.tool_runner.is_some() => {
+                let runner = opts.tool_runner.as_deref().unwrap_or_default();
+                if !opts.interactive_approve {
+                    tools::run(&client, request.clone(), runner, None)?
+                } else {
+                    let stdin = std::io::stdin();
+                    if !stdin.is_terminal() {
+                        return Err("--interactive-approve needs a terminal to answer on".into());
+                    }
+                    tools::run(&client, request.clone(), runner, Some((&mut stdin.lock(), &mut std::io::stderr())))?
+                }
+            }
+            None => {
+                let (response, model) = fallback::send(&request, &config.fallback_models, config.api, |request| client.chat(request))?;
+                // a fallback model's reply isn't the answer to the request as made
+                if opts.cache && model == request.model {
+                    cache::put(&cache::cache_dir()?, config, &request, &response)?;
+                }
+                answered_by = model;
+                response
+            }
+        };
+        if let Some(format) = &request.response_format {
+            for content in response.contents() {
+                format.validate(content)?;
+            }
+        }
+        let refusal = response.refusal().map(str::to_string);
+        let content = match opts.select {
+            Some(select) => strip(response.select(select).unwrap_or_default()),
+            None => {
+                let contents: Vec<String> = response.contents().map(|c| chat::output_text(&strip(c), trim).to_string()).collect();
+                contents.join("\n\n")
+            }
+        };
+        (content, refusal, response.usage)
+    };
+    let content = match opts.first_line {
+        true => chat::first_line(&content, trim).to_string(),
+        false => content,
+    };
+    // streamed content has already been printed as it arrived
+    if !opts.stream && !opts.quiet {
+        let text = truncator.push(chat::output_text(&content, trim));
+        write!(out, "{}{}", opts.line_endings.normalize(&text), newline)?;
+    }
+    // wait for the pager to be quit before anything else is printed
+    drop(out);
+    if answered_by != requested && !opts.quiet {
+        diagnostics::Diagnostic::info("answered_by", format!("answered by fallback model {}", answered_by))
+            .with("model", &answered_by)
+            .emit();
+    }
+    if truncator.truncated() {
+        diagnostics::Diagnostic::warning(
+            "truncated_output",
+            format!("the completion was cut to {} characters by --max-chars", opts.max_chars.unwrap_or_default()),
+        )
+        .with("max_chars", opts.max_chars.unwrap_or_default())
+        .emit();
+    }
+    #[cfg(feature = "clipboard")]
+    if opts.copy {
+        clipboard::copy(&content);
+    }
+    if let (true, Some(usage)) = (opts.show_usage, usage) {
+        eprintln!("{}", usage);
+    }
+    if opts.fail_on_refusal {
+        if let Some(refusal) = chat::refusal(&content, refusal.as_deref(), config.refusal_pattern.as_deref()) {
+            eprintln!("{}", refusal);
+            return Err("The model refused the request".into());
+        }
+    }
+    Ok(())
+}
+
+// the messages to send, from --messages-file, --prompt-file or the prompt,
+// with --prompt-suffix-file appended
+fn read_messages(opts: &cli::Options, config: &config::WinstonConfig) -> Result<Vec<chat::Message>> {
+    let mut messages = match &opts.messages_file {
+        Some(fp) => {
+            let mut messages = chat::load_messages(fp)?;
+            if opts.no_system {
+                messages.retain(|message| message.role != chat::Role::System);
+            }
+            messages
+        }
+        None => {
+            let prompt = match (&opts.prompt_file, &opts.prompt_url) {
+                (Some(fp), _) => prompt_file::PromptFile::load(fp, opts.input_encoding)?.prompt,
+                (None, Some(url)) => prompt_url::fetch(config, url, opts.input_encoding)?,
+                (None, None) => read_prompt(&opts.prompt, opts.input_encoding)?,
+            };
+            vec![chat::Message::new(chat::Role::User, prompt)]
+        }
+    };
+    if let Some(fp) = &opts.prompt_suffix_file {
+        chat::append_suffix_file(&mut messages, fp, opts.input_encoding)?;
+    }
+    Ok(messages)
+}
+
+// warn about parameters the configured model is unlikely to accept
+fn warn_unsupported(config: &config::WinstonConfig) {
+    if config.reasoning_effort.is_some() && !chat::supports_reasoning(&config.model) {
+        diagnostics::Diagnostic::warning(
+            "unsupported_parameter",
+            format!("model `{}` may not support reasoning_effort", config.model),
+        )
+        .with("model", &config.model)
+        .with("parameter", "reasoning_effort")
+        .emit();
+    }
+}
+
+// the prompt from the command line, or stdin when none was given
+fn read_prompt(words: &[String], encoding: &'static encoding_rs::Encoding) -> Result<String> {
+    if !words.is_empty() {
+        return Ok(words.join(" "));
+    }
+    let mut stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        return Err("No prompt given".into());
+    }
+    let mut prompt = Vec::new();
+    stdin.read_to_end(&mut prompt)?;
+    input::decode(&prompt, encoding, "stdin")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // test that with --moderate a flagged prompt given to ask or edit fails
+    // without a chat request being sent. Edit reads its text from stdin, so the
+    // checks run in a child process with the text piped in.
+    #[test]
+    fn moderate_subcommands_test() {
+        if std::env::var("WINSTON_MODERATE_TEST").is_err() {
+            let mut child = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "test::moderate_subcommands_test"])
+                .env("WINSTON_MODERATE_TEST", "1")
+                .stdin(std::process::Stdio::piped())
+                .stdout(std::process::Stdio::piped())
+                .spawn()
+                .unwrap();
+            child.stdin.take().unwrap().write_all(b"some threat").unwrap();
+            let output = child.wait_with_output().unwrap();
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+            return;
+        }
+
+        let mut server = mockito::Server::new();
+        let moderations = server
+            .mock("POST", "/v1/moderations")
+            .with_status(200)
+            .with_body(r#"{"results":[{"flagged":true,"categories":{"violence":true},"category_scores":{"violence":0.93}}]}"#)
+            .expect(2)
+            .create();
+        let chat = server.mock("POST", "/v1/chat/completions").expect(0).create();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("config.toml");
+        std::fs::write(&temp_file, "").unwrap();
+        let url = server.url();
+        let run_with = |args: &[&str]| {
+            let mut argv = vec!["winston", "--no-env", "--no-system-config", "--config", temp_file.to_str().unwrap()];
+            argv.extend(["-e", url.as_str(), "-k", "test-key", "--moderate"]);
+            argv.extend(args);
+            run(&cli::Options::try_parse_args(argv).unwrap())
+        };
+
+        let flagged = Some(&error::WinstonError::PromptFlagged { categories: vec!["violence".to_string()] });
+        let err = run_with(&["ask", "--as", "rust", "some threat"]).unwrap_err();
+        assert_eq!(err.downcast_ref::<error::WinstonError>(), flagged);
+        let err = run_with(&["edit", "make it friendlier"]).unwrap_err();
+        assert_eq!(err.downcast_ref::<error::WinstonError>(), flagged);
+        moderations.assert();
+        chat.assert();
+    }
+}
@@ -0,0 +1,130 @@
+// Winston moderation
+// `moderate <text>` sends text to the moderations endpoint and prints whether
+// it was flagged, with the flagged categories and their scores, most likely
+// first. With --moderate a prompt is checked the same way before it is sent,
+// and a flagged prompt fails the run without sending it, so a pipeline can
+// stop on it. Every subcommand that sends a prompt checks it, a batch checks
+// all its lines before sending any, and the repl skips a flagged prompt and
+// carries on. Only OpenAI endpoints have a moderations endpoint.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use serde::Deserialize;
+
+use crate::chat::{Message, Role};
+use crate::client::Client;
+use crate::error::WinstonError;
+use super::Result;
+
+#[derive(Debug, Deserialize)]
+pub struct ModerationResponse {
+    pub results: Vec<Moderation>,
+}
+
+// the verdict on one input
+#[derive(Debug, Deserialize)]
+pub struct Moderation {
+    pub flagged: bool,
+    #[serde(default)]
+    pub categories: BTreeMap<String, bool>,
+    #[serde(default)]
+    pub category_scores: BTreeMap<String, f64>,
+}
+
+impl Moderation {
+    // the flagged categories with their scores, highest first
+    pub fn flagged_categories(&self) -> Vec<(&str, f64)> {
+        let mut flagged: Vec<(&str, f64)> = self
+            .categories
+            .iter()
+            .filter(|(_, &flagged)| flagged)
+            .map(|(name, _)| (name.as_str(), self.category_scores.get(name).copied().unwrap_or_default()))
+            .collect();
+        flagged.sort_by(|a, b| b.1.total_cmp(&a.1));
+        flagged
+    }
+
+    // the verdict as printed by the moderate subcommand
+    pub fn render(&self) -> String {
+        if !self.flagged {
+            return "not flagged\n".to_string();
+        }
+        let flagged = self.flagged_categories();
+        let width = flagged.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        let mut rendered = String::from("flagged\n");
+        for (name, score) in flagged {
+            let _ = writeln!(rendered, "{:<width$}  {:.4}", name, score, width = width);
+        }
+        rendered
+    }
+}
+
+// check the user messages of a prompt, failing when they are flagged
+pub fn check(client: &Client, messages: &[Message]) -> Result<()> {
+    let input: Vec<&str> = messages
+        .iter()
+        .filter(|message| message.role == Role::User)
+        .map(|message| message.content.as_str())
+        .collect();
+    let moderation = client.moderate(&input.join("\n\n"))?;
+    if moderation.flagged {
+        let categories = moderation.flagged_categories().iter().map(|(name, _)| name.to_string()).collect();
+        return Err(WinstonError::PromptFlagged { categories }.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{ApiFlavor, WinstonConfigBuilder};
+
+    const FLAGGED: &str = r#"{"id":"modr-1","model":"omni-moderation-latest","results":[{"flagged":true,
+        "categories":{"harassment":true,"hate":false,"violence":true},
+        "category_scores":{"harassment":0.61,"hate":0.02,"violence":0.93}}]}"#;
+
+    // test that flagged categories are printed with their scores and that a
+    // flagged prompt fails the check
+    #[test]
+    fn moderation_flagged_test() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/v1/moderations")
+            .match_header("authorization", "Bearer test-key")
+            .match_body(mockito::Matcher::Json(serde_json::json!({"input": "some threat"})))
+            .with_status(200)
+            .with_body(FLAGGED)
+            .expect(2)
+            .create();
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .build()
+            .unwrap();
+        let client = Client::from_config(&config).unwrap();
+
+        let moderation = client.moderate("some threat").unwrap();
+        assert_eq!(moderation.flagged_categories(), [("violence", 0.93), ("harassment", 0.61)]);
+        assert_eq!(moderation.render(), "flagged\nviolence    0.9300\nharassment  0.6100\n");
+
+        let messages = [Message::new(Role::System, "Be brief."), Message::new(Role::User, "some threat")];
+        let err = check(&client, &messages).unwrap_err();
+        mock.assert();
+        assert_eq!(
+            err.downcast_ref::<WinstonError>(),
+            Some(&WinstonError::PromptFlagged { categories: vec!["violence".to_string(), "harassment".to_string()] })
+        );
+
+        let clean: Moderation = serde_json::from_str(r#"{"flagged":false}"#).unwrap();
+        assert_eq!(clean.render(), "not flagged\n");
+
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .flavor(ApiFlavor::Anthropic)
+            .build()
+            .unwrap();
+        assert!(Client::from_config(&config).unwrap().moderate("hello").is_err());
+    }
+}
@@ -0,0 +1,138 @@
+// Winston pager
+// With --pager a completion written to a terminal goes through a pager so a
+// long one doesn't scroll off screen. The pager is WINSTON_PAGER, then PAGER,
+// then less, which with -F quits on its own when the text fits on one screen.
+// Streamed deltas are passed on as they arrive and the pager shows them as it
+// reads them. Piped output and --quiet never use a pager. Quitting the pager
+// before the completion is done isn't an error, the rest is dropped.
+
+use std::io::{self, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+use super::Result;
+
+// the pager used when neither WINSTON_PAGER nor PAGER is set
+pub const DEFAULT_PAGER: &str = "less -FRX";
+
+// the pager command to run, if output should be paged at all
+pub fn command(enabled: bool, tty: bool, quiet: bool, winston_pager: Option<String>, pager: Option<String>) -> Option<String> {
+    if !enabled || !tty || quiet {
+        return None;
+    }
+    let command = winston_pager
+        .into_iter()
+        .chain(pager)
+        .find(|command| !command.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_PAGER.to_string());
+    Some(command)
+}
+
+// a running pager taking the output on its stdin
+pub struct Pager {
+    child: Child,
+    stdin: Option<ChildStdin>,
+}
+
+impl Pager {
+    // start the pager command through the shell, so it may have arguments
+    pub fn spawn(command: &str) -> Result<Self> {
+        let mut shell = if cfg!(windows) {
+            let mut shell = Command::new("cmd");
+            shell.arg("/C");
+            shell
+        } else {
+            let mut shell = Command::new("sh");
+            shell.arg("-c");
+            shell
+        };
+        let mut child = shell
+            .arg(command)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Could not start pager `{}`: {}", command, e))?;
+        let stdin = child.stdin.take();
+        Ok(Self { child, stdin })
+    }
+}
+
+impl Write for Pager {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(stdin) = &mut self.stdin else {
+            return Ok(buf.len());
+        };
+        match stdin.write(buf) {
+            // the pager was quit, drop the rest
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+                self.stdin = None;
+                Ok(buf.len())
+            }
+            result => result,
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.stdin.as_mut().map(ChildStdin::flush) {
+            Some(Err(e)) if e.kind() != io::ErrorKind::BrokenPipe => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+// closing its stdin lets the pager see the end, then it is waited on so the
+// terminal is given back before winston exits
+impl Drop for Pager {
+    fn drop(&mut self) {
+        self.stdin = None;
+        let _ = self.child.wait();
+    }
+}
+
+// where the completion is written, the pager for command or stdout
+pub fn output(command: Option<String>) -> Result<Box<dyn Write>> {
+    Ok(match command {
+        Some(command) => Box::new(Pager::spawn(&command)?),
+        None => Box::new(io::stdout()),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // test that a pager is only used on a terminal and without --quiet, and
+    // that WINSTON_PAGER is preferred over PAGER
+    #[test]
+    fn pager_command_test() {
+        let env = |value: &str| Some(value.to_string());
+        assert_eq!(command(true, true, false, None, None), Some(DEFAULT_PAGER.to_string()));
+        assert_eq!(command(true, true, false, env("most"), env("more")), Some("most".to_string()));
+        assert_eq!(command(true, true, false, env(""), env("more")), Some("more".to_string()));
+        assert_eq!(command(false, true, false, None, env("more")), None);
+        assert_eq!(command(true, false, false, None, env("more")), None);
+        assert_eq!(command(true, true, true, None, env("more")), None);
+    }
+
+    // test that output written on a terminal goes through the pager command
+    #[cfg(unix)]
+    #[test]
+    fn pager_output_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let paged = temp_dir.path().join("paged.txt");
+        let fake_pager = format!("cat > '{}'", paged.display());
+        let command = command(true, true, false, Some(fake_pager), None);
+
+        let mut out = output(command).unwrap();
+        for delta in ["Long ", "streamed ", "completion\n"] {
+            out.write_all(delta.as_bytes()).unwrap();
+            out.flush().unwrap();
+        }
+        drop(out);
+        assert_eq!(std::fs::read_to_string(&paged).unwrap(), "Long streamed completion\n");
+
+        // a pager quit early doesn't fail the rest of the output
+        let mut out = output(Some("true".to_string())).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        out.write_all(&vec![b'x'; 1 << 20]).unwrap();
+        out.flush().unwrap();
+    }
+}
@@ -0,0 +1,84 @@
+// Winston personas
+// The ask subcommand sends a question with the system prompt of a persona
+// instead of the configured one. A few personas are bundled, and the
+// [personas] table of the config file adds more or replaces bundled ones by
+// name.
+
+use crate::chat::{ChatRequest, Message, Role};
+use crate::config::WinstonConfig;
+use super::Result;
+
+// the bundled personas and their system prompts
+pub const PERSONAS: &[(&str, &str)] = &[
+    (
+        "rust",
+        "You are an experienced Rust developer. Answer with idiomatic, safe Rust, explain ownership and lifetime issues plainly, and prefer the standard library over dependencies.",
+    ),
+    (
+        "sql",
+        "You are a database expert. Write correct, readable SQL, say which dialect a query assumes when it matters, and point out indexes or query plans that affect performance.",
+    ),
+    (
+        "shell",
+        "You are a Unix shell expert. Give POSIX sh commands unless asked otherwise, quote variables correctly, and warn before anything destructive.",
+    ),
+];
+
+// the system prompt of a persona, from the config file or the bundled ones
+pub fn system_prompt<'a>(config: &'a WinstonConfig, name: &str) -> Result<&'a str> {
+    if let Some(prompt) = config.personas.get(name) {
+        return Ok(prompt);
+    }
+    if let Some((_, prompt)) = PERSONAS.iter().find(|(persona, _)| *persona == name) {
+        return Ok(prompt);
+    }
+    let mut names: Vec<&str> = PERSONAS.iter().map(|(persona, _)| *persona).collect();
+    names.extend(config.personas.keys().map(String::as_str));
+    names.sort_unstable();
+    names.dedup();
+    Err(format!("Unknown persona `{}`, expected one of {}", name, names.join(", ")).into())
+}
+
+// a request asking question with the system prompt of the persona
+pub fn request(config: &WinstonConfig, name: &str, question: String) -> Result<ChatRequest> {
+    let messages = vec![
+        Message::new(Role::System, system_prompt(config, name)?),
+        Message::new(Role::User, question),
+    ];
+    Ok(ChatRequest::new(config, messages))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::config::WinstonConfigBuilder;
+
+    // test that the persona's system prompt replaces the configured one, and
+    // that config personas win over bundled ones
+    #[test]
+    fn persona_request_test() {
+        let personas = BTreeMap::from([
+            ("sql".to_string(), "You only write SQLite.".to_string()),
+            ("poet".to_string(), "Answer in verse.".to_string()),
+        ]);
+        let config = WinstonConfigBuilder::new()
+            .system("Be brief.".to_string())
+            .personas(personas)
+            .build()
+            .unwrap();
+
+        let request = request(&config, "rust", "What is a lifetime?".to_string()).unwrap();
+        assert_eq!(request.messages.len(), 2);
+        assert_eq!(request.messages[0], Message::new(Role::System, PERSONAS[0].1));
+        assert_eq!(request.messages[1], Message::new(Role::User, "What is a lifetime?"));
+
+        assert_eq!(system_prompt(&config, "sql").unwrap(), "You only write SQLite.");
+        assert_eq!(system_prompt(&config, "poet").unwrap(), "Answer in verse.");
+        assert_eq!(
+            system_prompt(&config, "chef").unwrap_err().to_string(),
+            "Unknown persona `chef`, expected one of poet, rust, shell, sql"
+        );
+    }
+}
@@ -0,0 +1,113 @@
+// Winston ping subcommand
+// Ping checks that the configured endpoint is reachable by repeatedly hitting
+// the models endpoint, reporting the HTTP status and round-trip latency of
+// each request followed by a min/avg/max summary. When stdout is not a
+// terminal only the latency of each request is printed, one per line, so the
+// output is easy to parse.
+
+use std::io::Write;
+use std::time::Duration;
+
+use crate::client::{Client, RateLimit};
+use super::Result;
+
+#[derive(Debug, PartialEq)]
+pub struct LatencyStats {
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    // summarize a set of latencies, returns None when there are none
+    pub fn from_latencies(latencies: &[Duration]) -> Option<Self> {
+        let min = *latencies.iter().min()?;
+        let max = *latencies.iter().max()?;
+        let avg = latencies.iter().sum::<Duration>() / latencies.len() as u32;
+
+        Some(Self { min, avg, max })
+    }
+}
+
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+// ping the endpoint count times, writing results to out and returning the
+// rate limit reported by the last response
+pub fn run(client: &Client, count: u32, tty: bool, out: &mut impl Write) -> Result<Option<RateLimit>> {
+    let mut latencies = Vec::new();
+    let mut rate_limit = None;
+    for seq in 1..=count {
+        let ping = client.ping()?;
+        if tty {
+            writeln!(out, "seq={} status={} time={:.1} ms", seq, ping.status, millis(ping.elapsed))?;
+        } else {
+            writeln!(out, "{:.1}", millis(ping.elapsed))?;
+        }
+        latencies.push(ping.elapsed);
+        rate_limit = ping.rate_limit;
+    }
+    if let (true, Some(stats)) = (tty, LatencyStats::from_latencies(&latencies)) {
+        writeln!(
+            out,
+            "min/avg/max = {:.1}/{:.1}/{:.1} ms",
+            millis(stats.min),
+            millis(stats.avg),
+            millis(stats.max)
+        )?;
+    }
+
+    Ok(rate_limit)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::WinstonConfigBuilder;
+
+    // test that latencies are summarized correctly
+    #[test]
+    fn latency_stats_test() {
+        let latencies = [
+            Duration::from_millis(10),
+            Duration::from_millis(30),
+            Duration::from_millis(20),
+        ];
+        let stats = LatencyStats::from_latencies(&latencies).unwrap();
+        assert_eq!(stats.min, Duration::from_millis(10));
+        assert_eq!(stats.avg, Duration::from_millis(20));
+        assert_eq!(stats.max, Duration::from_millis(30));
+        assert_eq!(LatencyStats::from_latencies(&[]), None);
+    }
+
+    // test that ping hits the models endpoint with the configured key and
+    // prints one latency per line when not attached to a terminal
+    #[test]
+    fn ping_mock_server_test() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("GET", "/v1/models")
+            .match_header("authorization", "Bearer test-key")
+            .with_status(200)
+            .with_body(r#"{"object":"list","data":[]}"#)
+            .expect(3)
+            .create();
+
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .build()
+            .unwrap();
+        let client = Client::from_config(&config).unwrap();
+        let mut out = Vec::new();
+        run(&client, 3, false, &mut out).unwrap();
+
+        mock.assert();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().count(), 3);
+        for line in out.lines() {
+            line.parse::<f64>().unwrap();
+        }
+    }
+}
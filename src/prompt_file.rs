@@ -0,0 +1,121 @@
+// Winston prompt files
+// A prompt file given with --prompt-file holds the prompt and, optionally, the
+// parameters it was written for in front-matter: a block of `key: value` lines
+// between two `---` lines at the top of the file. Only model, temperature and
+// max_tokens can be set there. They layer over the config file and any preset
+// and below the command line flags.
+
+use std::path::Path;
+
+use encoding_rs::Encoding;
+
+use crate::config::Preset;
+use crate::input;
+use super::Result;
+
+// the front-matter delimiter line
+const DELIMITER: &str = "---";
+
+#[derive(Debug, PartialEq)]
+pub struct PromptFile {
+    pub params: Preset,
+    pub prompt: String,
+}
+
+impl PromptFile {
+    pub fn load(fp: &Path, encoding: &'static Encoding) -> Result<Self> {
+        let source = input::read_file(fp, encoding)?;
+        Self::parse(&source).map_err(|e| format!("Invalid prompt file {}: {}", fp.display(), e).into())
+    }
+
+    // split a prompt file into its front-matter parameters and the prompt, a
+    // file that doesn't start with a delimiter line is all prompt
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut lines = source.lines();
+        if lines.next().map(str::trim_end) != Some(DELIMITER) {
+            return Ok(Self {
+                params: Preset::default(),
+                prompt: source.to_string(),
+            });
+        }
+
+        let mut params = Preset::default();
+        let mut closed = false;
+        for line in lines.by_ref() {
+            let line = line.trim();
+            if line == DELIMITER {
+                closed = true;
+                break;
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once(':')
+                .ok_or_else(|| format!("expected `key: value` in front-matter, found `{}`", line))?;
+            let value = unquote(value.trim());
+            match key.trim() {
+                "model" => params.model = Some(value.to_string()),
+                "temperature" => {
+                    params.temperature = Some(value.parse().map_err(|_| format!("temperature should be a number, found `{}`", value))?)
+                }
+                "max_tokens" => {
+                    params.max_tokens = Some(value.parse().map_err(|_| format!("max_tokens should be an integer, found `{}`", value))?)
+                }
+                key => return Err(format!("unknown front-matter key `{}`", key).into()),
+            }
+        }
+        if !closed {
+            return Err("front-matter is missing its closing `---`".into());
+        }
+
+        Ok(Self {
+            params,
+            prompt: lines.collect::<Vec<_>>().join("\n"),
+        })
+    }
+}
+
+// a value without the quotes around it, if it has any
+fn unquote(value: &str) -> &str {
+    ['"', '\'']
+        .iter()
+        .find_map(|quote| value.strip_prefix(*quote)?.strip_suffix(*quote))
+        .unwrap_or(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cli::Options;
+    use crate::config::resolve_config;
+
+    // sample prompt file
+    const PROMPT_FILE: &str = "---\nmodel: \"gpt-4o\"\ntemperature: 0.2\nmax_tokens: 300\n---\nSummarize the following.\nKeep it short.\n";
+
+    // test that front-matter parameters apply below the command line flags
+    #[test]
+    fn front_matter_test() {
+        let prompt_file = PromptFile::parse(PROMPT_FILE).unwrap();
+        assert_eq!(prompt_file.prompt, "Summarize the following.\nKeep it short.");
+        assert_eq!(prompt_file.params.model.as_deref(), Some("gpt-4o"));
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        std::fs::write(&config_file, "model = \"gpt-3.5-turbo\"\ntemperature = 0.9\n").unwrap();
+        let prompt_file = temp_dir.path().join("summarize.md");
+        std::fs::write(&prompt_file, PROMPT_FILE).unwrap();
+        let args = ["winston", "--no-env", "--no-system-config", "--config", config_file.to_str().unwrap(), "--prompt-file", prompt_file.to_str().unwrap()];
+
+        let config = resolve_config(&Options::try_parse_args(args).unwrap()).unwrap();
+        assert_eq!(config.model, "gpt-4o");
+        assert_eq!(config.temperature, 0.2);
+        assert_eq!(config.max_tokens, Some(300));
+
+        let opts = Options::try_parse_args(args.iter().copied().chain(["--openai-temperature", "0.7"])).unwrap();
+        assert_eq!(resolve_config(&opts).unwrap().temperature, 0.7);
+
+        assert_eq!(PromptFile::parse("just a prompt\n").unwrap().prompt, "just a prompt\n");
+        assert!(PromptFile::parse("---\ntop_k: 3\n---\nhi").is_err());
+    }
+}
@@ -0,0 +1,120 @@
+// Winston prompts from URLs
+// With --prompt-url the prompt is the text of a web page or remote file,
+// fetched with the configured timeout and the proxy settings from the
+// environment, as API requests are. Only text is accepted: the response must
+// have a text, JSON, XML or YAML content type and no NUL bytes, and bodies
+// larger than MAX_PROMPT_URL_BYTES are refused rather than cut. The body is
+// decoded with the charset from the content type, or --input-encoding when it
+// names none. Instructions for what to do with the text go in --system.
+
+use std::io::Read;
+use std::time::Duration;
+
+use encoding_rs::Encoding;
+use reqwest::header::CONTENT_TYPE;
+
+use crate::client::display_url;
+use crate::config::WinstonConfig;
+use crate::input;
+use super::Result;
+
+// the largest body used as a prompt
+pub const MAX_PROMPT_URL_BYTES: u64 = 1024 * 1024;
+
+// content types other than text/* that hold text
+const TEXT_TYPES: &[&str] = &["application/json", "application/xml", "application/javascript", "application/yaml", "application/x-yaml"];
+
+// whether a content type, without its parameters, holds text
+pub fn is_text(mime: &str) -> bool {
+    let mime = mime.trim().to_ascii_lowercase();
+    mime.starts_with("text/") || mime.ends_with("+json") || mime.ends_with("+xml") || TEXT_TYPES.contains(&mime.as_str())
+}
+
+// fetch the text at url to send as the prompt
+pub fn fetch(config: &WinstonConfig, url: &str, encoding: &'static Encoding) -> Result<String> {
+    let shown = display_url(url);
+    let mut http = reqwest::blocking::Client::builder();
+    if let Some(timeout) = config.timeout {
+        http = http.timeout(Duration::from_secs(timeout));
+    }
+    let response = http
+        .build()?
+        .get(url)
+        .send()
+        .map_err(|e| format!("Could not fetch {}: {}", shown, e.without_url()))?;
+    if !response.status().is_success() {
+        return Err(format!("Could not fetch {}: {}", shown, response.status()).into());
+    }
+
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let mut params = content_type.split(';');
+    let mime = params.next().unwrap_or_default();
+    if !is_text(mime) {
+        return Err(format!("{} is not text, its content type is `{}`", shown, mime.trim()).into());
+    }
+    let charset = params
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .map(|(_, value)| value.trim().trim_matches('"'));
+    let encoding = match charset {
+        Some(charset) => input::parse_encoding(charset)?,
+        None => encoding,
+    };
+
+    if response.content_length().is_some_and(|length| length > MAX_PROMPT_URL_BYTES) {
+        return Err(format!("{} is larger than {} bytes", shown, MAX_PROMPT_URL_BYTES).into());
+    }
+    let mut body = Vec::new();
+    response.take(MAX_PROMPT_URL_BYTES + 1).read_to_end(&mut body)?;
+    if body.len() as u64 > MAX_PROMPT_URL_BYTES {
+        return Err(format!("{} is larger than {} bytes", shown, MAX_PROMPT_URL_BYTES).into());
+    }
+    if body.contains(&0) {
+        return Err(format!("{} is not text, it holds NUL bytes", shown).into());
+    }
+    input::decode(&body, encoding, &shown)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::WinstonConfigBuilder;
+
+    // test that text is fetched and decoded with its charset, and that binary
+    // and oversized bodies are refused
+    #[test]
+    fn prompt_url_test() {
+        let mut server = mockito::Server::new();
+        server
+            .mock("GET", "/page.txt")
+            .with_header("content-type", "text/plain; charset=iso-8859-1")
+            .with_body(b"caf\xe9 notes")
+            .create();
+        server.mock("GET", "/data.json").with_header("content-type", "application/json").with_body("{}").create();
+        server.mock("GET", "/image.png").with_header("content-type", "image/png").with_body(b"\x89PNG").create();
+        server.mock("GET", "/nul.txt").with_header("content-type", "text/plain").with_body(b"a\0b").create();
+        server
+            .mock("GET", "/big.txt")
+            .with_header("content-type", "text/plain")
+            .with_body("x".repeat(MAX_PROMPT_URL_BYTES as usize + 1))
+            .create();
+        server.mock("GET", "/missing").with_status(404).create();
+
+        let config = WinstonConfigBuilder::new().build().unwrap();
+        let fetch = |path: &str| fetch(&config, &format!("{}{}", server.url(), path), encoding_rs::UTF_8);
+        assert_eq!(fetch("/page.txt").unwrap(), "café notes");
+        assert_eq!(fetch("/data.json").unwrap(), "{}");
+        assert_eq!(
+            fetch("/image.png").unwrap_err().to_string(),
+            format!("{}/image.png is not text, its content type is `image/png`", server.url())
+        );
+        assert!(fetch("/nul.txt").unwrap_err().to_string().contains("NUL bytes"));
+        assert!(fetch("/big.txt").unwrap_err().to_string().contains("is larger than"));
+        assert!(fetch("/missing").unwrap_err().to_string().contains("404"));
+    }
+}
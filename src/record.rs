@@ -0,0 +1,92 @@
+// Winston request recording
+// With --record <dir> every request sent for the prompt is written to dir
+// together with the response, as <key>.request.json and <key>.response.json
+// where the key is the hash of the request body. The request file is a single
+// line replay record, so it can be sent again with --replay, and names the
+// endpoint and the masked API key it was sent with. Identical requests share a
+// key and the latest pair is kept.
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::batch::request_key;
+use crate::chat::{ChatRequest, ChatResponse};
+use crate::config::mask_key;
+use super::Result;
+
+#[derive(Debug, Serialize)]
+struct RequestRecord<'a> {
+    endpoint: &'a str,
+    api_key: String,
+    request: &'a ChatRequest,
+}
+
+// write a request and its response to dir, returning the request file
+pub fn write(dir: &Path, endpoint: &str, api_key: &str, request: &ChatRequest, response: &ChatResponse) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Could not create record directory {}: {}", dir.display(), e))?;
+    let key = request_key(request)?;
+    let record = RequestRecord {
+        endpoint,
+        api_key: mask_key(api_key),
+        request,
+    };
+    let request_file = dir.join(format!("{}.request.json", key));
+    std::fs::write(&request_file, serde_json::to_string(&record)? + "\n")
+        .map_err(|e| format!("Could not write record {}: {}", request_file.display(), e))?;
+    let response_file = dir.join(format!("{}.response.json", key));
+    std::fs::write(&response_file, serde_json::to_string(response)? + "\n")
+        .map_err(|e| format!("Could not write record {}: {}", response_file.display(), e))?;
+    Ok(request_file)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chat::{Message, Role};
+    use crate::client::Client;
+    use crate::config::WinstonConfigBuilder;
+
+    // test that a recorded pair holds the request with a masked key and the
+    // response, and that the request replays as sent
+    #[test]
+    fn record_pair_test() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_body(r#"{"id":"chatcmpl-1","choices":[{"message":{"role":"assistant","content":"Rayleigh scattering."}}],"usage":{"prompt_tokens":9,"completion_tokens":3,"total_tokens":12}}"#)
+            .create();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("sk-abcdefghijklmnop".to_string())
+            .model("gpt-4o".to_string())
+            .build()
+            .unwrap();
+        let client = Client::from_config(&config).unwrap().record(temp_dir.path());
+        let request = ChatRequest::new(&config, vec![Message::new(Role::User, "Why is the sky blue?")]);
+        client.chat(&request).unwrap();
+        mock.assert();
+
+        let key = request_key(&request).unwrap();
+        let recorded = std::fs::read_to_string(temp_dir.path().join(format!("{}.request.json", key))).unwrap();
+        assert_eq!(recorded.lines().count(), 1);
+        assert!(!recorded.contains("sk-abcdefghijklmnop"));
+        let record: serde_json::Value = serde_json::from_str(&recorded).unwrap();
+        assert_eq!(record["endpoint"], server.url());
+        assert_eq!(record["api_key"], "sk-...mnop");
+        assert_eq!(record["request"]["messages"], serde_json::json!([{"role": "user", "content": "Why is the sky blue?"}]));
+        let replayed = crate::replay::parse(&recorded, &config).unwrap();
+        assert_eq!(serde_json::to_value(&replayed).unwrap(), serde_json::to_value(&request).unwrap());
+
+        let response = std::fs::read_to_string(temp_dir.path().join(format!("{}.response.json", key))).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&response).unwrap(),
+            serde_json::json!({
+                "choices": [{"message": {"role": "assistant", "content": "Rayleigh scattering."}}],
+                "usage": {"prompt_tokens": 9, "completion_tokens": 3, "total_tokens": 12},
+            })
+        );
+    }
+}
@@ -0,0 +1,806 @@
+// Winston interactive session
+// The repl subcommand reads prompts line by line and sends each together with
+// the conversation so far, so the model sees the whole exchange. Lines
+// starting with a slash are commands rather than prompts: /retry sends the
+// last prompt again in place of its reply, and /edit opens it in $VISUAL or
+// $EDITOR first. /def <name> <text> defines a macro, and /<name> anywhere in
+// a later prompt is replaced by its text. Macros saved with --save-macros are
+// there again in the next session. Token usage reported by each response is
+// added up over the session, which can be capped with a budget after which no
+// more prompts are accepted. /usage prints the tokens used so far and what
+// they cost, priced by the model of each reply, and --usage-footer prints the
+// same after every reply. Named sessions are saved after every reply.
+// With --summarize-history, once the conversation grows past a number of
+// tokens the older turns are condensed into a summary by a separate request,
+// to the summary_model when one is configured, and replaced by it. The latest
+// exchange and a leading system message are always kept as they are.
+// /model <name> switches the model for the prompts that follow and /params
+// sets their temperature and max_tokens, as in /params temperature=0.2
+// max_tokens=500, keeping the conversation so far.
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::chat::{api_for_model, output_text, ChatRequest, Message, Role, Usage};
+use crate::client::Client;
+use crate::config::WinstonConfig;
+use crate::cost::{cost, estimate_tokens, strict_estimate, TOKENS_PER_MESSAGE};
+use super::Result;
+
+// the conversation and token usage of a session
+#[derive(Debug, Default)]
+pub struct Session {
+    pub messages: Vec<Message>,
+    pub tokens: u32,
+    // the usage of every response so far, and its cost in dollars for those
+    // whose model has a known price
+    pub usage: Usage,
+    pub cost: f64,
+    // whether a response came from a model without a known price
+    pub unpriced: bool,
+    // print the session usage after every reply
+    pub footer: bool,
+    // stop accepting prompts once more than this many tokens were used
+    pub budget: Option<u32>,
+    // print replies without surrounding whitespace
+    pub trim: bool,
+    // where the conversation is saved after each reply
+    pub file: Option<PathBuf>,
+    // condense older turns once the conversation is estimated at more tokens
+    pub summarize_above: Option<u32>,
+    // refuse to summarize on estimates for a model with an unknown context window
+    pub strict_tokens: bool,
+    // check each prompt with the moderations endpoint before sending it
+    pub moderate: bool,
+    // the text each /name in a prompt stands for
+    pub macros: BTreeMap<String, String>,
+    // where macros are saved when they are defined
+    pub macros_file: Option<PathBuf>,
+    // the model and parameters set with /model and /params, used in place of
+    // the configured ones
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+}
+
+// commands that macros can't be named after
+const COMMANDS: &[&str] = &["def", "exit", "quit", "retry", "edit", "model", "params", "usage"];
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+// messages at the end of the conversation that are never summarized
+const KEEP_MESSAGES: usize = 2;
+
+// the start of the message that stands in for the summarized turns
+const SUMMARY_PREFIX: &str = "Summary of the earlier conversation:";
+
+// instructions for the summary request
+pub const SUMMARY_PROMPT: &str = "Summarize the conversation below in a few sentences. Keep names, facts, decisions and open questions, and leave out pleasantries.";
+
+impl Session {
+    pub fn new(budget: Option<u32>, trim: bool) -> Self {
+        Self {
+            budget,
+            trim,
+            ..Self::default()
+        }
+    }
+
+    // add the usage of a response from model to the session total
+    pub fn record(&mut self, usage: Option<Usage>, model: &str) {
+        if let Some(usage) = usage {
+            self.tokens += usage.total_tokens;
+            self.usage = self.usage + usage;
+            match cost(model, usage.prompt_tokens, usage.completion_tokens) {
+                Some(cost) => self.cost += cost,
+                None => self.unpriced = true,
+            }
+        }
+    }
+
+    // the usage and cost of the session so far, as printed by /usage
+    pub fn usage_summary(&self) -> String {
+        let cost = match self.unpriced {
+            true => format!("${:.4} for the models with a known price", self.cost),
+            false => format!("${:.4}", self.cost),
+        };
+        format!("session {}, {}", self.usage, cost)
+    }
+
+    // whether the session has used more tokens than its budget
+    pub fn over_budget(&self) -> bool {
+        self.budget.is_some_and(|budget| self.tokens > budget)
+    }
+
+    // the prompt of the last exchange, when the conversation ends with a reply
+    // to a prompt
+    pub fn last_prompt(&self) -> Option<&str> {
+        match self.messages.as_slice() {
+            [.., prompt, reply] if prompt.role == Role::User && reply.role == Role::Assistant => Some(&prompt.content),
+            _ => None,
+        }
+    }
+
+    // take back the last exchange, returning its prompt to send again
+    pub fn rewind(&mut self) -> Option<String> {
+        self.last_prompt()?;
+        self.messages.pop();
+        self.messages.pop().map(|prompt| prompt.content)
+    }
+
+    // define a macro from the rest of a /def line, the name followed by its
+    // text, returning the name
+    pub fn define(&mut self, definition: &str) -> Result<String> {
+        let (name, text) = definition.trim().split_once(char::is_whitespace).unwrap_or((definition.trim(), ""));
+        let name = name.strip_prefix('/').unwrap_or(name);
+        if name.is_empty() || !name.chars().all(is_name_char) {
+            return Err("Usage: /def <name> <text>, the name made of letters, digits, - and _".into());
+        }
+        if COMMANDS.contains(&name) {
+            return Err(format!("/{} is a command and can't be redefined", name).into());
+        }
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(format!("No text given for /{}", name).into());
+        }
+        self.macros.insert(name.to_string(), text.to_string());
+        if let Some(file) = &self.macros_file {
+            save_macros(file, &self.macros)?;
+        }
+        Ok(name.to_string())
+    }
+
+    // whether a line starts with a defined macro rather than a command
+    pub fn is_macro(&self, line: &str) -> bool {
+        line.strip_prefix('/')
+            .map(|rest| rest.split(|c| !is_name_char(c)).next().unwrap_or_default())
+            .is_some_and(|name| self.macros.contains_key(name))
+    }
+
+    // a prompt with every /name of a defined macro replaced by its text. Only
+    // whole words count, so paths like src/main.rs or /usr/bin stay as they are.
+    pub fn expand(&self, line: &str) -> String {
+        let mut expanded = String::new();
+        let mut copied = 0;
+        for (start, _) in line.match_indices('/') {
+            if start < copied {
+                continue;
+            }
+            let before = line[..start].chars().next_back();
+            let after = &line[start + 1..];
+            let end = after.find(|c| !is_name_char(c)).unwrap_or(after.len());
+            let next = after[end..].chars().next();
+            let word = before.is_none_or(char::is_whitespace) && next != Some('/');
+            if let Some(text) = self.macros.get(&after[..end]).filter(|_| word) {
+                expanded.push_str(&line[copied..start]);
+                expanded.push_str(text);
+                copied = start + 1 + end;
+            }
+        }
+        expanded.push_str(&line[copied..]);
+        expanded
+    }
+
+    // switch to the model named by the rest of a /model line, returning it
+    pub fn switch_model(&mut self, name: &str) -> Result<String> {
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err("Usage: /model <name>".into());
+        }
+        self.model = Some(name.to_string());
+        Ok(name.to_string())
+    }
+
+    // set the parameters given on the rest of a /params line as name=value
+    // pairs, leaving them all as they were when one is invalid
+    pub fn set_params(&mut self, params: &str) -> Result<()> {
+        let (mut temperature, mut max_tokens) = (self.temperature, self.max_tokens);
+        for param in params.split_whitespace() {
+            let (name, value) = param.split_once('=').ok_or("Usage: /params temperature=<number> max_tokens=<tokens>")?;
+            match name {
+                "temperature" => {
+                    let value: f32 = value.parse().map_err(|_| format!("Invalid temperature `{}`", value))?;
+                    if !(0.0..=2.0).contains(&value) {
+                        return Err(format!("Temperature {} is out of range, expected 0 to 2", value).into());
+                    }
+                    temperature = Some(value);
+                }
+                "max_tokens" => {
+                    max_tokens = Some(value.parse().map_err(|_| format!("Invalid max_tokens `{}`", value))?);
+                }
+                _ => return Err(format!("Unknown parameter `{}`, expected temperature or max_tokens", name).into()),
+            }
+        }
+        self.temperature = temperature;
+        self.max_tokens = max_tokens;
+        Ok(())
+    }
+
+    // the active model and parameters, as printed after /model and /params
+    pub fn describe(&self, config: &WinstonConfig) -> String {
+        let max_tokens = match self.max_tokens.or(config.max_tokens) {
+            Some(max_tokens) => max_tokens.to_string(),
+            None => "unset".to_string(),
+        };
+        format!(
+            "Using {}, temperature {}, max_tokens {}",
+            self.model.as_deref().unwrap_or(&config.model),
+            self.temperature.unwrap_or(config.temperature),
+            max_tokens
+        )
+    }
+
+    // a request for the conversation with the model and parameters set in
+    // the session
+    pub fn request(&self, config: &WinstonConfig) -> ChatRequest {
+        let mut request = ChatRequest::new(config, self.messages.clone());
+        if let Some(model) = &self.model {
+            request.api = config.api.unwrap_or_else(|| api_for_model(model));
+            request.model = model.clone();
+        }
+        if let Some(temperature) = self.temperature {
+            request.temperature = temperature;
+        }
+        if self.max_tokens.is_some() {
+            request.max_tokens = self.max_tokens;
+        }
+        request
+    }
+
+    // whether the conversation has grown past the summary threshold
+    pub fn needs_summary(&self) -> bool {
+        let tokens: u32 = self
+            .messages
+            .iter()
+            .map(|message| estimate_tokens(&message.content) + TOKENS_PER_MESSAGE)
+            .sum();
+        self.summarize_above.is_some_and(|threshold| tokens > threshold)
+    }
+
+    // replace the older turns with a summary of them from the summary model
+    pub fn summarize(&mut self, client: &Client, config: &WinstonConfig) -> Result<()> {
+        if self.strict_tokens {
+            strict_estimate(self.model.as_deref().unwrap_or(&config.model), "the conversation size")?;
+        }
+        // an earlier summary is folded into the new one
+        let start = match self.messages.first() {
+            Some(message) if message.role == Role::System && !message.content.starts_with(SUMMARY_PREFIX) => 1,
+            _ => 0,
+        };
+        let end = self.messages.len().saturating_sub(KEEP_MESSAGES);
+        if end <= start {
+            return Ok(());
+        }
+        let transcript: Vec<String> = self.messages[start..end]
+            .iter()
+            .map(|message| format!("{}: {}", message.role, message.content))
+            .collect();
+        let mut request = ChatRequest::new(config, vec![
+            Message::new(Role::System, SUMMARY_PROMPT),
+            Message::new(Role::User, transcript.join("\n")),
+        ]);
+        if let Some(model) = &config.summary_model {
+            request.model = model.clone();
+        }
+        let response = client.chat(&request)?;
+        self.record(response.usage, &request.model);
+        let summary = response.content().unwrap_or_default().trim();
+        if summary.is_empty() {
+            return Err("The summary request returned no content".into());
+        }
+        let summary = Message::new(Role::System, format!("{} {}", SUMMARY_PREFIX, summary));
+        self.messages.splice(start..end, [summary]);
+        Ok(())
+    }
+}
+
+// read prompts from input until it ends, /exit is given or the budget runs out
+pub fn run(
+    client: &Client,
+    config: &WinstonConfig,
+    session: &mut Session,
+    mut input: impl BufRead,
+    out: &mut impl Write,
+) -> Result<()> {
+    loop {
+        write!(out, "> ")?;
+        out.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            writeln!(out)?;
+            break;
+        }
+        let line = line.trim();
+        // the exchange taken back by /retry or /edit, put back if sending fails
+        let mut rewound = Vec::new();
+        let prompt = match line {
+            "" => continue,
+            "/exit" | "/quit" => break,
+            "/usage" => {
+                writeln!(out, "{}", session.usage_summary())?;
+                continue;
+            }
+            _ if line == "/def" || line.starts_with("/def ") => {
+                match session.define(&line["/def".len()..]) {
+                    Ok(name) => writeln!(out, "Defined /{}", name)?,
+                    Err(e) => writeln!(out, "{}", e)?,
+                }
+                continue;
+            }
+            _ if line == "/model" || line.starts_with("/model ") => {
+                match session.switch_model(&line["/model".len()..]) {
+                    Ok(_) => writeln!(out, "{}", session.describe(config))?,
+                    Err(e) => writeln!(out, "{}", e)?,
+                }
+                continue;
+            }
+            _ if line == "/params" || line.starts_with("/params ") => {
+                match session.set_params(&line["/params".len()..]) {
+                    Ok(()) => writeln!(out, "{}", session.describe(config))?,
+                    Err(e) => writeln!(out, "{}", e)?,
+                }
+                continue;
+            }
+            "/retry" | "/edit" => {
+                let Some(prompt) = session.last_prompt() else {
+                    writeln!(out, "No prompt to retry")?;
+                    continue;
+                };
+                // the exchange is kept when the edit fails or leaves nothing
+                let prompt = match line {
+                    "/edit" => match edit(prompt) {
+                        Ok(prompt) if !prompt.trim().is_empty() => prompt.trim().to_string(),
+                        Ok(_) => {
+                            writeln!(out, "The edited prompt is empty, nothing was sent")?;
+                            continue;
+                        }
+                        Err(e) => {
+                            writeln!(out, "{}", e)?;
+                            continue;
+                        }
+                    },
+                    _ => prompt.to_string(),
+                };
+                rewound = session.messages[session.messages.len() - 2..].to_vec();
+                session.rewind();
+                prompt
+            }
+            _ if line.starts_with('/') && !session.is_macro(line) => {
+                writeln!(out, "Unknown command `{}`", line)?;
+                continue;
+            }
+            _ => session.expand(line),
+        };
+
+        // a flagged prompt isn't sent, the conversation stays as it was
+        if session.moderate {
+            if let Err(e) = crate::moderate::check(client, &[Message::new(Role::User, prompt.as_str())]) {
+                session.messages.extend(rewound);
+                writeln!(out, "{}", e)?;
+                continue;
+            }
+        }
+        session.messages.push(Message::new(Role::User, prompt));
+        let request = session.request(config);
+        let response = match client.chat(&request) {
+            Ok(response) => response,
+            Err(e) => {
+                // leave the failed prompt out of the conversation
+                session.messages.pop();
+                session.messages.extend(rewound);
+                writeln!(out, "{}", e)?;
+                continue;
+            }
+        };
+        let content = response.content().unwrap_or_default();
+        writeln!(out, "{}", output_text(content, session.trim))?;
+        session.messages.push(Message::new(Role::Assistant, content));
+        session.record(response.usage, &request.model);
+        if session.footer {
+            writeln!(out, "{}", session.usage_summary())?;
+        }
+        // a failed summary leaves the history as it is for the next attempt
+        if session.needs_summary() {
+            if let Err(e) = session.summarize(client, config) {
+                writeln!(out, "Could not summarize the conversation: {}", e)?;
+            }
+        }
+        if let Some(file) = &session.file {
+            crate::session::save(file, &session.messages)?;
+        }
+
+        if session.over_budget() {
+            writeln!(
+                out,
+                "Token budget of {} exceeded, {} tokens used",
+                session.budget.unwrap_or_default(),
+                session.tokens
+            )?;
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+// the file macros are saved in, XDG_DATA_HOME/winston/macros.json
+pub fn macros_file() -> Result<PathBuf> {
+    let mut fp = dirs::data_dir().ok_or("Could not find data directory")?;
+    fp.push("winston");
+    fp.push("macros.json");
+    Ok(fp)
+}
+
+// the saved macros, none when nothing was saved yet
+pub fn load_macros(fp: &Path) -> Result<BTreeMap<String, String>> {
+    if !fp.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let macros = std::fs::read_to_string(fp).map_err(|e| format!("Could not read macros {}: {}", fp.display(), e))?;
+    Ok(serde_json::from_str(&macros).map_err(|e| format!("Invalid macros {}: {}", fp.display(), e))?)
+}
+
+pub fn save_macros(fp: &Path, macros: &BTreeMap<String, String>) -> Result<()> {
+    if let Some(dir) = fp.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Could not create directory {}: {}", dir.display(), e))?;
+    }
+    std::fs::write(fp, serde_json::to_string_pretty(macros)?)
+        .map_err(|e| format!("Could not write macros {}: {}", fp.display(), e))?;
+    Ok(())
+}
+
+// open text in $VISUAL or $EDITOR, vi when neither is set, and return it as
+// saved
+fn edit(text: &str) -> Result<String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let file = tempfile::Builder::new().prefix("winston-prompt-").suffix(".txt").tempfile()?;
+    std::fs::write(file.path(), text)?;
+    // the editor may be given with arguments, like "code --wait"
+    let mut words = editor.split_whitespace();
+    let program = words.next().ok_or("No editor set in $VISUAL or $EDITOR")?;
+    let status = std::process::Command::new(program)
+        .args(words)
+        .arg(file.path())
+        .status()
+        .map_err(|e| format!("Could not start editor `{}`: {}", editor, e))?;
+    if !status.success() {
+        return Err(format!("Editor `{}` exited with {}", editor, status).into());
+    }
+    Ok(std::fs::read_to_string(file.path())?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::WinstonConfigBuilder;
+
+    // test that the session stops taking prompts once usage crosses the budget
+    #[test]
+    fn budget_exceeded_test() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_body(
+                r#"{"choices":[{"message":{"role":"assistant","content":"ok"}}],"usage":{"prompt_tokens":30,"completion_tokens":10,"total_tokens":40}}"#,
+            )
+            .expect(3)
+            .create();
+
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .model("gpt-4o".to_string())
+            .build()
+            .unwrap();
+        let client = Client::from_config(&config).unwrap();
+        let mut session = Session::new(Some(100), true);
+        let mut out = Vec::new();
+        run(&client, &config, &mut session, "one\ntwo\nthree\nfour\n".as_bytes(), &mut out).unwrap();
+
+        mock.assert();
+        assert_eq!(session.tokens, 120);
+        assert!(session.over_budget());
+        assert_eq!(session.messages.len(), 6);
+        assert!(String::from_utf8(out).unwrap().ends_with("Token budget of 100 exceeded, 120 tokens used\n"));
+    }
+
+    // test that blank lines are skipped without sending a request
+    #[test]
+    fn empty_line_test() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"role":"assistant","content":"ok"}}]}"#)
+            .expect(1)
+            .create();
+
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .model("gpt-4o".to_string())
+            .build()
+            .unwrap();
+        let client = Client::from_config(&config).unwrap();
+        let mut session = Session::new(None, true);
+        let mut out = Vec::new();
+        run(&client, &config, &mut session, "\n   \nhello\n\t\n".as_bytes(), &mut out).unwrap();
+
+        mock.assert();
+        assert_eq!(session.messages.len(), 2);
+        assert_eq!(String::from_utf8(out).unwrap(), "> > > ok\n> > \n");
+    }
+
+    // test that older turns are replaced by a summary from the summary model
+    // once the history crosses the threshold
+    #[test]
+    fn summarize_history_test() {
+        let mut server = mockito::Server::new();
+        let chat = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJsonString(r#"{"model":"gpt-4o"}"#.to_string()))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"role":"assistant","content":"a reply long enough to count"}}]}"#)
+            .expect(2)
+            .create();
+        let summarizer = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "model": "gpt-4o-mini",
+                "messages": [
+                    {"role": "system", "content": SUMMARY_PROMPT},
+                    {"role": "user", "content": "user: first\nassistant: a reply long enough to count"},
+                ],
+            })))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"role":"assistant","content":"The user said first."}}]}"#)
+            .expect(1)
+            .create();
+
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .model("gpt-4o".to_string())
+            .summary_model("gpt-4o-mini".to_string())
+            .build()
+            .unwrap();
+        let client = Client::from_config(&config).unwrap();
+        let mut session = Session::new(None, true);
+        session.summarize_above = Some(30);
+        let mut out = Vec::new();
+        run(&client, &config, &mut session, "first\nsecond\n".as_bytes(), &mut out).unwrap();
+
+        chat.assert();
+        summarizer.assert();
+        assert_eq!(
+            session.messages,
+            [
+                Message::new(Role::System, "Summary of the earlier conversation: The user said first."),
+                Message::new(Role::User, "second"),
+                Message::new(Role::Assistant, "a reply long enough to count"),
+            ]
+        );
+
+        // with --strict-tokens a model of unknown size isn't summarized
+        session.strict_tokens = true;
+        session.model = Some("llama-3-70b".to_string());
+        assert!(session.summarize(&client, &config).is_err());
+        assert_eq!(session.messages.len(), 3);
+    }
+
+    // test that /retry replaces the last reply with a new one to the same prompt
+    #[test]
+    fn retry_test() {
+        let mut session = Session::new(None, true);
+        assert_eq!(session.rewind(), None);
+        session.messages = vec![
+            Message::new(Role::User, "one"),
+            Message::new(Role::Assistant, "1"),
+            Message::new(Role::User, "two"),
+            Message::new(Role::Assistant, "2"),
+        ];
+        assert_eq!(session.rewind().as_deref(), Some("two"));
+        assert_eq!(session.messages.len(), 2);
+
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({"messages": [
+                {"role": "user", "content": "one"},
+                {"role": "assistant", "content": "1"},
+                {"role": "user", "content": "two"},
+            ]})))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"role":"assistant","content":"two!"}}]}"#)
+            .expect(2)
+            .create();
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .model("gpt-4o".to_string())
+            .build()
+            .unwrap();
+        let client = Client::from_config(&config).unwrap();
+        run(&client, &config, &mut session, "two\n/retry\n".as_bytes(), &mut Vec::new()).unwrap();
+
+        mock.assert();
+        assert_eq!(
+            session.messages,
+            [
+                Message::new(Role::User, "one"),
+                Message::new(Role::Assistant, "1"),
+                Message::new(Role::User, "two"),
+                Message::new(Role::Assistant, "two!"),
+            ]
+        );
+
+        // a retry that fails keeps the exchange it would have replaced
+        mock.remove();
+        let failing = server.mock("POST", "/v1/chat/completions").with_status(400).create();
+        let kept = session.messages.clone();
+        let mut out = Vec::new();
+        run(&client, &config, &mut session, "/retry\n".as_bytes(), &mut out).unwrap();
+        failing.assert();
+        assert!(String::from_utf8(out).unwrap().contains("400"));
+        assert_eq!(session.messages, kept);
+    }
+
+    // test that defined macros are expanded as whole words in later prompts
+    // and saved when a macros file is set
+    #[test]
+    fn macro_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let fp = temp_dir.path().join("macros.json");
+        let mut session = Session::new(None, true);
+        session.macros_file = Some(fp.clone());
+        assert_eq!(session.define(" short Answer in one sentence.").unwrap(), "short");
+        assert_eq!(session.define("/rs  Use idiomatic Rust.").unwrap(), "rs");
+        assert!(session.define("retry again").is_err());
+        assert!(session.define("bad!name text").is_err());
+        assert!(session.define("empty").is_err());
+
+        assert_eq!(session.expand("/short what is a trait?"), "Answer in one sentence. what is a trait?");
+        assert_eq!(session.expand("fix src/short.rs, /rs /short"), "fix src/short.rs, Use idiomatic Rust. Answer in one sentence.");
+        assert_eq!(session.expand("see /short/x and /unknown"), "see /short/x and /unknown");
+        assert!(session.is_macro("/rs please"));
+        assert!(!session.is_macro("/rsx"));
+        assert_eq!(load_macros(&fp).unwrap(), session.macros);
+
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({"messages": [
+                {"role": "user", "content": "Be terse. hello"},
+            ]})))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"role":"assistant","content":"hi"}}]}"#)
+            .expect(1)
+            .create();
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .model("gpt-4o".to_string())
+            .build()
+            .unwrap();
+        let client = Client::from_config(&config).unwrap();
+        let mut session = Session::new(None, true);
+        let mut out = Vec::new();
+        run(&client, &config, &mut session, "/def terse Be terse.
+/terse hello
+".as_bytes(), &mut out).unwrap();
+
+        mock.assert();
+        assert!(String::from_utf8(out).unwrap().starts_with("> Defined /terse
+"));
+    }
+
+    // test that /model and /params change the model and parameters of later
+    // requests and keep the conversation
+    #[test]
+    fn switch_model_test() {
+        let mut session = Session::new(None, true);
+        assert!(session.switch_model("  ").is_err());
+        assert!(session.set_params("temperature=hot").is_err());
+        assert!(session.set_params("temperature=0.5 top_k=3").is_err());
+        assert_eq!(session.temperature, None);
+        assert!(session.set_params("temperature=3").is_err());
+
+        let mut server = mockito::Server::new();
+        let cheap = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({"model": "gpt-4o-mini", "temperature": 0.7})))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"role":"assistant","content":"draft"}}]}"#)
+            .expect(1)
+            .create();
+        let strong = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "model": "gpt-4o",
+                "temperature": 0.2,
+                "max_tokens": 500,
+                "messages": [
+                    {"role": "user", "content": "one"},
+                    {"role": "assistant", "content": "draft"},
+                    {"role": "user", "content": "two"},
+                ],
+            })))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"role":"assistant","content":"better"}}]}"#)
+            .expect(1)
+            .create();
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .model("gpt-4o-mini".to_string())
+            .temperature(0.7)
+            .build()
+            .unwrap();
+        let client = Client::from_config(&config).unwrap();
+        let mut out = Vec::new();
+        let input = "one\n/model gpt-4o\n/params temperature=0.2 max_tokens=500\ntwo\n";
+        run(&client, &config, &mut session, input.as_bytes(), &mut out).unwrap();
+
+        cheap.assert();
+        strong.assert();
+        assert_eq!(session.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(session.messages.len(), 4);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Using gpt-4o, temperature 0.7, max_tokens unset\n"), "{}", out);
+        assert!(out.contains("Using gpt-4o, temperature 0.2, max_tokens 500\n"), "{}", out);
+    }
+
+    // test that usage adds up over the turns, priced by the model of each,
+    // and is printed by /usage and after every reply with the footer
+    #[test]
+    fn session_usage_test() {
+        let mut session = Session::new(None, true);
+        let usage = |prompt_tokens, completion_tokens| {
+            Some(Usage { prompt_tokens, completion_tokens, total_tokens: prompt_tokens + completion_tokens })
+        };
+        session.record(usage(1000, 500), "gpt-4o-mini");
+        session.record(None, "gpt-4o-mini");
+        session.record(usage(2000, 1000), "gpt-4o");
+        assert_eq!(session.tokens, 4500);
+        assert_eq!(session.usage, Usage { prompt_tokens: 3000, completion_tokens: 1500, total_tokens: 4500 });
+        assert_eq!(
+            session.usage_summary(),
+            "session usage: 3000 prompt + 1500 completion = 4500 tokens, $0.0155"
+        );
+        session.record(usage(10, 10), "llama-3");
+        assert!(session.unpriced);
+        assert!(session.usage_summary().ends_with("$0.0155 for the models with a known price"));
+
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_body(
+                r#"{"choices":[{"message":{"role":"assistant","content":"ok"}}],"usage":{"prompt_tokens":400,"completion_tokens":100,"total_tokens":500}}"#,
+            )
+            .expect(2)
+            .create();
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .model("gpt-4o".to_string())
+            .build()
+            .unwrap();
+        let client = Client::from_config(&config).unwrap();
+        let mut session = Session::new(None, true);
+        session.footer = true;
+        let mut out = Vec::new();
+        run(&client, &config, &mut session, "one\ntwo\n/usage\n".as_bytes(), &mut out).unwrap();
+
+        mock.assert();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("ok\nsession usage: 400 prompt + 100 completion = 500 tokens, $0.0020\n"), "{}", out);
+        assert_eq!(out.matches("session usage: 800 prompt + 200 completion = 1000 tokens, $0.0040\n").count(), 2, "{}", out);
+    }
+}
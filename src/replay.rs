@@ -0,0 +1,72 @@
+// Winston request replay
+// --replay sends a logged request again so a reported problem can be
+// reproduced. A record is a JSON object holding the request body under
+// "request", or the request body itself, given either inline or as a JSON
+// lines file of which the last record is replayed. Logged bodies carry no
+// credentials, so the replayed request uses the key and endpoint of the local
+// configuration. Whether to stream is up to --stream rather than the record.
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::chat::{api_for_model, ChatRequest};
+use crate::config::WinstonConfig;
+use super::Result;
+
+// the record to replay, the argument itself when it is a JSON object and the
+// last line of the file it names otherwise
+pub fn read_record(source: &str) -> Result<String> {
+    if source.trim_start().starts_with('{') {
+        return Ok(source.to_string());
+    }
+    let records = std::fs::read_to_string(source)
+        .map_err(|e| format!("Could not read replay file {}: {}", source, e))?;
+    records
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .map(str::to_string)
+        .ok_or_else(|| format!("No records in replay file {}", source).into())
+}
+
+// rebuild the request of a record
+pub fn parse(record: &str, config: &WinstonConfig) -> Result<ChatRequest> {
+    let record: Value = serde_json::from_str(record).map_err(|e| format!("Invalid replay record: {}", e))?;
+    let body = record.get("request").unwrap_or(&record);
+    let mut request = ChatRequest::deserialize(body).map_err(|e| format!("Invalid replay record: {}", e))?;
+    request.api = config.api.unwrap_or_else(|| api_for_model(&request.model));
+    request.stream = false;
+    request.stream_options = None;
+    Ok(request)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::WinstonConfigBuilder;
+
+    const RECORD: &str = r#"{"time":"2026-10-01T12:00:00Z","status":200,"request":{"model":"gpt-4o","messages":[{"role":"system","content":"Be brief."},{"role":"user","content":"Why is the sky blue?"}],"max_tokens":50,"temperature":0.5,"top_p":1.0,"stop":"\n","stream":true,"stream_options":{"include_usage":true}}}"#;
+
+    // test that a replayed record produces the logged request body
+    #[test]
+    fn replay_record_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log = temp_dir.path().join("audit.jsonl");
+        std::fs::write(&log, format!("{{\"request\":{{\"model\":\"davinci\"}}}}\n{}\n\n", RECORD)).unwrap();
+        let config = WinstonConfigBuilder::new().model("gpt-3.5-turbo".to_string()).build().unwrap();
+
+        let record = read_record(log.to_str().unwrap()).unwrap();
+        assert_eq!(record, RECORD);
+        let request = parse(&record, &config).unwrap();
+        let mut expected: Value = serde_json::from_str(RECORD).unwrap();
+        let expected = expected["request"].as_object_mut().unwrap();
+        expected.remove("stream");
+        expected.remove("stream_options");
+        assert_eq!(serde_json::to_value(&request).unwrap(), Value::Object(expected.clone()));
+
+        let body = serde_json::to_string(&Value::Object(expected.clone())).unwrap();
+        let request = parse(&read_record(&body).unwrap(), &config).unwrap();
+        assert_eq!(request.model, "gpt-4o");
+        assert!(parse("{\"request\":{}}", &config).is_err());
+    }
+}
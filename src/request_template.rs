@@ -0,0 +1,143 @@
+// Winston request templates
+// A request template is a TOML file in XDG_CONFIG_HOME/winston/requests that
+// bundles a whole request: the user message, optionally a system message, and
+// any of the parameters a preset can set. `winston run <name>` sends it, with
+// every {{variable}} in the messages filled from --var name=value. The
+// parameters layer over the config file and any preset and below the command
+// line flags, like prompt file front-matter.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::chat::{ChatRequest, Message, Role};
+use crate::config::{Preset, WinstonConfig};
+use super::Result;
+
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct RequestTemplate {
+    pub system: Option<String>,
+    pub user: String,
+    #[serde(flatten)]
+    pub params: Preset,
+}
+
+// the directory request templates are kept in
+pub fn requests_dir() -> Result<PathBuf> {
+    let mut dir = dirs::config_dir().ok_or("Could not find config directory")?;
+    dir.push("winston");
+    dir.push("requests");
+    Ok(dir)
+}
+
+// the file of a named request template in dir
+pub fn template_file(dir: &Path, name: &str) -> Result<PathBuf> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+    if !valid {
+        return Err(format!("Invalid request template name `{}`", name).into());
+    }
+    Ok(dir.join(format!("{}.toml", name)))
+}
+
+// parse a --var flag, a name and its value separated by =
+pub fn parse_var(s: &str) -> std::result::Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() => Ok((name.trim().to_string(), value.to_string())),
+        _ => Err(format!("Invalid variable `{}`, expected name=value", s)),
+    }
+}
+
+// text with every {{name}} replaced by the value of the variable
+fn fill(text: &str, vars: &BTreeMap<String, String>) -> Result<String> {
+    let mut filled = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| format!("Unclosed {{{{ in `{}`", text))?;
+        let name = rest[start + 2..start + end].trim();
+        let value = vars
+            .get(name)
+            .ok_or_else(|| format!("No value given for `{}`, set it with --var {}=...", name, name))?;
+        filled.push_str(&rest[..start]);
+        filled.push_str(value);
+        rest = &rest[start + end + 2..];
+    }
+    filled.push_str(rest);
+    Ok(filled)
+}
+
+impl RequestTemplate {
+    pub fn load(fp: &Path) -> Result<Self> {
+        let source = std::fs::read_to_string(fp)
+            .map_err(|e| format!("Could not read request template {}: {}", fp.display(), e))?;
+        let template = toml::from_str(&source)
+            .map_err(|e| format!("Invalid request template {}: {}", fp.display(), e))?;
+        Ok(template)
+    }
+
+    // the request with its variables filled in. The template's parameters are
+    // expected to be in config already, the system message replaces the
+    // configured one.
+    pub fn request(&self, config: &WinstonConfig, vars: &BTreeMap<String, String>) -> Result<ChatRequest> {
+        let mut messages = Vec::new();
+        if let Some(system) = &self.system {
+            messages.push(Message::new(Role::System, fill(system, vars)?));
+        }
+        messages.push(Message::new(Role::User, fill(&self.user, vars)?));
+        Ok(ChatRequest::new(config, messages))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::WinstonConfigBuilder;
+
+    // test that a template's parameters and filled in messages make up the request
+    #[test]
+    fn request_template_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let fp = template_file(temp_dir.path(), "review").unwrap();
+        std::fs::write(
+            &fp,
+            r#"system = "You review {{ language }} code."
+user = "Review this:\n{{code}}"
+model = "gpt-4o"
+temperature = 0.2
+max_tokens = 300
+"#,
+        )
+        .unwrap();
+        let template = RequestTemplate::load(&fp).unwrap();
+        let config = WinstonConfigBuilder::new()
+            .system("Be brief.".to_string())
+            .apply_preset(template.params.clone())
+            .build()
+            .unwrap();
+        let vars = BTreeMap::from([
+            parse_var("language=Rust").unwrap(),
+            parse_var("code=let x = 1;").unwrap(),
+        ]);
+
+        let request = template.request(&config, &vars).unwrap();
+        assert_eq!(request.model, "gpt-4o");
+        assert_eq!(request.temperature, 0.2);
+        assert_eq!(request.max_tokens, Some(300));
+        assert_eq!(
+            request.messages,
+            [
+                Message::new(Role::System, "You review Rust code."),
+                Message::new(Role::User, "Review this:\nlet x = 1;"),
+            ]
+        );
+
+        let err = template.request(&config, &BTreeMap::new()).unwrap_err();
+        assert_eq!(err.to_string(), "No value given for `language`, set it with --var language=...");
+        assert!(parse_var("novalue").is_err());
+        assert!(template_file(temp_dir.path(), "../escape").is_err());
+    }
+}
@@ -0,0 +1,216 @@
+// Winston structured output
+// --schema sends a JSON Schema as the json_schema response format so the model
+// replies with JSON of that shape, and the reply is checked against the schema
+// before it is printed. The check covers the keywords structured outputs
+// support: type, enum, const, properties, required, additionalProperties,
+// items and anyOf. Other keywords are accepted and not checked.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::Result;
+
+// the response_format of a structured output request
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    JsonSchema { json_schema: JsonSchema },
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct JsonSchema {
+    pub name: String,
+    pub schema: Value,
+    pub strict: bool,
+}
+
+impl ResponseFormat {
+    // the response format for a schema file, named after the file
+    pub fn load(fp: &Path) -> Result<Self> {
+        let source = std::fs::read_to_string(fp)
+            .map_err(|e| format!("Could not read schema {}: {}", fp.display(), e))?;
+        let schema: Value = serde_json::from_str(&source)
+            .map_err(|e| format!("Invalid schema {}: {}", fp.display(), e))?;
+        if !schema.is_object() {
+            return Err(format!("Invalid schema {}: expected a JSON object", fp.display()).into());
+        }
+        // names may only hold letters, digits, underscores and dashes
+        let name: String = fp
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+            .collect();
+        Ok(ResponseFormat::JsonSchema {
+            json_schema: JsonSchema { name, schema, strict: true },
+        })
+    }
+
+    pub fn schema(&self) -> &Value {
+        match self {
+            ResponseFormat::JsonSchema { json_schema } => &json_schema.schema,
+        }
+    }
+
+    // parse a completion and check it against the schema
+    pub fn validate(&self, content: &str) -> Result<Value> {
+        let value: Value = serde_json::from_str(content.trim())
+            .map_err(|e| format!("The response is not valid JSON: {}", e))?;
+        validate(self.schema(), &value, "$")
+            .map_err(|e| format!("The response doesn't match the schema: {}", e))?;
+        Ok(value)
+    }
+}
+
+// whether value is of a JSON Schema type
+fn is_type(value: &Value, name: &str) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0),
+        _ => true,
+    }
+}
+
+// check value against schema, returning the first mismatch with its path
+pub fn validate(schema: &Value, value: &Value, path: &str) -> std::result::Result<(), String> {
+    let Some(schema) = schema.as_object() else {
+        // true accepts everything and false nothing
+        return match schema {
+            Value::Bool(false) => Err(format!("{} is not allowed", path)),
+            _ => Ok(()),
+        };
+    };
+
+    let types: Vec<&str> = match schema.get("type") {
+        Some(Value::String(name)) => vec![name],
+        Some(Value::Array(names)) => names.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    if !types.is_empty() && !types.iter().any(|name| is_type(value, name)) {
+        return Err(format!("{} should be {}, found {}", path, types.join(" or "), value));
+    }
+    if let Some(Value::Array(options)) = schema.get("enum") {
+        if !options.contains(value) {
+            return Err(format!("{} should be one of {}, found {}", path, Value::Array(options.clone()), value));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            return Err(format!("{} should be {}, found {}", path, expected, value));
+        }
+    }
+    if let Some(Value::Array(schemas)) = schema.get("anyOf") {
+        if !schemas.iter().any(|schema| validate(schema, value, path).is_ok()) {
+            return Err(format!("{} matches none of the anyOf schemas", path));
+        }
+    }
+
+    if let Value::Object(object) = value {
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for name in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(name) {
+                    return Err(format!("{} is missing required property `{}`", path, name));
+                }
+            }
+        }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for (name, property) in object {
+            let path = format!("{}.{}", path, name);
+            match properties.and_then(|properties| properties.get(name)) {
+                Some(property_schema) => validate(property_schema, property, &path)?,
+                None => match schema.get("additionalProperties") {
+                    Some(Value::Bool(false)) => return Err(format!("{} is not an allowed property", path)),
+                    Some(additional) => validate(additional, property, &path)?,
+                    None => {}
+                },
+            }
+        }
+    }
+    if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (index, item) in items.iter().enumerate() {
+            validate(item_schema, item, &format!("{}[{}]", path, index))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chat::ChatRequest;
+    use crate::client::Client;
+    use crate::config::WinstonConfigBuilder;
+
+    const SCHEMA: &str = r#"{
+  "type": "object",
+  "properties": {
+    "name": {"type": "string"},
+    "age": {"type": "integer"},
+    "tags": {"type": "array", "items": {"type": "string"}}
+  },
+  "required": ["name", "age"],
+  "additionalProperties": false
+}"#;
+
+    // test that the schema is sent as the response format and replies are
+    // checked against it
+    #[test]
+    fn json_schema_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let fp = temp_dir.path().join("person.schema.json");
+        std::fs::write(&fp, SCHEMA).unwrap();
+        let format = ResponseFormat::load(&fp).unwrap();
+
+        let reply = |content: &str| {
+            serde_json::json!({"choices": [{"message": {"role": "assistant", "content": content}}]}).to_string()
+        };
+        let mut server = mockito::Server::new();
+        let conforming = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "response_format": {"type": "json_schema", "json_schema": {"name": "person_schema", "strict": true}}
+            })))
+            .with_status(200)
+            .with_body(reply(r#"{"name": "Ada", "age": 36, "tags": ["math"]}"#))
+            .create();
+
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .model("gpt-4o".to_string())
+            .build()
+            .unwrap();
+        let client = Client::from_config(&config).unwrap();
+        let mut request = ChatRequest::new(&config, Vec::new());
+        request.response_format = Some(format.clone());
+        let response = client.chat(&request).unwrap();
+        conforming.assert();
+        assert_eq!(format.validate(response.content().unwrap()).unwrap()["age"], 36);
+
+        conforming.remove();
+        let _nonconforming = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_body(reply(r#"{"name": "Ada", "age": "thirty-six", "tags": ["math"]}"#))
+            .create();
+        let response = client.chat(&request).unwrap();
+        assert_eq!(
+            format.validate(response.content().unwrap()).unwrap_err().to_string(),
+            "The response doesn't match the schema: $.age should be integer, found \"thirty-six\""
+        );
+
+        let err = |content: &str| format.validate(content).unwrap_err().to_string();
+        assert!(err(r#"{"name": "Ada"}"#).ends_with("$ is missing required property `age`"));
+        assert!(err(r#"{"name": "Ada", "age": 36, "tags": [1]}"#).ends_with("$.tags[0] should be string, found 1"));
+        assert!(err(r#"{"name": "Ada", "age": 36, "email": "a@b"}"#).ends_with("$.email is not an allowed property"));
+        assert!(err("Sure! Here it is").starts_with("The response is not valid JSON"));
+    }
+}
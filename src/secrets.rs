@@ -0,0 +1,91 @@
+// Winston keyring secrets
+// The API key and organization can be kept in the system keyring instead of
+// the config file by setting them to "keyring" there. With the keyring feature
+// enabled they are read from the keyring when a client is created, stored
+// under the service "winston" with the config field as the user, and
+// `config store-key` saves them there. On Linux this is the kernel keyring,
+// which keeps secrets until the user logs out. Without the feature, or without
+// a usable keyring, the secret is unavailable and the error says why.
+
+use super::Result;
+
+// the config value that stands for a secret kept in the keyring
+pub const KEYRING: &str = "keyring";
+#[cfg(feature = "keyring")]
+pub const SERVICE: &str = "winston";
+
+// look up a secret in the system keyring
+#[cfg(feature = "keyring")]
+pub fn lookup(field: &str) -> Result<String> {
+    read(&keyring::Entry::new(SERVICE, field)?, field)
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn lookup(field: &str) -> Result<String> {
+    Err(format!("{} is kept in the keyring, but winston was built without the keyring feature", field).into())
+}
+
+#[cfg(feature = "keyring")]
+fn read(entry: &keyring::Entry, field: &str) -> Result<String> {
+    entry.get_password().map_err(|e| match e {
+        keyring::Error::NoEntry => format!("No {} in the keyring, save one with `winston config store-key`", field).into(),
+        e => format!("Could not read {} from the keyring: {}", field, e).into(),
+    })
+}
+
+// save a secret in the system keyring
+#[cfg(feature = "keyring")]
+pub fn store(field: &str, secret: &str) -> Result<()> {
+    keyring::Entry::new(SERVICE, field)?
+        .set_password(secret)
+        .map_err(|e| format!("Could not save {} in the keyring: {}", field, e).into())
+}
+
+// the value of a secret setting, looked up in the keyring when it is "keyring"
+pub fn resolve(field: &str, value: &str) -> Result<String> {
+    resolve_with(field, value, lookup)
+}
+
+pub fn resolve_with(field: &str, value: &str, lookup: impl FnOnce(&str) -> Result<String>) -> Result<String> {
+    if value == KEYRING {
+        lookup(field)
+    } else {
+        Ok(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // test that "keyring" settings are read from a mock keyring and that an
+    // unavailable keyring is reported
+    #[cfg(feature = "keyring")]
+    #[test]
+    fn keyring_lookup_test() {
+        keyring::set_default_credential_builder(keyring::mock::default_credential_builder());
+        let entry = keyring::Entry::new(SERVICE, "openai_api_key").unwrap();
+        let lookup = |field: &str| read(&entry, field);
+        assert_eq!(
+            resolve_with("openai_api_key", KEYRING, lookup).unwrap_err().to_string(),
+            "No openai_api_key in the keyring, save one with `winston config store-key`"
+        );
+
+        entry.set_password("sk-from-keyring").unwrap();
+        assert_eq!(resolve_with("openai_api_key", KEYRING, lookup).unwrap(), "sk-from-keyring");
+        assert_eq!(resolve_with("openai_api_key", "sk-plain", lookup).unwrap(), "sk-plain");
+
+        let mock: &keyring::mock::MockCredential = entry.get_credential().downcast_ref().unwrap();
+        mock.set_error(keyring::Error::NoStorageAccess("no keyring daemon".into()));
+        let err = resolve_with("openai_api_key", KEYRING, lookup).unwrap_err().to_string();
+        assert!(err.starts_with("Could not read openai_api_key from the keyring"));
+    }
+
+    // test that without the feature a keyring setting is an error
+    #[cfg(not(feature = "keyring"))]
+    #[test]
+    fn keyring_unavailable_test() {
+        assert_eq!(resolve("openai_api_key", "sk-plain").unwrap(), "sk-plain");
+        assert!(resolve("openai_api_key", KEYRING).unwrap_err().to_string().contains("without the keyring feature"));
+    }
+}
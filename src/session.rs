@@ -0,0 +1,272 @@
+// Winston saved sessions
+// A repl started with --session keeps its conversation in a JSON file named
+// after the session in XDG_DATA_HOME/winston/sessions, so it can be picked up
+// again later or exported as a document. Exports render every message under a
+// heading for its role. Message text is usually Markdown already, so fenced
+// code blocks are kept as they are in Markdown and plain text and become
+// preformatted blocks in HTML. Sessions are listed most recently modified
+// first, optionally only those modified within a duration such as 7d or 24h.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::chat::{Message, Role};
+use super::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ExportFormat {
+    Md,
+    Html,
+    Txt,
+}
+
+// the directory sessions are saved in
+pub fn sessions_dir() -> Result<PathBuf> {
+    let mut dir = dirs::data_dir().ok_or("Could not find data directory")?;
+    dir.push("winston");
+    dir.push("sessions");
+    Ok(dir)
+}
+
+// the file of a named session in dir
+pub fn session_file(dir: &Path, name: &str) -> Result<PathBuf> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+    if !valid {
+        return Err(format!("Invalid session name `{}`", name).into());
+    }
+    Ok(dir.join(format!("{}.json", name)))
+}
+
+// the messages of a saved session, empty when it doesn't exist yet
+pub fn load(fp: &Path) -> Result<Vec<Message>> {
+    if !fp.exists() {
+        return Ok(Vec::new());
+    }
+    let messages = std::fs::read_to_string(fp)
+        .map_err(|e| format!("Could not read session {}: {}", fp.display(), e))?;
+    let messages = serde_json::from_str(&messages)
+        .map_err(|e| format!("Invalid session {}: {}", fp.display(), e))?;
+    Ok(messages)
+}
+
+pub fn save(fp: &Path, messages: &[Message]) -> Result<()> {
+    if let Some(dir) = fp.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Could not create session directory {}: {}", dir.display(), e))?;
+    }
+    std::fs::write(fp, serde_json::to_string_pretty(messages)?)
+        .map_err(|e| format!("Could not write session {}: {}", fp.display(), e))?;
+    Ok(())
+}
+
+// a saved session as listed
+#[derive(Debug, PartialEq)]
+pub struct SessionEntry {
+    pub name: String,
+    pub modified: SystemTime,
+}
+
+// parse a relative duration, a whole number followed by ms, s, m, h, d or w
+pub fn parse_duration(s: &str) -> std::result::Result<Duration, String> {
+    let s = s.trim();
+    let invalid = || format!("Invalid duration `{}`, expected a number followed by ms, s, m, h, d or w like 7d", s);
+    if let Some(millis) = s.strip_suffix("ms") {
+        return millis.parse().map(Duration::from_millis).map_err(|_| invalid());
+    }
+    let unit = s.chars().last().ok_or_else(invalid)?;
+    let seconds = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    let count: u64 = s[..s.len() - 1].parse().map_err(|_| invalid())?;
+    count.checked_mul(seconds).map(Duration::from_secs).ok_or_else(invalid)
+}
+
+// the sessions saved in dir, most recently modified first. With since, only
+// the sessions modified within that long before now are listed.
+pub fn list(dir: &Path, since: Option<Duration>, now: SystemTime) -> Result<Vec<SessionEntry>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let cutoff = since.and_then(|since| now.checked_sub(since));
+    let mut sessions = Vec::new();
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| format!("Could not read session directory {}: {}", dir.display(), e))?;
+    for entry in entries {
+        let path = entry?.path();
+        let Some(name) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".json"))
+        else {
+            continue;
+        };
+        let modified = std::fs::metadata(&path)?.modified()?;
+        if cutoff.is_some_and(|cutoff| modified < cutoff) {
+            continue;
+        }
+        sessions.push(SessionEntry {
+            name: name.to_string(),
+            modified,
+        });
+    }
+    sessions.sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| a.name.cmp(&b.name)));
+    Ok(sessions)
+}
+
+fn heading(role: Role) -> &'static str {
+    match role {
+        Role::System => "System",
+        Role::User => "User",
+        Role::Assistant => "Assistant",
+        Role::Tool => "Tool",
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// render text as HTML paragraphs, with fenced code blocks as pre blocks
+fn html_body(text: &str) -> String {
+    let mut html = String::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut code: Option<Vec<&str>> = None;
+    let flush = |paragraph: &mut Vec<&str>, html: &mut String| {
+        if !paragraph.is_empty() {
+            html.push_str(&format!("<p>{}</p>\n", escape_html(&paragraph.join("\n"))));
+            paragraph.clear();
+        }
+    };
+    for line in text.lines() {
+        let fence = line.trim_start().starts_with("```");
+        match (&mut code, fence) {
+            (Some(lines), true) => {
+                html.push_str(&format!("<pre><code>{}</code></pre>\n", escape_html(&lines.join("\n"))));
+                code = None;
+            }
+            (Some(lines), false) => lines.push(line),
+            (None, true) => {
+                flush(&mut paragraph, &mut html);
+                code = Some(Vec::new());
+            }
+            (None, false) if line.trim().is_empty() => flush(&mut paragraph, &mut html),
+            (None, false) => paragraph.push(line),
+        }
+    }
+    // an unclosed fence still ends up as code
+    if let Some(lines) = code {
+        html.push_str(&format!("<pre><code>{}</code></pre>\n", escape_html(&lines.join("\n"))));
+    }
+    flush(&mut paragraph, &mut html);
+    html
+}
+
+// render a conversation as a document
+pub fn export(messages: &[Message], format: ExportFormat, assistant_label: Option<&str>) -> String {
+    let label = |role: Role| match (role, assistant_label) {
+        (Role::Assistant, Some(label)) => label,
+        (role, _) => heading(role),
+    };
+    let mut document = String::new();
+    match format {
+        ExportFormat::Md => {
+            for message in messages {
+                document.push_str(&format!("## {}\n\n{}\n\n", label(message.role), message.content.trim_end()));
+            }
+        }
+        ExportFormat::Txt => {
+            for message in messages {
+                document.push_str(&format!("{}:\n{}\n\n", label(message.role), message.content.trim_end()));
+            }
+        }
+        ExportFormat::Html => {
+            document.push_str("<!DOCTYPE html>\n<html>\n<body>\n");
+            for message in messages {
+                document.push_str(&format!("<h2>{}</h2>\n{}", escape_html(label(message.role)), html_body(&message.content)));
+            }
+            document.push_str("</body>\n</html>\n");
+        }
+    }
+    document
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn conversation() -> Vec<Message> {
+        vec![
+            Message::new(Role::User, "How do I print in Rust?"),
+            Message::new(Role::Assistant, "Use println:\n\n```rust\nprintln!(\"<hi>\");\n```\n"),
+        ]
+    }
+
+    // test that exported Markdown has role headings and the message text
+    #[test]
+    fn export_markdown_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let fp = session_file(temp_dir.path(), "rust-help").unwrap();
+        save(&fp, &conversation()).unwrap();
+
+        let markdown = export(&load(&fp).unwrap(), ExportFormat::Md, None);
+        assert_eq!(
+            markdown,
+            "## User\n\nHow do I print in Rust?\n\n## Assistant\n\nUse println:\n\n```rust\nprintln!(\"<hi>\");\n```\n\n"
+        );
+        assert!(session_file(temp_dir.path(), "../escape").is_err());
+
+        let text = export(&conversation(), ExportFormat::Txt, Some("Winston"));
+        assert!(text.starts_with("User:\nHow do I print in Rust?\n\nWinston:\nUse println:"));
+    }
+
+    // test that durations are parsed with each unit and bad ones rejected
+    #[test]
+    fn parse_duration_test() {
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("15m"), Ok(Duration::from_secs(15 * 60)));
+        assert_eq!(parse_duration("24h"), Ok(Duration::from_secs(24 * 60 * 60)));
+        assert_eq!(parse_duration("7d"), Ok(Duration::from_secs(7 * 24 * 60 * 60)));
+        assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(14 * 24 * 60 * 60)));
+        for invalid in ["", "ms", "d", "7", "7y", "-1d", "1.5h", "99999999999999999w"] {
+            assert!(parse_duration(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    // test that sessions are listed newest first and filtered by modification time
+    #[test]
+    fn list_since_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        let hours = |hours: u64| Duration::from_secs(hours * 60 * 60);
+        for (name, age) in [("last-week", hours(24 * 6)), ("today", hours(2)), ("last-month", hours(24 * 30))] {
+            let fp = session_file(temp_dir.path(), name).unwrap();
+            save(&fp, &conversation()).unwrap();
+            std::fs::File::options().write(true).open(&fp).unwrap().set_modified(now - age).unwrap();
+        }
+        std::fs::write(temp_dir.path().join("notes.txt"), "").unwrap();
+
+        let names = |since| -> Vec<String> {
+            list(temp_dir.path(), since, now).unwrap().into_iter().map(|entry| entry.name).collect()
+        };
+        assert_eq!(names(None), ["today", "last-week", "last-month"]);
+        assert_eq!(names(Some(hours(24 * 7))), ["today", "last-week"]);
+        assert_eq!(names(Some(hours(24))), ["today"]);
+        assert!(list(&temp_dir.path().join("missing"), None, now).unwrap().is_empty());
+    }
+
+    // test that code blocks are preserved and escaped in HTML
+    #[test]
+    fn export_html_test() {
+        let html = export(&conversation(), ExportFormat::Html, None);
+        assert!(html.contains("<h2>User</h2>\n<p>How do I print in Rust?</p>\n"));
+        assert!(html.contains("<pre><code>println!(\"&lt;hi&gt;\");</code></pre>\n"));
+    }
+}
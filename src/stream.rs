@@ -0,0 +1,389 @@
+// Winston streaming responses
+// Streamed chat completions arrive as server-sent events, each data line
+// holding a chunk with the next content delta, until a final [DONE] line. When
+// stream_options.include_usage is set, the last chunk before [DONE] has no
+// choices and carries the usage of the whole request. Legacy completions
+// stream their text in the choice itself rather than in a delta.
+// Each delta is handed on as soon as it arrives, so whatever was received is
+// already out when the connection drops. A stream that ends before [DONE],
+// whether the connection failed or closed early, is an interrupted stream.
+// Chunks are read as typed events, content and tool call deltas, finish reasons
+// and usage, for callers that need more than the text. Plain content streaming
+// is layered on top of them.
+// Servers leave the matched stop sequence out of a completion, but some let it
+// through when streaming, so streamed content is cut at the first stop
+// sequence too. Text that might be the start of one is held back until the
+// next delta shows whether it is.
+// With --show-usage or --verbose a stream is timed, from sending the request to
+// the first content and on to the end, and the rate of deltas after the first
+// is reported as the throughput. Servers send about one token per delta.
+
+use std::fmt;
+use std::io::BufRead;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::chat::Usage;
+use crate::error::WinstonError;
+use crate::think::partial_len;
+use super::Result;
+
+#[derive(Debug, Deserialize)]
+struct Chunk {
+    #[serde(default)]
+    choices: Vec<ChunkChoice>,
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChunkChoice {
+    #[serde(default)]
+    delta: Delta,
+    text: Option<String>,
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Delta {
+    content: Option<String>,
+    refusal: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCallChunk>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ToolCallChunk {
+    index: u32,
+    id: Option<String>,
+    #[serde(default)]
+    function: FunctionChunk,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct FunctionChunk {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+// a piece of a tool call. The first piece of each call has its id and name,
+// and its arguments arrive in pieces of JSON text to be joined up by index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCallDelta {
+    pub index: u32,
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub arguments: String,
+}
+
+// something received over a stream, in the order it arrived
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatEvent {
+    // the next piece of the completion
+    Delta(String),
+    // the next piece of a refusal
+    Refusal(String),
+    ToolCallDelta(ToolCallDelta),
+    // why the model stopped, "tool_calls" when it is waiting on tool results
+    Finish(String),
+    // the usage of the whole request, when include_usage was set
+    Usage(Usage),
+}
+
+// everything received over a stream once it has finished
+#[derive(Debug, Default)]
+pub struct StreamSummary {
+    pub content: String,
+    pub refusal: Option<String>,
+    pub usage: Option<Usage>,
+    // why the model stopped, "length" when it ran into max_tokens
+    pub finish_reason: Option<String>,
+}
+
+// times a stream as its deltas arrive
+#[derive(Debug)]
+pub struct StreamTimer {
+    start: Instant,
+    first: Option<Instant>,
+    deltas: u32,
+}
+
+impl StreamTimer {
+    // start timing, just before the request is sent
+    pub fn start() -> Self {
+        Self {
+            start: Instant::now(),
+            first: None,
+            deltas: 0,
+        }
+    }
+
+    // note a content delta, empty ones carry no tokens
+    pub fn delta(&mut self, delta: &str) {
+        if delta.is_empty() {
+            return;
+        }
+        self.first.get_or_insert_with(Instant::now);
+        self.deltas += 1;
+    }
+
+    pub fn finish(&self) -> StreamStats {
+        StreamStats {
+            time_to_first_token: self.first.map(|first| first - self.start),
+            elapsed: self.start.elapsed(),
+            deltas: self.deltas,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct StreamStats {
+    pub time_to_first_token: Option<Duration>,
+    pub elapsed: Duration,
+    pub deltas: u32,
+}
+
+impl StreamStats {
+    // deltas per second after the first, unknown with fewer than two
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        let generating = self.elapsed.checked_sub(self.time_to_first_token?)?.as_secs_f64();
+        (self.deltas > 1 && generating > 0.0).then(|| (self.deltas - 1) as f64 / generating)
+    }
+}
+
+impl fmt::Display for StreamStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.time_to_first_token {
+            Some(ttft) => write!(f, "time to first token: {} ms", ttft.as_millis())?,
+            None => f.write_str("time to first token: none received")?,
+        }
+        if let Some(rate) = self.tokens_per_second() {
+            write!(f, ", {:.1} tokens/s", rate)?;
+        }
+        write!(f, " ({} deltas in {} ms)", self.deltas, self.elapsed.as_millis())
+    }
+}
+
+// cuts streamed content at the first stop sequence
+#[derive(Debug)]
+pub struct StopTrimmer {
+    stops: Vec<String>,
+    // text not passed on yet because it may be part of a stop sequence
+    pending: String,
+    stopped: bool,
+}
+
+impl StopTrimmer {
+    pub fn new(stops: &[&str]) -> Self {
+        Self {
+            stops: stops.iter().filter(|stop| !stop.is_empty()).map(|stop| stop.to_string()).collect(),
+            pending: String::new(),
+            stopped: false,
+        }
+    }
+
+    // the part of the next delta that can be passed on so far, nothing once a
+    // stop sequence was seen
+    pub fn push(&mut self, delta: &str) -> String {
+        if self.stopped {
+            return String::new();
+        }
+        self.pending.push_str(delta);
+        if let Some(start) = self.stops.iter().filter_map(|stop| self.pending.find(stop.as_str())).min() {
+            self.stopped = true;
+            self.pending.truncate(start);
+            return std::mem::take(&mut self.pending);
+        }
+        let keep = self.stops.iter().map(|stop| partial_len(&self.pending, stop)).max().unwrap_or(0);
+        self.pending.drain(..self.pending.len() - keep).collect()
+    }
+
+    // the text held back at the end of the stream
+    pub fn finish(&mut self) -> String {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+// read server-sent events from reader, passing each event to on_event as it
+// arrives
+pub fn read_events(reader: impl BufRead, mut on_event: impl FnMut(ChatEvent) -> Result<()>) -> Result<StreamSummary> {
+    let mut summary = StreamSummary::default();
+    let interrupted = |summary: &StreamSummary, reason: String| WinstonError::StreamInterrupted {
+        received: summary.content.len(),
+        reason,
+    };
+    for line in reader.lines() {
+        let line = line.map_err(|e| interrupted(&summary, e.to_string()))?;
+        let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+            continue;
+        };
+        if data == "[DONE]" {
+            return Ok(summary);
+        }
+        let chunk: Chunk = match serde_json::from_str(data) {
+            Ok(chunk) => chunk,
+            // the connection closed partway through a chunk
+            Err(e) if e.is_eof() => return Err(interrupted(&summary, e.to_string()).into()),
+            Err(e) => return Err(e.into()),
+        };
+        for choice in chunk.choices {
+            if let Some(content) = choice.delta.content.or(choice.text) {
+                summary.content.push_str(&content);
+                on_event(ChatEvent::Delta(content))?;
+            }
+            if let Some(refusal) = choice.delta.refusal {
+                summary.refusal.get_or_insert_with(String::new).push_str(&refusal);
+                on_event(ChatEvent::Refusal(refusal))?;
+            }
+            for call in choice.delta.tool_calls {
+                on_event(ChatEvent::ToolCallDelta(ToolCallDelta {
+                    index: call.index,
+                    id: call.id,
+                    name: call.function.name,
+                    arguments: call.function.arguments.unwrap_or_default(),
+                }))?;
+            }
+            if let Some(reason) = choice.finish_reason {
+                summary.finish_reason = Some(reason.clone());
+                on_event(ChatEvent::Finish(reason))?;
+            }
+        }
+        if let Some(usage) = chunk.usage {
+            summary.usage = Some(usage);
+            on_event(ChatEvent::Usage(usage))?;
+        }
+    }
+
+    Err(interrupted(&summary, "the stream ended without [DONE]".to_string()).into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // read the stream, passing only the content deltas to on_delta
+    fn read_stream(reader: impl BufRead, mut on_delta: impl FnMut(&str) -> Result<()>) -> Result<StreamSummary> {
+        read_events(reader, |event| match event {
+            ChatEvent::Delta(content) => on_delta(&content),
+            _ => Ok(()),
+        })
+    }
+
+    // a stream requested with include_usage, the final chunk carries usage
+    const USAGE_STREAM: &str = r#"data: {"choices":[{"index":0,"delta":{"role":"assistant","content":""}}],"usage":null}
+
+data: {"choices":[{"index":0,"delta":{"content":"Hello"}}],"usage":null}
+
+data: {"choices":[{"index":0,"delta":{"content":" there"},"finish_reason":"stop"}],"usage":null}
+
+data: {"choices":[],"usage":{"prompt_tokens":9,"completion_tokens":2,"total_tokens":11}}
+
+data: [DONE]
+
+"#;
+
+    // reads fail as if the connection was reset
+    struct ResetReader;
+
+    impl std::io::Read for ResetReader {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::ConnectionReset.into())
+        }
+    }
+
+    // test that the deltas received before an error are passed on and the
+    // stream is reported as interrupted
+    #[test]
+    fn stream_interrupted_test() {
+        use std::io::Read;
+
+        let cut = USAGE_STREAM.find("data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\" there").unwrap();
+        let received = &USAGE_STREAM.as_bytes()[..cut];
+        let mut deltas = Vec::new();
+        let reader = std::io::BufReader::new(received.chain(ResetReader));
+        let err = read_stream(reader, |delta| {
+            deltas.push(delta.to_string());
+            Ok(())
+        })
+        .unwrap_err();
+        assert_eq!(deltas, ["", "Hello"]);
+        assert!(matches!(
+            err.downcast_ref::<WinstonError>(),
+            Some(WinstonError::StreamInterrupted { received: 5, .. })
+        ));
+
+        let err = read_stream(received, |_| Ok(())).unwrap_err();
+        assert!(err.to_string().ends_with("the stream ended without [DONE]"));
+    }
+
+    // test that deltas are passed on in order and the trailing usage is kept
+    #[test]
+    fn stream_usage_chunk_test() {
+        let mut deltas = Vec::new();
+        let summary = read_stream(USAGE_STREAM.as_bytes(), |delta| {
+            deltas.push(delta.to_string());
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(deltas, ["", "Hello", " there"]);
+        assert_eq!(summary.content, "Hello there");
+        assert_eq!(summary.finish_reason.as_deref(), Some("stop"));
+        let usage = summary.usage.unwrap();
+        assert_eq!(usage.prompt_tokens, 9);
+        assert_eq!(usage.completion_tokens, 2);
+        assert_eq!(usage.total_tokens, 11);
+    }
+
+    // yields each part after its delay, like a server generating tokens
+    struct TimedReader {
+        parts: std::collections::VecDeque<(Duration, &'static [u8])>,
+    }
+
+    impl std::io::Read for TimedReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let Some((delay, part)) = self.parts.pop_front() else {
+                return Ok(0);
+            };
+            std::thread::sleep(delay);
+            buf[..part.len()].copy_from_slice(part);
+            Ok(part.len())
+        }
+    }
+
+    // test that the time to the first delta and the rate after it are measured
+    #[test]
+    fn stream_stats_test() {
+        let delay = Duration::from_millis(50);
+        let reader = TimedReader {
+            parts: [
+                (delay * 2, b"data: {\"choices\":[{\"delta\":{\"content\":\"\"}}]}\n\n".as_slice()),
+                (Duration::ZERO, b"data: {\"choices\":[{\"delta\":{\"content\":\"one\"}}]}\n\n".as_slice()),
+                (delay, b"data: {\"choices\":[{\"delta\":{\"content\":\" two\"}}]}\n\n".as_slice()),
+                (delay, b"data: {\"choices\":[{\"delta\":{\"content\":\" three\"}}]}\n\n".as_slice()),
+                (Duration::ZERO, b"data: [DONE]\n\n".as_slice()),
+            ]
+            .into(),
+        };
+        let mut timer = StreamTimer::start();
+        read_stream(std::io::BufReader::new(reader), |delta| {
+            timer.delta(delta);
+            Ok(())
+        })
+        .unwrap();
+        let stats = timer.finish();
+
+        assert_eq!(stats.deltas, 3);
+        let ttft = stats.time_to_first_token.unwrap();
+        assert!(ttft >= delay * 2 && ttft < delay * 4, "{:?}", ttft);
+        // two deltas over about 100 ms
+        let rate = stats.tokens_per_second().unwrap();
+        assert!(rate > 5.0 && rate <= 20.0, "{}", rate);
+        let report = stats.to_string();
+        assert!(report.starts_with("time to first token: ") && report.contains(" tokens/s (3 deltas in "), "{}", report);
+
+        let stats = StreamTimer::start().finish();
+        assert_eq!(stats.tokens_per_second(), None);
+        assert!(stats.to_string().starts_with("time to first token: none received (0 deltas in "));
+    }
+}
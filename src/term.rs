@@ -0,0 +1,285 @@
+// Winston terminal helpers
+// Formatted output needs to know how wide the terminal is, which is asked of
+// the terminal itself, with COLUMNS as set by the shell when it can't say. When
+// stdout is piped there is no terminal width at all, so a fixed default is used
+// instead of guessing, which keeps piped output stable between runs.
+// Completions are written with the line endings chosen by --line-endings, those
+// of the platform by default. Color follows --color, then NO_COLOR, then
+// whether the output is a terminal. Completions longer than --max-chars are cut
+// short and end with the --truncation-marker so the cut is visible.
+
+use std::borrow::Cow;
+use std::io::IsTerminal;
+
+// the width assumed when the real width is unknown
+pub const DEFAULT_WIDTH: usize = 80;
+
+// the width of the terminal attached to stdout, falling back to COLUMNS as
+// set by the shell and then DEFAULT_WIDTH
+pub fn width() -> usize {
+    let stdout = std::io::stdout();
+    let detected = terminal_size::terminal_size_of(&stdout).map(|(terminal_size::Width(width), _)| width as usize);
+    width_from(stdout.is_terminal(), detected, std::env::var("COLUMNS").ok().as_deref())
+}
+
+// the output width given whether stdout is a terminal, the width the terminal
+// reported and the COLUMNS value
+pub fn width_from(tty: bool, detected: Option<usize>, columns: Option<&str>) -> usize {
+    if !tty {
+        return DEFAULT_WIDTH;
+    }
+    detected
+        .filter(|&width| width > 0)
+        .or_else(|| columns.and_then(|columns| columns.trim().parse().ok()).filter(|&width| width > 0))
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+// a heading ruled out to the width, as in `== title =====`
+pub fn heading(title: &str, width: usize) -> String {
+    let heading = format!("== {} ", title);
+    let fill = width.saturating_sub(heading.chars().count()).max(2);
+    format!("{}{}", heading, "=".repeat(fill))
+}
+
+// items laid out in as many columns as fit the width, row by row
+pub fn columns(items: &[&str], width: usize) -> String {
+    let column = items.iter().map(|item| item.chars().count()).max().unwrap_or(0) + 2;
+    let per_row = ((width + 2) / column).max(1);
+    let mut laid_out = String::new();
+    for row in items.chunks(per_row) {
+        let line: Vec<String> = row.iter().map(|item| format!("{:<width$}", item, width = column)).collect();
+        laid_out.push_str(line.concat().trim_end());
+        laid_out.push('\n');
+    }
+    laid_out
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum LineEndings {
+    Lf,
+    Crlf,
+    // CRLF on Windows and LF everywhere else
+    #[default]
+    Native,
+}
+
+impl LineEndings {
+    // text with every line ending, LF or CRLF, replaced by these
+    pub fn normalize<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let crlf = match self {
+            LineEndings::Lf => false,
+            LineEndings::Crlf => true,
+            LineEndings::Native => cfg!(windows),
+        };
+        if crlf {
+            if !text.contains('\n') {
+                return Cow::Borrowed(text);
+            }
+            Cow::Owned(text.replace("\r\n", "\n").replace('\n', "\r\n"))
+        } else if text.contains("\r\n") {
+            Cow::Owned(text.replace("\r\n", "\n"))
+        } else {
+            Cow::Borrowed(text)
+        }
+    }
+}
+
+// normalizes streamed output piece by piece, holding back a trailing CR until
+// the next piece shows whether it starts a CRLF split between the two
+#[derive(Debug)]
+pub struct StreamLineEndings {
+    endings: LineEndings,
+    pending_cr: bool,
+}
+
+impl StreamLineEndings {
+    pub fn new(endings: LineEndings) -> Self {
+        Self {
+            endings,
+            pending_cr: false,
+        }
+    }
+
+    // the next piece of output with its line endings replaced
+    pub fn push(&mut self, text: &str) -> String {
+        let mut text = match std::mem::take(&mut self.pending_cr) {
+            true => format!("\r{}", text),
+            false => text.to_string(),
+        };
+        if text.ends_with('\r') {
+            text.pop();
+            self.pending_cr = true;
+        }
+        self.endings.normalize(&text).into_owned()
+    }
+
+    // a CR held back at the end of the output
+    pub fn finish(&mut self) -> &'static str {
+        match std::mem::take(&mut self.pending_cr) {
+            true => "\r",
+            false => "",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ColorChoice {
+    Always,
+    // color when writing to a terminal and NO_COLOR isn't set
+    #[default]
+    Auto,
+    Never,
+}
+
+impl ColorChoice {
+    // whether to color output given the NO_COLOR value and whether the output
+    // is a terminal. NO_COLOR counts when it is set to anything but nothing.
+    pub fn enabled(&self, no_color: Option<&str>, tty: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => no_color.is_none_or(str::is_empty) && tty,
+        }
+    }
+}
+
+// the marker put where output was cut short unless one is configured
+pub const TRUNCATION_MARKER: &str = "\u{2026}";
+
+// cuts output to a number of characters as it is written, piece by piece for
+// streamed output, ending it with a marker when anything was left out
+#[derive(Debug)]
+pub struct Truncator {
+    remaining: Option<usize>,
+    marker: String,
+    truncated: bool,
+}
+
+impl Truncator {
+    pub fn new(max_chars: Option<usize>, marker: &str) -> Self {
+        Self {
+            remaining: max_chars,
+            marker: marker.to_string(),
+            truncated: false,
+        }
+    }
+
+    // the part of the next piece of output to write
+    pub fn push<'a>(&mut self, text: &'a str) -> Cow<'a, str> {
+        if self.truncated {
+            return Cow::Borrowed("");
+        }
+        let Some(remaining) = &mut self.remaining else {
+            return Cow::Borrowed(text);
+        };
+        match text.char_indices().nth(*remaining) {
+            Some((end, _)) => {
+                self.truncated = true;
+                Cow::Owned(format!("{}{}", &text[..end], self.marker))
+            }
+            None => {
+                *remaining -= text.chars().count();
+                Cow::Borrowed(text)
+            }
+        }
+    }
+
+    // whether any output was left out
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+// text wrapped in an ANSI SGR sequence such as "1;31", when color is on
+pub fn paint(text: &str, sgr: &str, color: bool) -> String {
+    match color {
+        true => format!("\x1b[{}m{}\x1b[0m", sgr, text),
+        false => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // test that the default width is used without a terminal or a usable COLUMNS
+    #[test]
+    fn fallback_width_test() {
+        assert_eq!(width_from(false, Some(100), Some("120")), DEFAULT_WIDTH);
+        assert_eq!(width_from(true, None, None), DEFAULT_WIDTH);
+        assert_eq!(width_from(true, None, Some("wide")), DEFAULT_WIDTH);
+        assert_eq!(width_from(true, None, Some("0")), DEFAULT_WIDTH);
+        assert_eq!(width_from(true, None, Some("120")), 120);
+        assert_eq!(width_from(true, Some(100), Some("120")), 100);
+        assert_eq!(width_from(true, Some(0), Some("120")), 120);
+    }
+
+    // test that headings are ruled to the width and items fill the columns
+    // that fit
+    #[test]
+    fn layout_width_test() {
+        assert_eq!(heading("gpt-4o", 16), "== gpt-4o ======");
+        assert_eq!(heading("a-very-long-model-name", 10), "== a-very-long-model-name ==");
+        let names = ["alpha", "beta", "gamma-ray", "delta"];
+        assert_eq!(columns(&names, 24), "alpha      beta\ngamma-ray  delta\n");
+        assert_eq!(columns(&names, 80), "alpha      beta       gamma-ray  delta\n");
+        assert_eq!(columns(&names, 5), "alpha\nbeta\ngamma-ray\ndelta\n");
+        assert_eq!(columns(&[], 80), "");
+    }
+
+    // test that LF and mixed line endings are converted under each option
+    #[test]
+    fn line_endings_test() {
+        assert_eq!(LineEndings::Crlf.normalize("one\ntwo\r\nthree\n"), "one\r\ntwo\r\nthree\r\n");
+        assert_eq!(LineEndings::Lf.normalize("one\r\ntwo\n"), "one\ntwo\n");
+        assert!(matches!(LineEndings::Lf.normalize("one\ntwo"), Cow::Borrowed(_)));
+        let native = if cfg!(windows) { "a\r\nb" } else { "a\nb" };
+        assert_eq!(LineEndings::default().normalize("a\nb"), native);
+    }
+
+    // test that a CRLF split between streamed pieces becomes one line ending
+    #[test]
+    fn stream_line_endings_test() {
+        let mut endings = StreamLineEndings::new(LineEndings::Crlf);
+        let pieces = ["one\r", "\ntwo\n", "three\r"].map(|piece| endings.push(piece));
+        assert_eq!(pieces.concat(), "one\r\ntwo\r\nthree");
+        assert_eq!(endings.finish(), "\r");
+        assert_eq!(endings.finish(), "");
+
+        let mut endings = StreamLineEndings::new(LineEndings::Lf);
+        assert_eq!(endings.push("a\r") + &endings.push("\nb"), "a\nb");
+    }
+
+    // test that the flag wins over NO_COLOR, which wins over the terminal check
+    #[test]
+    fn color_choice_test() {
+        assert!(ColorChoice::Always.enabled(Some("1"), false));
+        assert!(!ColorChoice::Never.enabled(None, true));
+        assert!(ColorChoice::Auto.enabled(None, true));
+        assert!(ColorChoice::Auto.enabled(Some(""), true));
+        assert!(!ColorChoice::Auto.enabled(Some("1"), true));
+        assert!(!ColorChoice::Auto.enabled(None, false));
+        assert_eq!(paint("error", "1;31", true), "\x1b[1;31merror\x1b[0m");
+        assert_eq!(paint("error", "1;31", false), "error");
+    }
+
+    // test that output is cut at the limit across pieces and ends with the
+    // configured marker
+    #[test]
+    fn truncation_marker_test() {
+        let mut truncator = Truncator::new(Some(8), " [cut]");
+        assert_eq!(truncator.push("héllo "), "héllo ");
+        assert_eq!(truncator.push("wörld"), "wö [cut]");
+        assert_eq!(truncator.push("!"), "");
+        assert!(truncator.truncated());
+
+        let mut truncator = Truncator::new(Some(5), TRUNCATION_MARKER);
+        assert_eq!(truncator.push("hello"), "hello");
+        assert!(!truncator.truncated());
+        assert_eq!(truncator.push(" there"), "\u{2026}");
+
+        let mut truncator = Truncator::new(None, TRUNCATION_MARKER);
+        assert_eq!(truncator.push(&"x".repeat(10_000)).len(), 10_000);
+        assert!(!truncator.truncated());
+    }
+}
@@ -0,0 +1,126 @@
+// Winston reasoning blocks
+// Some reasoning models write their reasoning into the content between tags
+// like <think> and </think>. With --strip-think-tags those blocks are removed
+// before the completion is printed. Streamed content arrives in pieces that
+// can split a tag, so text that might be the start of a tag is held back until
+// the next piece shows whether it is one. A block that is never closed runs to
+// the end of the content.
+
+// the tag stripped when --strip-think-tags names none
+pub const THINK_TAG: &str = "think";
+
+#[derive(Debug)]
+pub struct TagStripper {
+    // opening and closing delimiter of each tag
+    tags: Vec<(String, String)>,
+    // text not passed on yet because it may be part of a delimiter
+    pending: String,
+    // the tag of the block being removed
+    inside: Option<usize>,
+}
+
+// the length of the longest end of text that is the start of delimiter
+pub fn partial_len(text: &str, delimiter: &str) -> usize {
+    (1..delimiter.len())
+        .rev()
+        .find(|&len| text.as_bytes().ends_with(&delimiter.as_bytes()[..len]))
+        .unwrap_or(0)
+}
+
+impl TagStripper {
+    pub fn new(tags: &[String]) -> Self {
+        Self {
+            tags: tags.iter().map(|tag| (format!("<{}>", tag), format!("</{}>", tag))).collect(),
+            pending: String::new(),
+            inside: None,
+        }
+    }
+
+    // remove the blocks from the next piece of text, returning what can be
+    // passed on so far
+    pub fn push(&mut self, text: &str) -> String {
+        self.pending.push_str(text);
+        let mut out = String::new();
+        loop {
+            match self.inside {
+                None => {
+                    let open = self
+                        .tags
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(tag, (open, _))| Some((self.pending.find(open.as_str())?, tag)))
+                        .min();
+                    if let Some((start, tag)) = open {
+                        out.push_str(&self.pending[..start]);
+                        self.pending.drain(..start + self.tags[tag].0.len());
+                        self.inside = Some(tag);
+                        continue;
+                    }
+                    let keep = self.tags.iter().map(|(open, _)| partial_len(&self.pending, open)).max().unwrap_or(0);
+                    out.extend(self.pending.drain(..self.pending.len() - keep));
+                }
+                Some(tag) => {
+                    let close = &self.tags[tag].1;
+                    if let Some(start) = self.pending.find(close.as_str()) {
+                        self.pending.drain(..start + close.len());
+                        self.inside = None;
+                        continue;
+                    }
+                    let keep = partial_len(&self.pending, close);
+                    self.pending.drain(..self.pending.len() - keep);
+                }
+            }
+            return out;
+        }
+    }
+
+    // the text held back at the end, unless it is inside a block
+    pub fn finish(&mut self) -> String {
+        let pending = std::mem::take(&mut self.pending);
+        match self.inside {
+            None => pending,
+            Some(_) => String::new(),
+        }
+    }
+
+    // remove the blocks from complete content
+    pub fn strip(tags: &[String], content: &str) -> String {
+        let mut stripper = Self::new(tags);
+        let mut stripped = stripper.push(content);
+        stripped.push_str(&stripper.finish());
+        stripped
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONTENT: &str = "<think>The user wants a greeting.\nKeep it short.</think>\n\nHello <b>there</b>!";
+
+    // test that think blocks are removed whole or in streamed pieces
+    #[test]
+    fn strip_think_tags_test() {
+        let tags = [THINK_TAG.to_string()];
+        assert_eq!(TagStripper::strip(&tags, CONTENT), "\n\nHello <b>there</b>!");
+
+        for size in 1..8 {
+            let mut stripper = TagStripper::new(&tags);
+            let chars: Vec<char> = CONTENT.chars().collect();
+            let mut stripped: String = chars.chunks(size).map(|piece| stripper.push(&piece.iter().collect::<String>())).collect();
+            stripped.push_str(&stripper.finish());
+            assert_eq!(stripped, "\n\nHello <b>there</b>!", "pieces of {}", size);
+        }
+
+        let tags = ["reasoning".to_string(), THINK_TAG.to_string()];
+        assert_eq!(TagStripper::strip(&tags, "<reasoning>a</reasoning>b<think>c</think>d<think>e"), "bd");
+        assert_eq!(TagStripper::strip(&tags, "a < b <thin"), "a < b <thin");
+
+        use clap::Parser;
+        let opts = crate::cli::Options::try_parse_from(["winston", "--strip-think-tags", "hello"]).unwrap();
+        assert_eq!(opts.strip_think_tags, Some(vec![THINK_TAG.to_string()]));
+        assert_eq!(opts.prompt, ["hello"]);
+        let opts = crate::cli::Options::try_parse_from(["winston", "--strip-think-tags=reasoning,think", "hello"]).unwrap();
+        assert_eq!(opts.strip_think_tags, Some(tags.to_vec()));
+    }
+}
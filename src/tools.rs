@@ -0,0 +1,173 @@
+// Winston tool calls
+// With --tools the model may answer with calls to the tools instead of text.
+// Given --tool-runner, winston runs each call with that command: the function
+// name is in WINSTON_TOOL_NAME, the arguments JSON is on its stdin and what it
+// prints is sent back to the model as the result, until the model answers with
+// text. With --interactive-approve every call is shown first and only run once
+// approved, a declined call is reported to the model as declined so it can
+// carry on without it. The model gets a limited number of rounds of calls.
+
+use std::io::{BufRead, Write};
+use std::process::{Command, Stdio};
+
+use crate::chat::{ChatRequest, ChatResponse, Message, ToolCall};
+use crate::client::Client;
+use super::Result;
+
+// rounds of tool calls before giving up on a text answer
+pub const MAX_TOOL_ROUNDS: usize = 8;
+
+// the result sent for a call the user didn't approve
+pub const DECLINED: &str = "The user declined to run this tool call.";
+
+// show a call and ask whether to run it, anything but y or yes declines
+pub fn approve(call: &ToolCall, input: &mut impl BufRead, out: &mut impl Write) -> Result<bool> {
+    writeln!(out, "The model wants to call `{}` with {}", call.function.name, call.function.arguments)?;
+    write!(out, "Run it? [y/N] ")?;
+    out.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes"))
+}
+
+// run a call with the runner command through the shell, returning its output
+pub fn execute(runner: &str, call: &ToolCall) -> Result<String> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let mut child = shell
+        .arg(runner)
+        .env("WINSTON_TOOL_NAME", &call.function.name)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Could not start tool runner `{}`: {}", runner, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(call.function.arguments.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(format!("Tool runner `{}` failed on `{}` with {}", runner, call.function.name, output.status).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// send the request, running the tool calls in the replies and sending their
+// results back until the model answers with text. With approval input, each
+// call is approved on it first.
+pub fn run(
+    client: &Client,
+    mut request: ChatRequest,
+    runner: &str,
+    mut approval: Option<(&mut dyn BufRead, &mut dyn Write)>,
+) -> Result<ChatResponse> {
+    for _ in 0..MAX_TOOL_ROUNDS {
+        let response = client.chat(&request)?;
+        let Some(message) = response.choices.first().map(|choice| &choice.message) else {
+            return Ok(response);
+        };
+        if message.tool_calls.is_empty() {
+            return Ok(response);
+        }
+        request.messages.push(message.clone());
+        for call in &message.tool_calls {
+            let approved = match &mut approval {
+                Some((input, out)) => approve(call, input, out)?,
+                None => true,
+            };
+            let result = match approved {
+                true => execute(runner, call)?,
+                false => DECLINED.to_string(),
+            };
+            request.messages.push(Message::tool_result(call, result));
+        }
+    }
+    Err(format!("The model was still calling tools after {} rounds", MAX_TOOL_ROUNDS).into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chat::{FunctionCall, Role};
+    use crate::config::WinstonConfigBuilder;
+
+    fn call(id: &str, name: &str) -> ToolCall {
+        ToolCall {
+            id: id.to_string(),
+            kind: "function".to_string(),
+            function: FunctionCall {
+                name: name.to_string(),
+                arguments: r#"{"path":"notes.txt"}"#.to_string(),
+            },
+        }
+    }
+
+    // test that only calls answered with y or yes are approved
+    #[test]
+    fn approve_test() {
+        for (answer, approved) in [("y\n", true), ("YES\n", true), ("\n", false), ("no\n", false), ("", false)] {
+            let mut out = Vec::new();
+            assert_eq!(approve(&call("call_1", "read_file"), &mut answer.as_bytes(), &mut out).unwrap(), approved);
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                "The model wants to call `read_file` with {\"path\":\"notes.txt\"}\nRun it? [y/N] "
+            );
+        }
+    }
+
+    // test that approved calls are run and declined ones are reported to the
+    // model as declined, with the scripted answers read in order
+    #[cfg(unix)]
+    #[test]
+    fn interactive_approve_test() {
+        let mut server = mockito::Server::new();
+        let calls = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({"messages": [{"role": "user", "content": "tidy up"}]})))
+            .with_status(200)
+            .with_body(
+                r#"{"choices":[{"message":{"role":"assistant","content":null,"tool_calls":[
+                    {"id":"call_1","type":"function","function":{"name":"read_file","arguments":"{\"path\":\"notes.txt\"}"}},
+                    {"id":"call_2","type":"function","function":{"name":"delete_file","arguments":"{\"path\":\"notes.txt\"}"}}
+                ]}}]}"#,
+            )
+            .expect(1)
+            .create();
+        let answer = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({"messages": [
+                {"role": "user", "content": "tidy up"},
+                {"role": "assistant", "content": ""},
+                {"role": "tool", "tool_call_id": "call_1", "content": "ran read_file with {\"path\":\"notes.txt\"}"},
+                {"role": "tool", "tool_call_id": "call_2", "content": DECLINED},
+            ]})))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"role":"assistant","content":"Left notes.txt alone."}}]}"#)
+            .expect(1)
+            .create();
+
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .model("gpt-4o".to_string())
+            .build()
+            .unwrap();
+        let client = Client::from_config(&config).unwrap();
+        let request = ChatRequest::new(&config, vec![Message::new(Role::User, "tidy up")]);
+        let runner = r#"printf 'ran %s with %s' "$WINSTON_TOOL_NAME" "$(cat)""#;
+        let mut input = "y\nn\n".as_bytes();
+        let mut out = Vec::new();
+        let response = run(&client, request, runner, Some((&mut input, &mut out))).unwrap();
+
+        calls.assert();
+        answer.assert();
+        assert_eq!(response.content(), Some("Left notes.txt alone."));
+        assert_eq!(String::from_utf8(out).unwrap().matches("Run it? [y/N] ").count(), 2);
+    }
+}
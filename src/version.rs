@@ -0,0 +1,42 @@
+// Winston version information
+// clap's --version prints a human readable string. --version-json prints the
+// same version as a JSON object together with the commit and profile winston
+// was built from, so install scripts can read it without pattern matching.
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct VersionInfo {
+    pub version: &'static str,
+    pub git_sha: &'static str,
+    pub build: &'static str,
+}
+
+impl VersionInfo {
+    // the version information recorded at compile time
+    pub fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: env!("WINSTON_GIT_SHA"),
+            build: env!("WINSTON_BUILD"),
+        }
+    }
+
+    pub fn json(&self) -> String {
+        serde_json::to_string(self).expect("version info serializes")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // test that the version JSON parses and carries the crate version
+    #[test]
+    fn version_json_test() {
+        let value: serde_json::Value = serde_json::from_str(&VersionInfo::current().json()).unwrap();
+        assert_eq!(value["version"], env!("CARGO_PKG_VERSION"));
+        assert!(value["git_sha"].as_str().is_some_and(|sha| !sha.is_empty()));
+        assert!(value["build"].is_string());
+    }
+}
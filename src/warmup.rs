@@ -0,0 +1,75 @@
+// Winston warmup
+// Local servers often load the model on the first request, which makes it
+// slow. `winston warmup`, or --warmup before a batch, sends a tiny request
+// asking for a single token so the model is loaded before the real requests,
+// and reports how long the endpoint took to answer.
+
+use std::time::{Duration, Instant};
+
+use crate::chat::{ChatRequest, Message, Role};
+use crate::client::Client;
+use crate::config::WinstonConfig;
+use super::Result;
+
+// the prompt of the warmup request
+pub const WARMUP_PROMPT: &str = "Hi";
+
+// a request for a single token, without the configured system message
+pub fn request(config: &WinstonConfig) -> ChatRequest {
+    let mut request = ChatRequest::new(config, Vec::new());
+    request.messages = vec![Message::new(Role::User, WARMUP_PROMPT)];
+    request.max_tokens = Some(1);
+    request
+}
+
+// send the warmup request, returning how long it took to be answered
+pub fn run(client: &Client, config: &WinstonConfig) -> Result<Duration> {
+    let start = Instant::now();
+    client
+        .chat(&request(config))
+        .map_err(|e| format!("The endpoint didn't answer the warmup request: {}", e))?;
+    Ok(start.elapsed())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::WinstonConfigBuilder;
+
+    // test that warmup sends a one token request to the configured model
+    #[test]
+    fn warmup_request_test() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "model": "llama3",
+                "max_tokens": 1,
+                "messages": [{"role": "user", "content": WARMUP_PROMPT}],
+            })))
+            .with_status(200)
+            .with_body(r#"{"choices":[{"message":{"role":"assistant","content":"Hello"}}]}"#)
+            .expect(1)
+            .create();
+
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .model("llama3".to_string())
+            .system("Be brief.".to_string())
+            .build()
+            .unwrap();
+        let client = Client::from_config(&config).unwrap();
+        run(&client, &config).unwrap();
+        mock.assert();
+
+        let config = WinstonConfigBuilder::new()
+            .api_endpoint(server.url())
+            .openai_api_key("test-key".to_string())
+            .model("missing".to_string())
+            .build()
+            .unwrap();
+        let err = run(&Client::from_config(&config).unwrap(), &config).unwrap_err();
+        assert!(err.to_string().starts_with("The endpoint didn't answer the warmup request"));
+    }
+}
@@ -0,0 +1,157 @@
+// Winston configuration wizard
+// The wizard walks first-time users through creating a config file, prompting
+// for each common setting with the currently resolved value as the default.
+// The API key is read without echo when attached to a terminal, and is checked
+// with a test request before the config file is written.
+
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+
+use crate::client::Client;
+use crate::config::{mask_key, ApiKey, ConfigFormat, WinstonConfig};
+use super::Result;
+
+pub struct Prompter<R, W> {
+    input: R,
+    out: W,
+    // read secrets from the terminal without echo
+    masked: bool,
+}
+
+impl<R: BufRead, W: Write> Prompter<R, W> {
+    pub fn new(input: R, out: W, masked: bool) -> Self {
+        Self { input, out, masked }
+    }
+
+    fn read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Err("Unexpected end of input".into());
+        }
+        Ok(line.trim().to_string())
+    }
+
+    // ask for a value, returning the default when the answer is blank
+    pub fn ask(&mut self, label: &str, default: &str) -> Result<String> {
+        if default.is_empty() {
+            write!(self.out, "{}: ", label)?;
+        } else {
+            write!(self.out, "{} [{}]: ", label, default)?;
+        }
+        self.out.flush()?;
+        let answer = self.read_line()?;
+        Ok(if answer.is_empty() { default.to_string() } else { answer })
+    }
+
+    // ask for a value until it parses, blank answers keep the default
+    pub fn ask_parsed<T: std::str::FromStr>(&mut self, label: &str, default: &str) -> Result<Option<T>> {
+        loop {
+            let answer = self.ask(label, default)?;
+            if answer.is_empty() {
+                return Ok(None);
+            }
+            match answer.parse() {
+                Ok(value) => return Ok(Some(value)),
+                Err(_) => writeln!(self.out, "`{}` is not a valid value", answer)?,
+            }
+        }
+    }
+
+    // ask for a secret, showing only a masked version of the default
+    pub fn ask_secret(&mut self, label: &str, default: Option<&str>) -> Result<String> {
+        match default {
+            Some(default) => write!(self.out, "{} [{}]: ", label, mask_key(default))?,
+            None => write!(self.out, "{}: ", label)?,
+        }
+        self.out.flush()?;
+        let answer = if self.masked {
+            rpassword::read_password()?.trim().to_string()
+        } else {
+            self.read_line()?
+        };
+        Ok(match (answer.is_empty(), default) {
+            (true, Some(default)) => default.to_string(),
+            _ => answer,
+        })
+    }
+}
+
+// prompt for the common settings starting from config, check the key and
+// write the result to fp
+pub fn run<R: BufRead, W: Write>(
+    prompter: &mut Prompter<R, W>,
+    mut config: WinstonConfig,
+    fp: Option<PathBuf>,
+    format: Option<ConfigFormat>,
+) -> Result<()> {
+    // keep a list of keys unless a different key is entered
+    let current = config.openai_api_key.as_ref().and_then(ApiKey::first).map(str::to_string);
+    let key = prompter.ask_secret("OpenAI API key", current.as_deref())?;
+    if current.as_deref() != Some(key.as_str()) {
+        config.openai_api_key = Some(key).filter(|key| !key.is_empty()).map(ApiKey::from);
+    }
+    let org_id = prompter.ask("Organization ID (optional)", config.openai_org_id.as_deref().unwrap_or_default())?;
+    config.openai_org_id = Some(org_id).filter(|org_id| !org_id.is_empty());
+    config.api_endpoint = prompter.ask("API endpoint", &config.api_endpoint)?;
+    config.model = prompter.ask("Model", &config.model)?;
+    if let Some(temperature) = prompter.ask_parsed("Temperature", &config.temperature.to_string())? {
+        config.temperature = temperature;
+    }
+    let max_tokens = config.max_tokens.map(|m| m.to_string()).unwrap_or_default();
+    config.max_tokens = prompter.ask_parsed("Max tokens (blank to let the model decide)", &max_tokens)?;
+
+    // check the key before saving it
+    let check = Client::from_config(&config).and_then(|client| client.ping());
+    let valid = match check {
+        Ok(ping) if ping.status.is_success() => true,
+        Ok(ping) => {
+            writeln!(prompter.out, "The API key was rejected: {}", ping.status)?;
+            false
+        }
+        Err(e) => {
+            writeln!(prompter.out, "Could not check the API key: {}", e)?;
+            false
+        }
+    };
+    if !valid && !prompter.ask("Save anyway? [y/N]", "n")?.eq_ignore_ascii_case("y") {
+        return Err("Configuration was not saved".into());
+    }
+
+    config.save_config(fp, format)?;
+    writeln!(prompter.out, "Configuration saved")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::WinstonConfigBuilder;
+
+    // test that scripted answers are validated and written to the config file
+    #[test]
+    fn wizard_scripted_input_test() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("GET", "/v1/models")
+            .match_header("authorization", "Bearer sk-wizard-key")
+            .with_status(200)
+            .create();
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let temp_file = temp_dir.path().join("config.toml");
+        let input = format!("sk-wizard-key\n\n{}\ngpt-4o\nwarm\n0.2\n\n", server.url());
+        let mut out = Vec::new();
+        let mut prompter = Prompter::new(input.as_bytes(), &mut out, false);
+        let config = WinstonConfigBuilder::new().build().unwrap();
+        run(&mut prompter, config, Some(temp_file.clone()), None).unwrap();
+
+        mock.assert();
+        let config = WinstonConfigBuilder::new().load_config(&temp_file).unwrap().build().unwrap();
+        assert_eq!(config.api_key().unwrap(), "sk-wizard-key");
+        assert_eq!(config.openai_org_id, None);
+        assert_eq!(config.model, "gpt-4o");
+        assert_eq!(config.temperature, 0.2);
+        assert_eq!(config.max_tokens, None);
+        assert!(String::from_utf8(out).unwrap().contains("`warm` is not a valid value"));
+    }
+}